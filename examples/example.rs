@@ -8,14 +8,14 @@ After the 4 seconds the key listener quits and no longer takes input
 */
 
 #[tokio::main]
-async fn main() {
+async fn main() -> Result<(), KeyListenerError> {
     // create a key listener and begin listening (this is non-blocking)
     let key_listener = init_custom_key_listener(
-        Box::new(on_key_down), // on key press callback
-        Box::new(on_key_up), // on key release callback
+        on_key_down, // on key press callback
+        on_key_up, // on key release callback
         vec![0x20, 0x1B], // keys to watch, (spacebar, esc)
         12 // time between each key state poll
-    );
+    )?; // fails for invalid key codes or a zero polling time
 
     println!("Listening for 'spacebar' or 'esc' key events...");
 
@@ -29,14 +29,19 @@ async fn main() {
     println!("Press ctrl+c to exit");
 
     tokio::signal::ctrl_c().await.unwrap();
+    Ok(())
 }
 
 // on key down callback fn
-fn on_key_down(vk: i32) {
-    println!("Key Pressed: {}", vk);
+fn on_key_down(key_event: KeyEvent) {
+    if let KeyEvent::Press(vk) = key_event {
+        println!("Key Pressed: {}", vk);
+    }
 }
 
 // on key up callback fn
-fn on_key_up(vk: i32) {
-    println!("Key Released: {}", vk);
+fn on_key_up(key_event: KeyEvent) {
+    if let KeyEvent::Release(vk) = key_event {
+        println!("Key Released: {}", vk);
+    }
 }
\ No newline at end of file
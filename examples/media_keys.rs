@@ -0,0 +1,52 @@
+use win_key_event::*;
+
+use std::time::Duration;
+
+/*
+This example turns F7 / F8 / F9 into previous track / play-pause / next track for keyboards without media keys,
+and reacts to the media keys themselves (real or remapped) by printing what they do
+Ctrl+Alt+M opens the volume mixer, press ctrl+c to exit
+*/
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // the remapper consumes F7 - F9 and injects the media keys in their place, Windows then acts on those
+    let remapper = Remapper::new()
+        .map(0x76, VirtualKey::MEDIA_PREV_TRACK) // F7
+        .map(0x77, VirtualKey::MEDIA_PLAY_PAUSE) // F8
+        .map(0x78, VirtualKey::MEDIA_NEXT_TRACK) // F9
+        .start()?;
+
+    // media keys are not in the default key list
+    let key_listener = KeyListener::builder()
+        .media_keys(true)
+        .spawn(on_key_down, |_| {})?;
+
+    let triggers = Triggers::new()
+        .command("mixer", "Ctrl+Alt+M".parse()?, "start sndvol.exe")
+        .debounce(Duration::from_millis(500))
+        .spawn(KeyListener::builder())?;
+
+    println!("F7 / F8 / F9 now control playback, press ctrl+c to exit");
+    tokio::signal::ctrl_c().await?;
+
+    remapper.quit();
+    key_listener.lock().await.quit();
+    triggers.quit().await;
+    Ok(())
+}
+
+fn on_key_down(key_event: KeyEvent) {
+    let KeyEvent::Press(vk) = key_event else { return };
+    let action = match vk {
+        VirtualKey::VOLUME_MUTE => "mute",
+        VirtualKey::VOLUME_DOWN => "volume down",
+        VirtualKey::VOLUME_UP => "volume up",
+        VirtualKey::MEDIA_NEXT_TRACK => "next track",
+        VirtualKey::MEDIA_PREV_TRACK => "previous track",
+        VirtualKey::MEDIA_STOP => "stop",
+        VirtualKey::MEDIA_PLAY_PAUSE => "play / pause",
+        _ => return
+    };
+    println!("{}", action);
+}
@@ -0,0 +1,10 @@
+/*
+
+C interface
+
+Builds the win_key_event ffi module as a DLL, declared in include/win_key_event.h.
+
+*/
+
+
+pub use win_key_event::ffi::*;
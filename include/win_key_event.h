@@ -0,0 +1,54 @@
+/*
+
+C interface of win-key-event, built with the "ffi" feature
+
+Link against win_key_event_ffi.dll, built by the win-key-event-ffi crate. Callbacks are called on the listener's dispatch thread.
+
+*/
+
+#ifndef WIN_KEY_EVENT_H
+#define WIN_KEY_EVENT_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define WKE_OK 0
+#define WKE_INVALID_VK_CODE 1
+#define WKE_EMPTY_KEY_LIST 2
+#define WKE_ZERO_POLLING_INTERVAL 3
+#define WKE_NULL_POINTER 4
+#define WKE_ERROR -1
+
+typedef struct WkeListener WkeListener;
+
+typedef void (*WkeCallback)(int32_t vk_code, void *user_data);
+
+// returns one of the WKE_ status codes, on WKE_OK the listener is stored in listener_out
+int32_t wke_listener_start(
+    const int32_t *vk_codes,
+    size_t vk_codes_len,
+    uint64_t polling_wait,
+    WkeCallback on_press,
+    WkeCallback on_release,
+    void *user_data,
+    WkeListener **listener_out
+);
+
+// stops and frees the listener, waiting for a running callback unless called from one
+void wke_listener_stop(WkeListener *listener);
+
+bool wke_listener_is_pressed(const WkeListener *listener, int32_t vk_code);
+
+// returns WKE_ZERO_POLLING_INTERVAL for 0, keeping the previous polling wait
+int32_t wke_listener_set_polling_wait(const WkeListener *listener, uint64_t polling_wait);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,162 @@
+/*
+
+Executor agnostic async backend, enabled with the "executor-agnostic" feature
+
+Polling runs on a plain thread (like the "thread" backend) and events are handed to async code through a small
+waker based queue, so it works the same under async-std, smol or any other executor.
+For callbacks, the dispatch future is given to a user supplied spawn function, e.g.
+
+    init_default_key_listener_on(|task| { async_std::task::spawn(task); }, ...)
+    init_default_key_listener_on(|task| smol::spawn(task).detach(), ...)
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+use crate::callbacks::Callbacks;
+use crate::error::KeyListenerError;
+use crate::poll::{DEFAULT_POLLING_WAIT, default_vk_codes};
+use crate::thread_listener::{ThreadKeyListener, spawn_listener};
+
+use std::collections::VecDeque;
+use std::future::{Future, poll_fn};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+
+
+
+pub type SpawnedTask = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+struct QueueState {
+    events: VecDeque<KeyEvent>,
+    waker: Option<Waker>,
+    is_sender_dropped: bool,
+    is_receiver_dropped: bool
+}
+
+type EventQueue = Arc<Mutex<QueueState>>;
+
+
+
+struct QueueSender {
+    queue: EventQueue
+}
+
+impl QueueSender {
+    // returns false once the receiving side is gone
+    fn send(&self, key_event: KeyEvent) -> bool {
+        let mut state = self.queue.lock().unwrap();
+        if state.is_receiver_dropped {
+            return false;
+        }
+        state.events.push_back(key_event);
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+        true
+    }
+}
+
+impl Drop for QueueSender {
+    fn drop(&mut self) {
+        let mut state = self.queue.lock().unwrap();
+        state.is_sender_dropped = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+
+
+// async receiver of key events, recv resolves to None once the listener quits
+pub struct AsyncKeyEvents {
+    queue: EventQueue
+}
+
+impl AsyncKeyEvents {
+    pub async fn recv(&self) -> Option<KeyEvent> {
+        poll_fn(|cx| {
+            let mut state = self.queue.lock().unwrap();
+            if let Some(key_event) = state.events.pop_front() {
+                Poll::Ready(Some(key_event))
+            } else if state.is_sender_dropped {
+                Poll::Ready(None)
+            } else {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }).await
+    }
+
+    pub fn try_recv(&self) -> Option<KeyEvent> {
+        self.queue.lock().unwrap().events.pop_front()
+    }
+}
+
+impl Drop for AsyncKeyEvents {
+    fn drop(&mut self) {
+        self.queue.lock().unwrap().is_receiver_dropped = true;
+    }
+}
+
+fn event_queue() -> (QueueSender, AsyncKeyEvents) {
+    let queue = Arc::new(Mutex::new(QueueState {
+        events: VecDeque::new(),
+        waker: None,
+        is_sender_dropped: false,
+        is_receiver_dropped: false
+    }));
+    (QueueSender { queue: queue.clone() }, AsyncKeyEvents { queue })
+}
+
+
+
+pub fn init_default_async_key_events() -> Result<(ThreadKeyListener, AsyncKeyEvents), KeyListenerError> {
+    init_custom_async_key_events(default_vk_codes(), DEFAULT_POLLING_WAIT)
+}
+
+pub fn init_custom_async_key_events(
+    vk_codes: impl IntoIterator<Item = impl Into<VirtualKey>>,
+    polling_wait: u64
+) -> Result<(ThreadKeyListener, AsyncKeyEvents), KeyListenerError> {
+    let vk_codes = vk_codes.into_iter().map(|vk| vk.into().code()).collect();
+    let (sender, key_events) = event_queue();
+    let key_listener = spawn_listener(vk_codes, polling_wait, move |key_event| sender.send(key_event))?;
+
+    Ok((key_listener, key_events))
+}
+
+pub fn init_default_key_listener_on<D, U>(
+    spawn: impl FnOnce(SpawnedTask),
+    key_down_callback: D, key_up_callback: U
+) -> Result<ThreadKeyListener, KeyListenerError>
+where
+    D: FnMut(KeyEvent) + Send + 'static,
+    U: FnMut(KeyEvent) + Send + 'static
+{
+    init_custom_key_listener_on(spawn, key_down_callback, key_up_callback, default_vk_codes(), DEFAULT_POLLING_WAIT)
+}
+
+pub fn init_custom_key_listener_on<D, U>(
+    spawn: impl FnOnce(SpawnedTask),
+    key_down_callback: D, key_up_callback: U,
+    vk_codes: Vec<i32>,
+    polling_wait: u64
+) -> Result<ThreadKeyListener, KeyListenerError>
+where
+    D: FnMut(KeyEvent) + Send + 'static,
+    U: FnMut(KeyEvent) + Send + 'static
+{
+    let (key_listener, key_events) = init_custom_async_key_events(vk_codes, polling_wait)?;
+    let mut callbacks = Callbacks::new(key_down_callback, key_up_callback);
+
+    spawn(Box::pin(async move {
+        while let Some(key_event) = key_events.recv().await {
+            callbacks.call(key_event);
+        }
+    }));
+
+    Ok(key_listener)
+}
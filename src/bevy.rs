@@ -0,0 +1,128 @@
+/*
+
+Bevy plugin
+
+Gives a Bevy app global keyboard capture, i.e. key events while its window is not focused. The plugin runs a
+polling thread (Bevy brings no tokio runtime) and moves what it polled into the ECS at the start of every frame,
+as GlobalKeyEvent events and the GlobalKeys resource. The thread stops with the app.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+use crate::poll::{DEFAULT_POLLING_WAIT, default_vk_codes};
+use crate::thread_listener::{KeyEvents, ThreadKeyListener, init_custom_key_events};
+
+use bevy_app::{App, Plugin, PreUpdate};
+use bevy_ecs::prelude::{Event, EventWriter, Res, ResMut, Resource};
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+
+
+// every key event polled since the last frame, in order
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GlobalKeyEvent(pub KeyEvent);
+
+// watched keys held down, and the ones pressed / released since the last frame
+#[derive(Resource, Default, Debug)]
+pub struct GlobalKeys {
+    pressed: HashSet<VirtualKey>,
+    just_pressed: HashSet<VirtualKey>,
+    just_released: HashSet<VirtualKey>
+}
+
+impl GlobalKeys {
+    pub fn pressed(&self, vk: impl Into<VirtualKey>) -> bool {
+        self.pressed.contains(&vk.into())
+    }
+
+    pub fn just_pressed(&self, vk: impl Into<VirtualKey>) -> bool {
+        self.just_pressed.contains(&vk.into())
+    }
+
+    pub fn just_released(&self, vk: impl Into<VirtualKey>) -> bool {
+        self.just_released.contains(&vk.into())
+    }
+
+    pub fn get_pressed(&self) -> impl Iterator<Item = VirtualKey> + '_ {
+        self.pressed.iter().copied()
+    }
+}
+
+#[derive(Resource)]
+struct GlobalKeyListener {
+    key_listener: ThreadKeyListener,
+    // Resources have to be Sync, the receiver is only read by pump_key_events
+    key_events: Mutex<KeyEvents>
+}
+
+impl Drop for GlobalKeyListener {
+    fn drop(&mut self) {
+        self.key_listener.quit();
+    }
+}
+
+
+
+pub struct WinKeyEventPlugin {
+    vk_codes: Vec<i32>,
+    polling_wait: u64
+}
+
+impl Default for WinKeyEventPlugin {
+    fn default() -> Self {
+        WinKeyEventPlugin { vk_codes: default_vk_codes(), polling_wait: DEFAULT_POLLING_WAIT }
+    }
+}
+
+impl WinKeyEventPlugin {
+    pub fn new() -> Self {
+        WinKeyEventPlugin::default()
+    }
+
+    pub fn vk_codes(mut self, vk_codes: impl IntoIterator<Item = impl Into<VirtualKey>>) -> Self {
+        self.vk_codes = vk_codes.into_iter().map(|vk| vk.into().code()).collect();
+        self
+    }
+
+    // time in milliseconds between each round of key polling
+    pub fn polling_wait(mut self, polling_wait: u64) -> Self {
+        self.polling_wait = polling_wait;
+        self
+    }
+}
+
+impl Plugin for WinKeyEventPlugin {
+    // panics for an invalid key list or polling wait, like other plugins do for invalid settings
+    fn build(&self, app: &mut App) {
+        let (key_listener, key_events) = init_custom_key_events(self.vk_codes.clone(), self.polling_wait)
+            .unwrap_or_else(|error| panic!("WinKeyEventPlugin could not start listening: {}", error));
+
+        app.add_event::<GlobalKeyEvent>()
+            .init_resource::<GlobalKeys>()
+            .insert_resource(GlobalKeyListener { key_listener, key_events: Mutex::new(key_events) })
+            .add_systems(PreUpdate, pump_key_events);
+    }
+}
+
+fn pump_key_events(listener: Res<GlobalKeyListener>, mut keys: ResMut<GlobalKeys>, mut events: EventWriter<GlobalKeyEvent>) {
+    keys.just_pressed.clear();
+    keys.just_released.clear();
+
+    let key_events = listener.key_events.lock().unwrap();
+    while let Ok(key_event) = key_events.try_recv() {
+        match key_event {
+            KeyEvent::Press(vk) => {
+                keys.pressed.insert(vk);
+                keys.just_pressed.insert(vk);
+            }
+            KeyEvent::Release(vk) => {
+                keys.pressed.remove(&vk);
+                keys.just_released.insert(vk);
+            }
+            _ => {}
+        }
+        events.write(GlobalKeyEvent(key_event));
+    }
+}
@@ -0,0 +1,172 @@
+/*
+
+win-key-monitor
+
+Prints key events as they happen, or records them to a session file, e.g.
+win-key-monitor --keys A,Space,0x10 --format json
+win-key-monitor --format binary --output session.bin
+Keys are named as in hotkey strings, or given as key codes. Without --keys every key of the default list is watched.
+Runs until ctrl+c, or until the output is closed (e.g. piped into head).
+
+*/
+
+
+use win_key_event::*;
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::broadcast::error::RecvError;
+
+
+
+const USAGE: &str = "usage: win-key-monitor [--keys KEY,KEY,...] [--format text|json|binary] [--output FILE]";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+    Binary
+}
+
+struct Args {
+    keys: Option<Vec<VirtualKey>>,
+    format: Format,
+    output: Option<PathBuf>
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args { keys: None, format: Format::Text, output: None };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--keys" => parsed.keys = Some(value()?.split(',').map(parse_key).collect::<Result<_, _>>()?),
+            "--format" => parsed.format = match value()?.as_str() {
+                "text" => Format::Text,
+                "json" => Format::Json,
+                "binary" => Format::Binary,
+                other => return Err(format!("unknown format '{}'", other))
+            },
+            "--output" => parsed.output = Some(PathBuf::from(value()?)),
+            other => return Err(format!("unknown argument '{}'", other))
+        }
+    }
+    // binary records are no use on a terminal
+    if parsed.format == Format::Binary && parsed.output.is_none() {
+        return Err("--format binary needs --output".to_string());
+    }
+    Ok(parsed)
+}
+
+// a key code (0x41, 65) or a key name from hotkey strings (a, space, f5, ...)
+fn parse_key(key: &str) -> Result<VirtualKey, String> {
+    let key = key.trim();
+    let code = match key.strip_prefix("0x").or_else(|| key.strip_prefix("0X")) {
+        Some(hex) => i32::from_str_radix(hex, 16).ok(),
+        None => key.parse().ok()
+    };
+    if let Some(code) = code {
+        return Ok(VirtualKey(code));
+    }
+    match key.parse::<Hotkey>() {
+        Ok(hotkey) if hotkey.modifiers == Modifiers::default() => Ok(hotkey.key),
+        _ => Err(format!("unknown key '{}'", key))
+    }
+}
+
+
+
+fn time_ms() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis()
+}
+
+// the recorder's JSON lines, with the key's name added
+fn print_event(output: &mut impl Write, format: Format, key_event: &KeyEvent) -> io::Result<()> {
+    let (event, vk) = match key_event {
+        KeyEvent::Press(vk) => ("press", vk),
+        KeyEvent::Release(vk) => ("release", vk),
+        // the listener is not asked for any other events
+        _ => return Ok(())
+    };
+    let name = vk.display_name();
+    match format {
+        Format::Json => writeln!(
+            output, "{{\"time_ms\":{},\"event\":\"{}\",\"vk\":{},\"name\":\"{}\"}}",
+            time_ms(), event, vk.code(), name.replace('\\', "\\\\").replace('"', "\\\"")
+        ),
+        _ => writeln!(output, "{:<8} {:<12} 0x{:02X}", event, name, vk.code())
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("win-key-monitor: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut recorder = match &args.output {
+        Some(path) => {
+            let format = if args.format == Format::Binary { SessionFormat::Binary } else { SessionFormat::JsonLines };
+            Some(Recorder::builder(path).format(format).open()?)
+        }
+        None => None
+    };
+
+    let mut builder = KeyListener::builder();
+    if let Some(keys) = args.keys {
+        builder = builder.vk_codes(keys);
+    }
+    let key_listener = builder.spawn(|_| {}, |_| {})?;
+    let mut receiver = key_listener.lock().await.subscribe();
+    eprintln!("listening, press ctrl+c to exit");
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut stdout = io::stdout();
+    let result = loop {
+        let received = tokio::select! {
+            _ = &mut ctrl_c => break Ok(()),
+            received = receiver.recv() => received
+        };
+        let written = match received {
+            Ok(key_event) => match recorder.as_mut() {
+                Some(recorder) => recorder.record(&key_event),
+                // flushed every event, keeps piped output line by line
+                None => print_event(&mut stdout, args.format, &key_event).and_then(|()| stdout.flush())
+            },
+            Err(RecvError::Lagged(skipped)) => {
+                eprintln!("skipped {} events", skipped);
+                Ok(())
+            }
+            Err(RecvError::Closed) => break Ok(())
+        };
+        match written {
+            Ok(()) => {}
+            // whoever read the output is gone, e.g. head has seen enough lines
+            Err(error) if error.kind() == io::ErrorKind::BrokenPipe => break Ok(()),
+            Err(error) => break Err(error)
+        }
+    };
+
+    key_listener.lock().await.quit();
+    result?;
+    if let Some(recorder) = recorder.as_mut() {
+        recorder.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,67 @@
+/*
+
+Blocking queries off the polling task
+
+Some state lives in another process and can only be asked for with calls that block until that process answers
+(UI Automation, messages to its IME window). Such a query runs on a thread of its own: the polling task waits for
+the answer without blocking the runtime, and keeps the last answer if it does not come in time. An answer that
+comes late is picked up by the next ask instead of queueing another query behind it.
+
+*/
+
+
+use tokio::sync::oneshot;
+use tokio::time::{Duration, timeout};
+use std::sync::mpsc::{Sender, channel};
+
+
+
+// how long the polling task waits for an answer before going on with the last one
+const ANSWER_TIMEOUT: Duration = Duration::from_millis(100);
+
+pub(crate) struct BlockingQuery {
+    // the query thread ends once this is dropped
+    queries: Sender<oneshot::Sender<bool>>,
+    // a query that timed out
+    pending: Option<oneshot::Receiver<bool>>,
+    last: bool
+}
+
+impl BlockingQuery {
+    pub(crate) fn new(query: fn() -> bool) -> Self {
+        let (queries, receiver) = channel::<oneshot::Sender<bool>>();
+        std::thread::spawn(move || {
+            for reply in receiver {
+                let _ = reply.send(query());
+            }
+        });
+        Self { queries, pending: None, last: false }
+    }
+
+    pub(crate) async fn ask(&mut self) -> bool {
+        let mut pending = match self.pending.take() {
+            Some(pending) => pending,
+            None => {
+                let (reply, pending) = oneshot::channel();
+                // only fails if the query thread is gone, which the reply then reports
+                let _ = self.queries.send(reply);
+                pending
+            }
+        };
+        match timeout(ANSWER_TIMEOUT, &mut pending).await {
+            Ok(answer) => self.last = answer.unwrap_or(false),
+            Err(_) => self.pending = Some(pending)
+        }
+        self.last
+    }
+
+    // the last answer, without asking
+    pub(crate) fn last(&self) -> bool {
+        self.last
+    }
+
+    // whether the last ask timed out and its answer is still to come
+    pub(crate) fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+}
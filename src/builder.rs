@@ -0,0 +1,575 @@
+/*
+
+Builder for the tokio key listener
+
+init_default_key_listener and init_custom_key_listener cover the common cases,
+the builder exposes every other listener option.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey, MOUSE_BUTTONS, WINDOWS_KEYS, MEDIA_KEYS, BROWSER_KEYS, FUNCTION_KEYS};
+use crate::error::{KeyListenerError, checked_vk_codes, dedup_vk_codes, validate_polling_interval};
+use crate::channel::{ChannelMode, OverflowPolicy, EventReceiver, event_channel};
+use crate::poll::{DEFAULT_POLLING_WAIT, PriorityScan, default_vk_codes};
+use crate::tokio_listener::{KeyListener, ListenerOptions, AdaptivePolling, AsyncCallbackMode, spawn_receiver, spawn_blocking_receiver, spawn_async_receiver};
+use crate::callbacks::{Callbacks, AsyncCallbacks, stateful_callbacks, CallbackPanic, PanicHandler, PanicReporter};
+use crate::consumer::EventConsumer;
+use crate::debounce::Debounce;
+use crate::rate_limit::{RateLimiter, RateLimitPolicy};
+use crate::modifiers::ModifierMode;
+use crate::numpad::with_numpad_navigation;
+use crate::pipeline::Pipeline;
+use crate::priority::ThreadPriority;
+use crate::window::WindowFilter;
+use crate::warnings::{ListenerWarning, WarningHandler, WarningSender};
+use crate::supervisor::Supervision;
+
+use tokio::sync::Mutex;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::time::Duration;
+
+
+
+pub struct KeyListenerBuilder {
+    vk_codes: Vec<i32>,
+    skip_invalid_vk_codes: bool,
+    channel_mode: ChannelMode,
+    modifier_mode: ModifierMode,
+    numpad_navigation: bool,
+    mouse_buttons: bool,
+    windows_keys: bool,
+    media_keys: bool,
+    browser_keys: bool,
+    function_keys: bool,
+    debounce: Debounce,
+    rate_limiter: RateLimiter,
+    pipeline: Pipeline,
+    blocking_callbacks: bool,
+    supervision: Option<Supervision>,
+    panic_handler: Option<PanicHandler>,
+    warning_handler: Option<WarningHandler>,
+    options: ListenerOptions
+}
+
+impl Default for KeyListenerBuilder {
+    fn default() -> Self {
+        KeyListenerBuilder {
+            vk_codes: default_vk_codes(),
+            skip_invalid_vk_codes: false,
+            channel_mode: ChannelMode::default(),
+            modifier_mode: ModifierMode::default(),
+            numpad_navigation: false,
+            mouse_buttons: false,
+            windows_keys: false,
+            media_keys: false,
+            browser_keys: false,
+            function_keys: false,
+            debounce: Debounce::default(),
+            rate_limiter: RateLimiter::default(),
+            pipeline: Pipeline::default(),
+            blocking_callbacks: false,
+            supervision: None,
+            panic_handler: None,
+            warning_handler: None,
+            options: ListenerOptions {
+                polling_interval: Duration::from_millis(DEFAULT_POLLING_WAIT),
+                high_resolution: false,
+                dedicated_thread: None,
+                adaptive_polling: None,
+                track_toggles: false,
+                track_layout: false,
+                track_focus: false,
+                release_on_focus_loss: false,
+                release_on_quit: false,
+                track_session: false,
+                track_power: false,
+                idle_threshold: None,
+                pause_on_secure_desktop: false,
+                text_mode: false,
+                privacy_mode: false,
+                window_filter: None,
+                window_context: false,
+                state_changes: false,
+                modifier_gestures: None,
+                history_capacity: None,
+                priority_scan: None,
+                #[cfg(feature = "tokio-util")]
+                cancellation_token: None,
+                #[cfg(feature = "mock")]
+                simulated: false
+            }
+        }
+    }
+}
+
+impl KeyListenerBuilder {
+    pub fn new() -> Self {
+        KeyListenerBuilder::default()
+    }
+
+    pub fn vk_codes(mut self, vk_codes: impl IntoIterator<Item = impl Into<VirtualKey>>) -> Self {
+        self.vk_codes = vk_codes.into_iter().map(|vk| vk.into().code()).collect();
+        self
+    }
+
+    // drop key codes outside of 0x01 - 0xFE instead of failing to spawn with KeyListenerError::InvalidVkCode
+    pub fn skip_invalid_vk_codes(mut self, skip_invalid_vk_codes: bool) -> Self {
+        self.skip_invalid_vk_codes = skip_invalid_vk_codes;
+        self
+    }
+
+    // also watch the mouse buttons, which are reported through the same press / release events as keys
+    pub fn mouse_buttons(mut self, mouse_buttons: bool) -> Self {
+        self.mouse_buttons = mouse_buttons;
+        self
+    }
+
+    // also watch the left / right Win keys and the Apps key, see WINDOWS_KEYS
+    pub fn windows_keys(mut self, windows_keys: bool) -> Self {
+        self.windows_keys = windows_keys;
+        self
+    }
+
+    // also watch the volume and media playback keys, see MEDIA_KEYS
+    pub fn media_keys(mut self, media_keys: bool) -> Self {
+        self.media_keys = media_keys;
+        self
+    }
+
+    // also watch the browser back / forward / refresh / ... keys, see BROWSER_KEYS
+    pub fn browser_keys(mut self, browser_keys: bool) -> Self {
+        self.browser_keys = browser_keys;
+        self
+    }
+
+    // also watch F1 - F24, see FUNCTION_KEYS
+    pub fn function_keys(mut self, function_keys: bool) -> Self {
+        self.function_keys = function_keys;
+        self
+    }
+
+    // time in milliseconds between each round of key polling
+    pub fn polling_wait(self, polling_wait: u64) -> Self {
+        self.polling_interval(Duration::from_millis(polling_wait))
+    }
+
+    // like polling_wait, with sub-millisecond intervals for use with high_resolution(true)
+    pub fn polling_interval(mut self, polling_interval: Duration) -> Self {
+        self.options.polling_interval = polling_interval;
+        self
+    }
+
+    // raise the system timer resolution and spin through the last millisecond of each wait, for intervals that are
+    // accurate to well below a millisecond. Keeps a core busy while the interval is close to or below 1 ms
+    pub fn high_resolution(mut self, high_resolution: bool) -> Self {
+        self.options.high_resolution = high_resolution;
+        self
+    }
+
+    // poll on an OS thread of its own with the given priority instead of a task on the application's runtime,
+    // callbacks still run on the application's runtime
+    pub fn dedicated_thread(mut self, priority: ThreadPriority) -> Self {
+        self.options.dedicated_thread = Some(priority);
+        self
+    }
+
+    // poll the given keys every round and the other watched keys only every other_keys_every rounds, for less
+    // polling overhead with many keys at short intervals. The other keys react later, and presses shorter than
+    // other_keys_every rounds can be missed. An other_keys_every of 0 fails to spawn with
+    // KeyListenerError::ZeroScanInterval
+    pub fn priority_keys(mut self, vk_codes: impl IntoIterator<Item = impl Into<VirtualKey>>, other_keys_every: u32) -> Self {
+        let vk_codes = vk_codes.into_iter().map(|vk| vk.into().code()).collect();
+        self.options.priority_scan = Some(PriorityScan { vk_codes, other_keys_every });
+        self
+    }
+
+    // poll every idle_polling_wait milliseconds once no key has changed for idle_after, the first change detected
+    // switches back to the normal polling wait
+    pub fn adaptive_polling(mut self, idle_after: Duration, idle_polling_wait: u64) -> Self {
+        self.options.adaptive_polling = Some(AdaptivePolling { idle_after, idle_polling_wait });
+        self
+    }
+
+    pub fn channel_mode(mut self, channel_mode: ChannelMode) -> Self {
+        self.channel_mode = channel_mode;
+        self
+    }
+
+    // a capacity of 0 fails to spawn with KeyListenerError::ZeroCapacity
+    pub fn bounded(self, capacity: usize, overflow: OverflowPolicy) -> Self {
+        self.channel_mode(ChannelMode::Bounded { capacity, overflow })
+    }
+
+    // how left / right modifier keys are reported, applied to Shift, Ctrl, Alt and Win
+    pub fn modifier_mode(mut self, modifier_mode: ModifierMode) -> Self {
+        self.modifier_mode = modifier_mode;
+        self
+    }
+
+    // also watch the navigation codes numpad keys report while NumLock is off (VK_NUMPAD7 -> VK_HOME, ...)
+    pub fn numpad_navigation(mut self, numpad_navigation: bool) -> Self {
+        self.numpad_navigation = numpad_navigation;
+        self
+    }
+
+    // emit KeyEvent::ToggleChanged whenever CapsLock, NumLock or ScrollLock is switched on or off
+    pub fn track_toggles(mut self, track_toggles: bool) -> Self {
+        self.options.track_toggles = track_toggles;
+        self
+    }
+
+    // emit KeyEvent::LayoutChanged whenever the foreground window switches keyboard layout
+    pub fn track_layout(mut self, track_layout: bool) -> Self {
+        self.options.track_layout = track_layout;
+        self
+    }
+
+    // emit KeyEvent::FocusChanged whenever another window comes to the foreground
+    pub fn track_focus(mut self, track_focus: bool) -> Self {
+        self.options.track_focus = track_focus;
+        self
+    }
+
+    // emit a release for every held key when another window comes to the foreground, keys still held are
+    // reported as pressed again on the next poll
+    pub fn release_on_focus_loss(mut self, release_on_focus_loss: bool) -> Self {
+        self.options.release_on_focus_loss = release_on_focus_loss;
+        self
+    }
+
+    // emit a release for every held key when the listener quits, so nothing is left pressed
+    pub fn release_on_quit(mut self, release_on_quit: bool) -> Self {
+        self.options.release_on_quit = release_on_quit;
+        self
+    }
+
+    // emit KeyEvent::SessionLocked / SessionUnlocked when the secure desktop (lock screen, UAC prompt) comes and goes
+    pub fn track_session(mut self, track_session: bool) -> Self {
+        self.options.track_session = track_session;
+        self
+    }
+
+    // pause polling while the system sleeps and emit KeyEvent::Suspended / Resumed. Resumed is followed by the
+    // releases and presses of keys that changed during sleep
+    pub fn track_power(mut self, track_power: bool) -> Self {
+        self.options.track_power = track_power;
+        self
+    }
+
+    // emit KeyEvent::IdleStarted after threshold without keyboard or mouse input, and KeyEvent::IdleEnded on the next
+    pub fn idle_threshold(mut self, threshold: Duration) -> Self {
+        self.options.idle_threshold = Some(threshold);
+        self
+    }
+
+    // stop polling keys while the secure desktop is active, where every key reads as released
+    pub fn pause_on_secure_desktop(mut self, pause_on_secure_desktop: bool) -> Self {
+        self.options.pause_on_secure_desktop = pause_on_secure_desktop;
+        self
+    }
+
+    // follow every press with KeyEvent::Char events for the text it types, using the foreground window's layout
+    pub fn text_mode(mut self, text_mode: bool) -> Self {
+        self.options.text_mode = text_mode;
+        self
+    }
+
+    // send SensitiveInputStarted / SensitiveInputEnded as the focus moves into / out of a password field, text mode
+    // produces no Char events and recorders record no keys in between
+    pub fn privacy_mode(mut self, privacy_mode: bool) -> Self {
+        self.options.privacy_mode = privacy_mode;
+        self
+    }
+
+    // only emit events while the foreground window matches the filter
+    pub fn foreground_window(mut self, filter: WindowFilter) -> Self {
+        self.options.window_filter = Some(filter);
+        self
+    }
+
+    // capture the foreground window's title and process with each event, see KeyListener::subscribe_with_context
+    pub fn window_context(mut self, window_context: bool) -> Self {
+        self.options.window_context = window_context;
+        self
+    }
+
+    // send the whole pressed set whenever it changes, see KeyListener::subscribe_state_changes
+    pub fn state_changes(mut self, state_changes: bool) -> Self {
+        self.options.state_changes = state_changes;
+        self
+    }
+
+    // send ModifierTap / ModifierDoubleTap for modifiers tapped on their own, window is how long a tap may be
+    // held and how soon the second tap of a double tap has to follow
+    pub fn modifier_gestures(mut self, window: Duration) -> Self {
+        self.options.modifier_gestures = Some(window);
+        self
+    }
+
+    // drops a press coming within window of the same key's previous release, and the release that follows it
+    pub fn debounce(mut self, window: Duration) -> Self {
+        self.debounce.set_window(window);
+        self
+    }
+
+    // a debounce window for one key, overriding the one given to debounce
+    pub fn debounce_key(mut self, vk: impl Into<VirtualKey>, window: Duration) -> Self {
+        self.debounce.set_key_window(vk.into().code(), window);
+        self
+    }
+
+    // at most presses_per_second presses of all keys together, see rate_limit_policy for what happens to the others
+    pub fn rate_limit(mut self, presses_per_second: usize) -> Self {
+        self.rate_limiter.set_limit(presses_per_second);
+        self
+    }
+
+    // at most presses_per_second presses of vk, on top of the limit for all keys
+    pub fn rate_limit_key(mut self, vk: impl Into<VirtualKey>, presses_per_second: usize) -> Self {
+        self.rate_limiter.set_key_limit(vk.into().code(), presses_per_second);
+        self
+    }
+
+    pub fn rate_limit_policy(mut self, policy: RateLimitPolicy) -> Self {
+        self.rate_limiter.set_policy(policy);
+        self
+    }
+
+    // adds a stage to the end of the filter chain, returning None from it drops the event
+    pub fn filter<F>(mut self, stage: F) -> Self
+    where
+        F: FnMut(KeyEvent) -> Option<KeyEvent> + Send + 'static
+    {
+        self.pipeline.push(Box::new(stage));
+        self
+    }
+
+    // adds a loaded script to the end of the filter chain, see KeyScript
+    #[cfg(feature = "scripting")]
+    pub fn script(self, mut script: crate::KeyScript) -> Self {
+        self.filter(move |key_event| script.process(key_event))
+    }
+
+    // registers a consumer, higher priorities are called first and can mark events as handled, see EventConsumer
+    pub fn consumer(mut self, priority: i32, consumer: impl EventConsumer + 'static) -> Self {
+        self.pipeline.add_consumer(priority, Box::new(consumer));
+        self
+    }
+
+    // stops polling once the token is cancelled, like quit, e.g. as part of an application's shutdown
+    #[cfg(feature = "tokio-util")]
+    pub fn cancellation_token(mut self, cancellation_token: tokio_util::sync::CancellationToken) -> Self {
+        self.options.cancellation_token = Some(cancellation_token);
+        self
+    }
+
+    // never polls the keyboard, events only come from KeyListener::mock_backend, e.g. for tests without input
+    #[cfg(feature = "mock")]
+    pub fn simulated(mut self) -> Self {
+        self.options.simulated = true;
+        self
+    }
+
+    // keeps the last capacity events sent, for KeyListener::recent_events
+    pub fn history(mut self, capacity: usize) -> Self {
+        self.options.history_capacity = Some(capacity);
+        self
+    }
+
+    // run the key callbacks (spawn, spawn_any, spawn_with_state) on tokio's blocking thread pool instead of as a
+    // task, for callbacks that block on disk writes, HTTP calls and the like. They take up one of the pool's
+    // threads for as long as the listener lives, and are still called one at a time and in order
+    pub fn blocking_callbacks(mut self, blocking_callbacks: bool) -> Self {
+        self.blocking_callbacks = blocking_callbacks;
+        self
+    }
+
+    // start the polling task again when it panics, after initial_backoff and then twice as long each time up to
+    // max_backoff. Each restart is reported as ListenerWarning::Restarted
+    pub fn supervise(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.supervision = Some(Supervision { initial_backoff, max_backoff: max_backoff.max(initial_backoff) });
+        self
+    }
+
+    // called when a key callback panics, the listener keeps running either way
+    pub fn on_callback_panic<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(CallbackPanic) + Send + 'static
+    {
+        self.panic_handler = Some(Box::new(handler));
+        self
+    }
+
+    // called with conditions that make events late or lost, from a task of its own
+    pub fn on_warning<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(ListenerWarning) + Send + 'static
+    {
+        self.warning_handler = Some(Box::new(handler));
+        self
+    }
+
+    // starts polling and hands back the receiving end of the event channel instead of calling callbacks
+    pub(crate) fn spawn_with_receiver(self) -> Result<(Arc<Mutex<KeyListener>>, EventReceiver), KeyListenerError> {
+        let (key_listener, receiver, _) = self.spawn_listener()?;
+        Ok((key_listener, receiver))
+    }
+
+    // the panic reporter is shared by the spawning callbacks and the ones added later with on_key_down / up
+    fn spawn_listener(mut self) -> Result<(Arc<Mutex<KeyListener>>, EventReceiver, PanicReporter), KeyListenerError> {
+        let mut vk_codes = checked_vk_codes(self.vk_codes, self.skip_invalid_vk_codes)?;
+        validate_polling_interval(self.options.polling_interval)?;
+        if self.options.priority_scan.as_ref().is_some_and(|priority_scan| priority_scan.other_keys_every == 0) {
+            return Err(KeyListenerError::ZeroScanInterval);
+        }
+        if let ChannelMode::Bounded { capacity: 0, .. } = self.channel_mode {
+            return Err(KeyListenerError::ZeroCapacity);
+        }
+
+        // ahead of the modifier mode, which treats the Win keys like the other modifiers
+        if self.windows_keys {
+            vk_codes.extend(WINDOWS_KEYS.iter().map(|key| key.0));
+        }
+        let mut vk_codes = self.modifier_mode.apply_to_vk_codes(vk_codes);
+        // priority keys name modifiers like the key list does
+        if let Some(priority_scan) = self.options.priority_scan.as_mut() {
+            priority_scan.vk_codes = self.modifier_mode.apply_to_vk_codes(std::mem::take(&mut priority_scan.vk_codes));
+        }
+        if self.numpad_navigation {
+            vk_codes = with_numpad_navigation(vk_codes);
+        }
+        if self.mouse_buttons {
+            vk_codes.extend(MOUSE_BUTTONS.iter().map(|button| button.0));
+        }
+        if self.media_keys {
+            vk_codes.extend(MEDIA_KEYS.iter().map(|key| key.0));
+        }
+        if self.browser_keys {
+            vk_codes.extend(BROWSER_KEYS.iter().map(|key| key.0));
+        }
+        if self.function_keys {
+            vk_codes.extend(FUNCTION_KEYS.iter().map(|key| key.0));
+        }
+        // the modes above can list a key again, e.g. a sided modifier that was already watched
+        let vk_codes = dedup_vk_codes(vk_codes);
+        if let Some(stage) = self.modifier_mode.stage() {
+            self.pipeline.push_front(stage);
+        }
+        if self.rate_limiter.is_enabled() {
+            self.pipeline.set_rate_limiter(self.rate_limiter);
+        }
+        // ahead of the modifier stage, bounces are dropped before anything else sees them
+        if let Some(stage) = self.debounce.stage() {
+            self.pipeline.push_front(stage);
+        }
+
+        let warning_sender = self.warning_handler.map(WarningSender::spawn).unwrap_or_default();
+        let dropped_events = Arc::new(AtomicU64::new(0));
+        let (sender, receiver) = event_channel(self.channel_mode, dropped_events.clone());
+        let panic_reporter = PanicReporter::new(self.panic_handler);
+        let mut key_listener = KeyListener::new(
+            sender, dropped_events, warning_sender.clone(), panic_reporter.clone(), self.pipeline, vk_codes, self.options
+        );
+        key_listener.spawn_poll_task();
+        let states = key_listener.watch_state();
+
+        let key_listener = Arc::new(Mutex::new(key_listener));
+        if let Some(supervision) = self.supervision {
+            supervision.spawn(Arc::downgrade(&key_listener), states, warning_sender);
+        }
+        Ok((key_listener, receiver, panic_reporter))
+    }
+
+    pub fn spawn<D, U>(self, key_down_callback: D, key_up_callback: U) -> Result<Arc<Mutex<KeyListener>>, KeyListenerError>
+    where
+        D: FnMut(KeyEvent) + Send + 'static,
+        U: FnMut(KeyEvent) + Send + 'static
+    {
+        let blocking_callbacks = self.blocking_callbacks;
+        let (key_listener, receiver, panic_reporter) = self.spawn_listener()?;
+
+        let callbacks = Callbacks::new(key_down_callback, key_up_callback).with_panic_reporter(panic_reporter);
+        run_callbacks(receiver, callbacks, blocking_callbacks);
+
+        Ok(key_listener)
+    }
+
+    // one callback receiving every event kind (presses, releases, toggles, focus changes, ...) rather than
+    // a key down and a key up callback
+    pub fn spawn_any<F>(self, callback: F) -> Result<Arc<Mutex<KeyListener>>, KeyListenerError>
+    where
+        F: FnMut(KeyEvent) + Send + 'static
+    {
+        let blocking_callbacks = self.blocking_callbacks;
+        let (key_listener, receiver, panic_reporter) = self.spawn_listener()?;
+
+        run_callbacks(receiver, Callbacks::any(callback).with_panic_reporter(panic_reporter), blocking_callbacks);
+
+        Ok(key_listener)
+    }
+
+    // callbacks sharing state, each gets &mut state with the event, so counters and buffers need no Arc<Mutex<_>>
+    pub fn spawn_with_state<S, D, U>(self, state: S, key_down_callback: D, key_up_callback: U) -> Result<Arc<Mutex<KeyListener>>, KeyListenerError>
+    where
+        S: Send + 'static,
+        D: FnMut(&mut S, KeyEvent) + Send + 'static,
+        U: FnMut(&mut S, KeyEvent) + Send + 'static
+    {
+        let blocking_callbacks = self.blocking_callbacks;
+        let (key_listener, receiver, panic_reporter) = self.spawn_listener()?;
+
+        let callbacks = stateful_callbacks(state, key_down_callback, key_up_callback).with_panic_reporter(panic_reporter);
+        run_callbacks(receiver, callbacks, blocking_callbacks);
+
+        Ok(key_listener)
+    }
+
+    pub fn spawn_async<D, DF, U, UF>(self, key_down_callback: D, key_up_callback: U, mode: AsyncCallbackMode) -> Result<Arc<Mutex<KeyListener>>, KeyListenerError>
+    where
+        D: FnMut(KeyEvent) -> DF + Send + 'static,
+        DF: Future<Output = ()> + Send + 'static,
+        U: FnMut(KeyEvent) -> UF + Send + 'static,
+        UF: Future<Output = ()> + Send + 'static
+    {
+        let (key_listener, receiver, panic_reporter) = self.spawn_listener()?;
+
+        let callbacks = AsyncCallbacks::new(key_down_callback, key_up_callback).with_panic_reporter(panic_reporter);
+        spawn_async_receiver(receiver, callbacks, mode);
+
+        Ok(key_listener)
+    }
+}
+
+
+
+// on the blocking pool with KeyListenerBuilder::blocking_callbacks, as a task otherwise
+fn run_callbacks<D, U>(receiver: EventReceiver, callbacks: Callbacks<D, U>, blocking: bool)
+where
+    D: FnMut(KeyEvent) + Send + 'static,
+    U: FnMut(KeyEvent) + Send + 'static
+{
+    if blocking {
+        spawn_blocking_receiver(receiver, callbacks);
+    } else {
+        spawn_receiver(receiver, callbacks);
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_capacity_is_rejected_before_spawning() {
+        // no runtime here, spawning anything would panic
+        for overflow in [OverflowPolicy::DropOldest, OverflowPolicy::DropNewest, OverflowPolicy::Block, OverflowPolicy::Coalesce] {
+            let spawned = KeyListener::builder().bounded(0, overflow).spawn(|_| {}, |_| {});
+            assert!(matches!(spawned, Err(KeyListenerError::ZeroCapacity)));
+        }
+    }
+}
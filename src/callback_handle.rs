@@ -0,0 +1,119 @@
+/*
+
+Callbacks added to a running listener
+
+Besides the pair given when spawning, any number of press / release callbacks can be added to a tokio listener,
+each detached again when its CallbackHandle is removed or dropped. They are called in the order they were added,
+from a task of their own spawned along with the listener, so adding one works from any thread. The polling task
+only starts feeding it once the first one is added. That task runs independently of the one calling the spawning
+callbacks, so there is no ordering between an added callback and those for the same event.
+
+*/
+
+
+use crate::KeyEvent;
+use crate::callbacks::{CallbackKind, PanicReporter};
+use crate::sequence::{SequencedKeyEvent, with_sequence_number};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+
+type RegisteredCallback = Box<dyn FnMut(KeyEvent) + Send + 'static>;
+
+struct Registered {
+    key_up: bool,
+    removed: Arc<AtomicBool>,
+    callback: RegisteredCallback
+}
+
+#[derive(Clone)]
+pub(crate) struct CallbackRegistry {
+    callbacks: Arc<Mutex<Vec<Registered>>>,
+    // events are only forwarded once a callback was added
+    active: Arc<AtomicBool>,
+    sender: UnboundedSender<SequencedKeyEvent>
+}
+
+impl CallbackRegistry {
+    // called when the listener is spawned, on its runtime
+    pub(crate) fn new(panic_reporter: PanicReporter) -> Self {
+        let (sender, receiver) = unbounded_channel();
+        let callbacks = Arc::default();
+        tokio::spawn(dispatch(receiver, Arc::clone(&callbacks), panic_reporter));
+        CallbackRegistry { callbacks, active: Arc::default(), sender }
+    }
+
+    pub(crate) fn add(&self, key_up: bool, callback: RegisteredCallback) -> CallbackHandle {
+        let removed = Arc::new(AtomicBool::new(false));
+        self.callbacks.lock().unwrap().push(Registered { key_up, removed: removed.clone(), callback });
+        self.active.store(true, Ordering::Relaxed);
+        CallbackHandle { removed, kept: false }
+    }
+
+    pub(crate) fn forward(&self, key_event: SequencedKeyEvent) {
+        if self.active.load(Ordering::Relaxed) {
+            let _ = self.sender.send(key_event); // the dispatch task ends only once the registry is gone
+        }
+    }
+}
+
+async fn dispatch(mut receiver: UnboundedReceiver<SequencedKeyEvent>, callbacks: Arc<Mutex<Vec<Registered>>>, panic_reporter: PanicReporter) {
+    while let Some(SequencedKeyEvent { seq, key_event, .. }) = receiver.recv().await {
+        let Some(kind) = CallbackKind::of(&key_event) else {
+            continue;
+        };
+
+        // called without holding the lock, so callbacks can add and remove callbacks themselves
+        let mut running = std::mem::take(&mut *callbacks.lock().unwrap());
+        for registered in running.iter_mut() {
+            let wanted = match kind {
+                CallbackKind::KeyDown => !registered.key_up,
+                CallbackKind::KeyUp => registered.key_up,
+                CallbackKind::Both => true
+            };
+            if !wanted || registered.removed.load(Ordering::Relaxed) {
+                continue;
+            }
+            let called = with_sequence_number(seq, || catch_unwind(AssertUnwindSafe(|| (registered.callback)(key_event))));
+            if let Err(payload) = called {
+                panic_reporter.report(key_event, payload);
+            }
+        }
+
+        let mut callbacks = callbacks.lock().unwrap();
+        // callbacks added meanwhile go after the ones that were running
+        running.append(&mut callbacks);
+        running.retain(|registered| !registered.removed.load(Ordering::Relaxed));
+        *callbacks = running;
+    }
+}
+
+
+
+// detaches its callback when removed or dropped, unless kept
+#[must_use = "dropping the handle removes the callback right away, use keep to keep it for the listener's lifetime"]
+pub struct CallbackHandle {
+    removed: Arc<AtomicBool>,
+    kept: bool
+}
+
+impl CallbackHandle {
+    pub fn remove(self) {}
+
+    // keeps the callback for as long as the listener lives
+    pub fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for CallbackHandle {
+    fn drop(&mut self) {
+        if !self.kept {
+            self.removed.store(true, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+/*
+
+Key event callbacks shared by every backend
+
+Callbacks are owned by the single task / thread that receives events, so they only need to be Send and can
+mutably capture state. A panicking callback is caught so the receiving task / thread keeps delivering events.
+
+*/
+
+
+use crate::KeyEvent;
+
+use std::any::Any;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "tokio")]
+use std::future::Future;
+#[cfg(feature = "tokio")]
+use std::pin::Pin;
+#[cfg(feature = "tokio")]
+use std::task::{Context, Poll};
+
+
+
+// a callback panicked while handling key_event, the event is lost but the listener keeps running
+#[derive(Debug, Clone)]
+pub struct CallbackPanic {
+    pub key_event: KeyEvent,
+    pub message: String
+}
+
+impl CallbackPanic {
+    fn new(key_event: KeyEvent, payload: Box<dyn Any + Send>) -> Self {
+        // panic! payloads are a &str or a String unless panic_any was used
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload.downcast_ref::<&str>().map(|message| message.to_string()).unwrap_or_default()
+        };
+        CallbackPanic { key_event, message }
+    }
+}
+
+
+
+pub(crate) type PanicHandler = Box<dyn FnMut(CallbackPanic) + Send>;
+
+// shared, since concurrently spawned async callbacks can all panic
+#[derive(Clone, Default)]
+pub(crate) struct PanicReporter(Option<Arc<Mutex<PanicHandler>>>);
+
+impl PanicReporter {
+    #[cfg(feature = "tokio")]
+    pub(crate) fn new(handler: Option<PanicHandler>) -> Self {
+        PanicReporter(handler.map(|handler| Arc::new(Mutex::new(handler))))
+    }
+
+    pub(crate) fn report(&self, key_event: KeyEvent, payload: Box<dyn Any + Send>) {
+        #[cfg(feature = "tracing")]
+        tracing::error!(?key_event, "callback panicked");
+
+        if let Some(handler) = &self.0 {
+            let panic = CallbackPanic::new(key_event, payload);
+            // a handler that panicked itself poisoned the lock, keep using it anyway
+            let mut handler = handler.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let _ = catch_unwind(AssertUnwindSafe(|| handler(panic)));
+        }
+    }
+}
+
+
+
+// which of the two callbacks an event goes to, None for event kinds only delivered to subscribers
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CallbackKind {
+    KeyDown,
+    KeyUp,
+    // a Coalesced event stands for presses and their releases, both callbacks get it
+    Both
+}
+
+impl CallbackKind {
+    pub(crate) fn of(key_event: &KeyEvent) -> Option<CallbackKind> {
+        match key_event {
+            KeyEvent::Press(_) | KeyEvent::GamepadPress { .. } => Some(CallbackKind::KeyDown),
+            KeyEvent::Release(_) | KeyEvent::GamepadRelease { .. } => Some(CallbackKind::KeyUp),
+            KeyEvent::Coalesced { .. } => Some(CallbackKind::Both),
+            _ => None
+        }
+    }
+}
+
+
+
+pub(crate) struct Callbacks<D, U> {
+    key_down_callback: D,
+    key_up_callback: U,
+    // key_down_callback receives every event kind, see Callbacks::any
+    all_events: bool,
+    panic_reporter: PanicReporter
+}
+
+impl<D> Callbacks<D, fn(KeyEvent)>
+where
+    D: FnMut(KeyEvent) + Send + 'static
+{
+    // a single callback for presses, releases and every other event kind
+    pub(crate) fn any(callback: D) -> Self {
+        Callbacks { key_down_callback: callback, key_up_callback: |_| {}, all_events: true, panic_reporter: PanicReporter::default() }
+    }
+}
+
+// both callbacks get &mut state, which they could not share as two closures without a lock
+pub(crate) fn stateful_callbacks<S, D, U>(mut state: S, mut key_down_callback: D, mut key_up_callback: U) -> Callbacks<impl FnMut(KeyEvent) + Send + 'static, fn(KeyEvent)>
+where
+    S: Send + 'static,
+    D: FnMut(&mut S, KeyEvent) + Send + 'static,
+    U: FnMut(&mut S, KeyEvent) + Send + 'static
+{
+    Callbacks::any(move |key_event| match CallbackKind::of(&key_event) {
+        Some(CallbackKind::KeyDown) => key_down_callback(&mut state, key_event),
+        Some(CallbackKind::KeyUp) => key_up_callback(&mut state, key_event),
+        Some(CallbackKind::Both) => {
+            key_down_callback(&mut state, key_event);
+            key_up_callback(&mut state, key_event);
+        }
+        None => {}
+    })
+}
+
+impl<D, U> Callbacks<D, U>
+where
+    D: FnMut(KeyEvent) + Send + 'static,
+    U: FnMut(KeyEvent) + Send + 'static
+{
+    pub(crate) fn new(key_down_callback: D, key_up_callback: U) -> Self {
+        Callbacks { key_down_callback, key_up_callback, all_events: false, panic_reporter: PanicReporter::default() }
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn with_panic_reporter(mut self, panic_reporter: PanicReporter) -> Self {
+        self.panic_reporter = panic_reporter;
+        self
+    }
+
+    pub(crate) fn call(&mut self, key_event: KeyEvent) {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("callback", ?key_event).entered();
+
+        let result = catch_unwind(AssertUnwindSafe(|| match CallbackKind::of(&key_event) {
+            _ if self.all_events => (self.key_down_callback)(key_event),
+            Some(CallbackKind::KeyDown) => (self.key_down_callback)(key_event),
+            Some(CallbackKind::KeyUp) => (self.key_up_callback)(key_event),
+            Some(CallbackKind::Both) => {
+                (self.key_down_callback)(key_event);
+                (self.key_up_callback)(key_event);
+            }
+            None => {}
+        }));
+        if let Err(payload) = result {
+            self.panic_reporter.report(key_event, payload);
+        }
+    }
+}
+
+
+
+#[cfg(feature = "tokio")]
+pub(crate) type CallbackFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+// callbacks returning futures, running (awaiting or spawning) them is left to the backend
+#[cfg(feature = "tokio")]
+pub(crate) struct AsyncCallbacks<D, U> {
+    key_down_callback: D,
+    key_up_callback: U,
+    panic_reporter: PanicReporter
+}
+
+#[cfg(feature = "tokio")]
+impl<D, DF, U, UF> AsyncCallbacks<D, U>
+where
+    D: FnMut(KeyEvent) -> DF + Send + 'static,
+    DF: Future<Output = ()> + Send + 'static,
+    U: FnMut(KeyEvent) -> UF + Send + 'static,
+    UF: Future<Output = ()> + Send + 'static
+{
+    pub(crate) fn new(key_down_callback: D, key_up_callback: U) -> Self {
+        AsyncCallbacks { key_down_callback, key_up_callback, panic_reporter: PanicReporter::default() }
+    }
+
+    pub(crate) fn with_panic_reporter(mut self, panic_reporter: PanicReporter) -> Self {
+        self.panic_reporter = panic_reporter;
+        self
+    }
+
+    // panics are caught both when creating the future and while polling it
+    pub(crate) fn call(&mut self, key_event: KeyEvent) -> CallbackFuture {
+        let result = catch_unwind(AssertUnwindSafe(|| -> CallbackFuture {
+            match CallbackKind::of(&key_event) {
+                Some(CallbackKind::KeyDown) => Box::pin((self.key_down_callback)(key_event)),
+                Some(CallbackKind::KeyUp) => Box::pin((self.key_up_callback)(key_event)),
+                Some(CallbackKind::Both) => {
+                    let key_down = (self.key_down_callback)(key_event);
+                    let key_up = (self.key_up_callback)(key_event);
+                    Box::pin(async move {
+                        key_down.await;
+                        key_up.await;
+                    })
+                }
+                None => Box::pin(async {})
+            }
+        }));
+
+        let panic_reporter = self.panic_reporter.clone();
+        match result {
+            Ok(callback_future) => Box::pin(async move {
+                if let Err(payload) = CatchUnwind(callback_future).await {
+                    panic_reporter.report(key_event, payload);
+                }
+            }),
+            Err(payload) => {
+                panic_reporter.report(key_event, payload);
+                Box::pin(async {})
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+struct CatchUnwind(CallbackFuture);
+
+#[cfg(feature = "tokio")]
+impl Future for CatchUnwind {
+    type Output = Result<(), Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match catch_unwind(AssertUnwindSafe(|| self.0.as_mut().poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(payload) => Poll::Ready(Err(payload))
+        }
+    }
+}
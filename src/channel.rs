@@ -0,0 +1,272 @@
+/*
+
+Event channel between the tokio polling task and the receiving task
+
+Unbounded by default. In bounded mode the overflow policy decides what happens once the receiver falls
+`capacity` events behind, and every event lost to it is counted. Coalesce keeps a backlog of its own next to the
+channel, where redundant events are merged while the receiver catches up. When the backlog overflows a press is
+dropped together with its release, and releases of presses already sent are kept. Events carry their sequence number, a
+merged event keeps the number of the first event merged into it.
+
+*/
+
+
+use crate::KeyEvent;
+use crate::sequence::SequencedKeyEvent;
+
+use tokio::sync::{broadcast, mpsc};
+use tokio::sync::mpsc::error::TrySendError;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChannelMode {
+    #[default]
+    Unbounded,
+    Bounded { capacity: usize, overflow: OverflowPolicy }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    // discard the oldest queued event to make room for the new one
+    DropOldest,
+    // discard the new event
+    DropNewest,
+    // pause polling until the receiver catches up (key changes while paused are coalesced or missed)
+    Block,
+    // queue up to another `capacity` events, merging press / release pairs of a key into KeyEvent::Coalesced and
+    // consecutive mouse moves into one, the oldest are discarded once that is full too
+    Coalesce
+}
+
+
+
+#[derive(Clone)]
+pub(crate) enum EventSender {
+    Unbounded(mpsc::UnboundedSender<SequencedKeyEvent>),
+    Bounded(mpsc::Sender<SequencedKeyEvent>, OverflowPolicy, Arc<AtomicU64>),
+    // a broadcast channel already drops its oldest values when full, which is what DropOldest needs
+    Ring(broadcast::Sender<SequencedKeyEvent>),
+    Coalescing(mpsc::Sender<SequencedKeyEvent>, Arc<Mutex<Backlog>>, Arc<AtomicU64>)
+}
+
+pub(crate) enum EventReceiver {
+    Unbounded(mpsc::UnboundedReceiver<SequencedKeyEvent>),
+    Bounded(mpsc::Receiver<SequencedKeyEvent>),
+    Ring(broadcast::Receiver<SequencedKeyEvent>, Arc<AtomicU64>)
+}
+
+// the capacity has been checked to be above 0, which both channels panic on
+pub(crate) fn event_channel(mode: ChannelMode, dropped_events: Arc<AtomicU64>) -> (EventSender, EventReceiver) {
+    match mode {
+        ChannelMode::Unbounded => {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            (EventSender::Unbounded(sender), EventReceiver::Unbounded(receiver))
+        }
+        ChannelMode::Bounded { capacity, overflow: OverflowPolicy::DropOldest } => {
+            let (sender, receiver) = broadcast::channel(capacity);
+            (EventSender::Ring(sender), EventReceiver::Ring(receiver, dropped_events))
+        }
+        ChannelMode::Bounded { capacity, overflow: OverflowPolicy::Coalesce } => {
+            let (sender, receiver) = mpsc::channel(capacity);
+            let backlog = Arc::new(Mutex::new(Backlog::new(capacity)));
+            (EventSender::Coalescing(sender, backlog, dropped_events), EventReceiver::Bounded(receiver))
+        }
+        ChannelMode::Bounded { capacity, overflow } => {
+            let (sender, receiver) = mpsc::channel(capacity);
+            (EventSender::Bounded(sender, overflow, dropped_events), EventReceiver::Bounded(receiver))
+        }
+    }
+}
+
+
+
+impl EventSender {
+    pub(crate) async fn send(&self, key_event: SequencedKeyEvent) {
+        match self {
+            EventSender::Unbounded(sender) => {
+                let _ = sender.send(key_event);
+            }
+            EventSender::Bounded(sender, OverflowPolicy::Block, _) => {
+                let _ = sender.send(key_event).await;
+            }
+            EventSender::Bounded(sender, _, dropped_events) => {
+                if let Err(TrySendError::Full(_)) = sender.try_send(key_event) {
+                    dropped_events.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            EventSender::Ring(sender) => {
+                let _ = sender.send(key_event);
+            }
+            EventSender::Coalescing(sender, backlog, dropped_events) => {
+                let mut backlog = backlog.lock().unwrap_or_else(PoisonError::into_inner);
+                // the backlog goes first, events stay in order
+                backlog.flush(sender);
+                if !backlog.is_empty() || sender.try_send(key_event).is_err() {
+                    let dropped = backlog.push(key_event);
+                    dropped_events.fetch_add(dropped, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    // moves what fits of the coalescing backlog into the channel, called every round of polling
+    pub(crate) fn flush(&self) {
+        if let EventSender::Coalescing(sender, backlog, _) = self {
+            backlog.lock().unwrap_or_else(PoisonError::into_inner).flush(sender);
+        }
+    }
+
+    // events waiting to be received, None for the unbounded channel which does not track it
+    #[cfg(feature = "tracing")]
+    pub(crate) fn queue_depth(&self) -> Option<usize> {
+        match self {
+            EventSender::Unbounded(_) => None,
+            EventSender::Bounded(sender, _, _) => Some(sender.max_capacity() - sender.capacity()),
+            EventSender::Ring(sender) => Some(sender.len()),
+            EventSender::Coalescing(sender, backlog, _) => {
+                let backlog = backlog.lock().unwrap_or_else(PoisonError::into_inner);
+                Some(sender.max_capacity() - sender.capacity() + backlog.events.len())
+            }
+        }
+    }
+
+    // true once the receiving side has been dropped
+    pub(crate) fn is_closed(&self) -> bool {
+        match self {
+            EventSender::Unbounded(sender) => sender.is_closed(),
+            EventSender::Bounded(sender, _, _) => sender.is_closed(),
+            EventSender::Ring(sender) => sender.receiver_count() == 0,
+            EventSender::Coalescing(sender, _, _) => sender.is_closed()
+        }
+    }
+}
+
+
+
+// events waiting for room in a full channel
+pub(crate) struct Backlog {
+    events: VecDeque<SequencedKeyEvent>,
+    capacity: usize
+}
+
+impl Backlog {
+    fn new(capacity: usize) -> Self {
+        Backlog { events: VecDeque::new(), capacity }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    fn flush(&mut self, sender: &mpsc::Sender<SequencedKeyEvent>) {
+        while let Some(&key_event) = self.events.front() {
+            if sender.try_send(key_event).is_err() {
+                break;
+            }
+            self.events.pop_front();
+        }
+    }
+
+    // returns how many events were discarded to stay within capacity
+    fn push(&mut self, sequenced: SequencedKeyEvent) -> u64 {
+        match sequenced.key_event {
+            KeyEvent::Release(vk) => {
+                // the key's latest event being its press, the pair is folded into a Coalesced event
+                let latest = self.events.iter().rposition(|queued| queued.key_event.vk() == Some(vk));
+                if let Some(index) = latest
+                    && self.events[index].key_event == KeyEvent::Press(vk)
+                {
+                    let press = self.events.remove(index).expect("the index was just found");
+                    let earlier = self.events.iter().rposition(|queued| queued.key_event.vk() == Some(vk));
+                    match earlier.map(|earlier| (earlier, self.events[earlier].key_event)) {
+                        Some((earlier, KeyEvent::Coalesced { key, presses })) => {
+                            self.events[earlier].key_event = KeyEvent::Coalesced { key, presses: presses + 1 };
+                        }
+                        _ => self.events.insert(index, SequencedKeyEvent {
+                            seq: press.seq,
+                            polled_at: press.polled_at,
+                            key_event: KeyEvent::Coalesced { key: vk, presses: 1 }
+                        })
+                    }
+                    return 0;
+                }
+            }
+            KeyEvent::MouseMove { x, y, dx, dy } => {
+                if let Some(SequencedKeyEvent {
+                    key_event: KeyEvent::MouseMove { x: queued_x, y: queued_y, dx: queued_dx, dy: queued_dy }, ..
+                }) = self.events.back_mut() {
+                    (*queued_x, *queued_y) = (x, y);
+                    *queued_dx += dx;
+                    *queued_dy += dy;
+                    return 0;
+                }
+            }
+            _ => {}
+        }
+
+        self.events.push_back(sequenced);
+        let mut dropped = 0;
+        while self.events.len() > self.capacity {
+            dropped += self.drop_oldest();
+        }
+        dropped
+    }
+
+    // a queued release belongs to a press the receiver already has (a dropped press takes its release along), so
+    // the oldest other event goes
+    fn drop_oldest(&mut self) -> u64 {
+        let oldest = self.events.iter()
+            .position(|queued| !matches!(queued.key_event, KeyEvent::Release(_)))
+            .unwrap_or(0);
+        let dropped = self.events.remove(oldest).expect("the backlog is over capacity");
+        if let KeyEvent::Press(vk) = dropped.key_event {
+            let next = self.events.iter().skip(oldest).position(|queued| queued.key_event.vk() == Some(vk));
+            if let Some(next) = next
+                && self.events[oldest + next].key_event == KeyEvent::Release(vk)
+            {
+                self.events.remove(oldest + next);
+                return 2;
+            }
+        }
+        1
+    }
+}
+
+impl EventReceiver {
+    // outside of the runtime, on a thread that may block
+    pub(crate) fn blocking_recv(&mut self) -> Option<SequencedKeyEvent> {
+        match self {
+            EventReceiver::Unbounded(receiver) => receiver.blocking_recv(),
+            EventReceiver::Bounded(receiver) => receiver.blocking_recv(),
+            EventReceiver::Ring(receiver, dropped_events) => loop {
+                match receiver.blocking_recv() {
+                    Ok(key_event) => return Some(key_event),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        dropped_events.fetch_add(skipped, Ordering::Relaxed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn recv(&mut self) -> Option<SequencedKeyEvent> {
+        match self {
+            EventReceiver::Unbounded(receiver) => receiver.recv().await,
+            EventReceiver::Bounded(receiver) => receiver.recv().await,
+            EventReceiver::Ring(receiver, dropped_events) => loop {
+                match receiver.recv().await {
+                    Ok(key_event) => return Some(key_event),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        dropped_events.fetch_add(skipped, Ordering::Relaxed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+/*
+
+Chord detection
+
+Groups keys pressed within a short window of the first one (e.g. 30 ms) into one chord, the way stenography
+keyboards and multi-key gestures expect. The window starts at the first press, a press after it closed starts the
+next chord. A key pressed on its own comes out as a chord of one key. Presses are timed by when they were polled
+where that is known (push_at, ChordStream), so a receiver that falls behind still groups them as they were pressed.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+
+use std::time::{Duration, Instant};
+#[cfg(feature = "tokio")]
+use crate::sequence::SequencedKeyEvent;
+
+#[cfg(feature = "tokio")]
+use tokio::sync::broadcast;
+
+
+
+// keys in the order they were pressed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chord(pub Vec<VirtualKey>);
+
+#[derive(Clone, Debug)]
+pub struct ChordDetector {
+    window: Duration,
+    keys: Vec<VirtualKey>,
+    started: Option<Instant>
+}
+
+impl ChordDetector {
+    pub fn new(window: Duration) -> Self {
+        ChordDetector { window, keys: Vec::new(), started: None }
+    }
+
+    // feeds one event in, returning the previous chord when a press came after its window closed
+    pub fn push(&mut self, key_event: KeyEvent) -> Option<Chord> {
+        self.push_at(key_event, Instant::now())
+    }
+
+    // push for an event that happened at pressed_at, e.g. SequencedKeyEvent::polled_at
+    pub fn push_at(&mut self, key_event: KeyEvent, pressed_at: Instant) -> Option<Chord> {
+        let KeyEvent::Press(vk) = key_event else {
+            return None;
+        };
+        let finished = match self.deadline() {
+            Some(deadline) if pressed_at >= deadline => self.take(),
+            _ => None
+        };
+        if self.started.is_none() {
+            self.started = Some(pressed_at);
+        }
+        if !self.keys.contains(&vk) {
+            self.keys.push(vk);
+        }
+        finished
+    }
+
+    // the pending chord once its window has closed, call this when no more presses are coming in
+    pub fn poll(&mut self) -> Option<Chord> {
+        let started = self.started?;
+        if started.elapsed() < self.window {
+            return None;
+        }
+        self.take()
+    }
+
+    // the pending chord without waiting for its window to close
+    pub fn take(&mut self) -> Option<Chord> {
+        self.started.take()?;
+        Some(Chord(std::mem::take(&mut self.keys)))
+    }
+
+    // when the pending chord's window closes, None without one
+    pub fn deadline(&self) -> Option<Instant> {
+        self.started.map(|started| started + self.window)
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.started = None;
+    }
+}
+
+
+
+// chords pressed while a listener is running, see KeyListener::chords
+#[cfg(feature = "tokio")]
+pub struct ChordStream {
+    receiver: broadcast::Receiver<SequencedKeyEvent>,
+    detector: ChordDetector
+}
+
+#[cfg(feature = "tokio")]
+impl ChordStream {
+    // from KeyListener::subscribe_sequenced
+    pub fn new(receiver: broadcast::Receiver<SequencedKeyEvent>, window: Duration) -> Self {
+        ChordStream { receiver, detector: ChordDetector::new(window) }
+    }
+
+    // None once the listener is gone
+    pub async fn next_chord(&mut self) -> Option<Chord> {
+        loop {
+            let received = match self.detector.deadline() {
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline.into(), self.receiver.recv()).await {
+                        Ok(received) => received,
+                        // a press polled before the deadline may still be on its way
+                        Err(_) => match self.receiver.try_recv() {
+                            Ok(sequenced) => Ok(sequenced),
+                            Err(_) => return self.detector.poll()
+                        }
+                    }
+                }
+                None => self.receiver.recv().await
+            };
+            match received {
+                Ok(sequenced) => {
+                    if let Some(chord) = self.detector.push_at(sequenced.key_event, sequenced.polled_at) {
+                        return Some(chord);
+                    }
+                }
+                // a chord with missed presses would be a different chord, so drop it
+                Err(broadcast::error::RecvError::Lagged(_)) => self.detector.clear(),
+                Err(broadcast::error::RecvError::Closed) => return self.detector.take()
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+/*
+
+Event consumers
+
+Consumers let independent parts of an application (or plugins) share one listener. They are called from the
+polling task in order of priority, highest first, after the filter stages and before the callbacks / subscribers.
+A consumer returning Handled stops the event there: lower priority consumers, callbacks and subscribers never
+see it. A consumer that handles a press should usually handle the matching release as well.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventStatus {
+    Unhandled,
+    Handled
+}
+
+pub trait EventConsumer: Send {
+    fn on_press(&mut self, _key: VirtualKey) -> EventStatus {
+        EventStatus::Unhandled
+    }
+
+    fn on_release(&mut self, _key: VirtualKey) -> EventStatus {
+        EventStatus::Unhandled
+    }
+
+    // once per polling round, after the round's events
+    fn on_tick(&mut self) {}
+}
+
+
+
+#[derive(Default)]
+pub(crate) struct Consumers {
+    // sorted by priority, highest first, ties in registration order
+    consumers: Vec<(i32, Box<dyn EventConsumer>)>
+}
+
+impl Consumers {
+    pub(crate) fn add(&mut self, priority: i32, consumer: Box<dyn EventConsumer>) {
+        let index = self.consumers.partition_point(|(other, _)| *other >= priority);
+        self.consumers.insert(index, (priority, consumer));
+    }
+
+    // None if a consumer handled the event, other events than presses and releases pass untouched
+    pub(crate) fn dispatch(&mut self, key_event: KeyEvent) -> Option<KeyEvent> {
+        for (_, consumer) in self.consumers.iter_mut() {
+            let status = match key_event {
+                KeyEvent::Press(key) => consumer.on_press(key),
+                KeyEvent::Release(key) => consumer.on_release(key),
+                _ => return Some(key_event)
+            };
+            if status == EventStatus::Handled {
+                return None;
+            }
+        }
+        Some(key_event)
+    }
+
+    pub(crate) fn tick(&mut self) {
+        for (_, consumer) in self.consumers.iter_mut() {
+            consumer.on_tick();
+        }
+    }
+}
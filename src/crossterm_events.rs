@@ -0,0 +1,176 @@
+/*
+
+Conversions to crossterm key events
+
+Lets a terminal application merge global key events into its crossterm event loop. Keys convert to the KeyCode
+crossterm reports for them: letters as lowercase characters (uppercase while shift is held, like crossterm), other
+character keys as the character they type on a US keyboard, and modifier / media keys as KeyCode::Modifier /
+KeyCode::Media, which crossterm itself only reports with keyboard enhancement flags enabled.
+
+*/
+
+
+use crate::{KeyEvent, Modifiers, UnmappedKey, VirtualKey};
+
+use crossterm::event::{
+    KeyCode, KeyEvent as CrosstermKeyEvent, KeyEventKind, KeyModifiers, MediaKeyCode, ModifierKeyCode
+};
+use tokio::sync::broadcast;
+use std::collections::HashSet;
+
+
+
+// keys that are not characters, sided modifiers come before the generic codes so each maps back to a sided key
+const NAMED_KEYS: [(i32, KeyCode); 42] = [
+    (0x08, KeyCode::Backspace), (0x09, KeyCode::Tab), (0x0C, KeyCode::KeypadBegin), (0x0D, KeyCode::Enter),
+    (0x13, KeyCode::Pause), (0x14, KeyCode::CapsLock), (0x1B, KeyCode::Esc), (0x21, KeyCode::PageUp),
+    (0x22, KeyCode::PageDown), (0x23, KeyCode::End), (0x24, KeyCode::Home), (0x25, KeyCode::Left),
+    (0x26, KeyCode::Up), (0x27, KeyCode::Right), (0x28, KeyCode::Down), (0x2C, KeyCode::PrintScreen),
+    (0x2D, KeyCode::Insert), (0x2E, KeyCode::Delete), (0x5D, KeyCode::Menu), (0x90, KeyCode::NumLock),
+    (0x91, KeyCode::ScrollLock),
+    (0xA0, KeyCode::Modifier(ModifierKeyCode::LeftShift)), (0xA1, KeyCode::Modifier(ModifierKeyCode::RightShift)),
+    (0xA2, KeyCode::Modifier(ModifierKeyCode::LeftControl)), (0xA3, KeyCode::Modifier(ModifierKeyCode::RightControl)),
+    (0xA4, KeyCode::Modifier(ModifierKeyCode::LeftAlt)), (0xA5, KeyCode::Modifier(ModifierKeyCode::RightAlt)),
+    (0x5B, KeyCode::Modifier(ModifierKeyCode::LeftSuper)), (0x5C, KeyCode::Modifier(ModifierKeyCode::RightSuper)),
+    (0x10, KeyCode::Modifier(ModifierKeyCode::LeftShift)), (0x11, KeyCode::Modifier(ModifierKeyCode::LeftControl)),
+    (0x12, KeyCode::Modifier(ModifierKeyCode::LeftAlt)),
+    (0xAD, KeyCode::Media(MediaKeyCode::MuteVolume)), (0xAE, KeyCode::Media(MediaKeyCode::LowerVolume)),
+    (0xAF, KeyCode::Media(MediaKeyCode::RaiseVolume)), (0xB0, KeyCode::Media(MediaKeyCode::TrackNext)),
+    (0xB1, KeyCode::Media(MediaKeyCode::TrackPrevious)), (0xB2, KeyCode::Media(MediaKeyCode::Stop)),
+    (0xB3, KeyCode::Media(MediaKeyCode::PlayPause)),
+    // the numpad's operators, its digits are handled with the other digits
+    (0x6A, KeyCode::Char('*')), (0x6B, KeyCode::Char('+')), (0x6F, KeyCode::Char('/'))
+];
+
+// punctuation keys and what they type on a US keyboard without shift
+const US_PUNCTUATION: [(i32, char); 11] = [
+    (0xBA, ';'), (0xBB, '='), (0xBC, ','), (0xBD, '-'), (0xBE, '.'), (0xBF, '/'), (0xC0, '`'), (0xDB, '['),
+    (0xDC, '\\'), (0xDD, ']'), (0xDE, '\'')
+];
+
+
+
+impl TryFrom<VirtualKey> for KeyCode {
+    type Error = UnmappedKey;
+
+    fn try_from(vk: VirtualKey) -> Result<Self, Self::Error> {
+        let vk_code = vk.code();
+        let key_code = match vk_code {
+            0x20 => KeyCode::Char(' '),
+            0x30..=0x39 => KeyCode::Char(vk_code as u8 as char),
+            0x41..=0x5A => KeyCode::Char((vk_code as u8 as char).to_ascii_lowercase()),
+            0x60..=0x69 => KeyCode::Char((b'0' + (vk_code - 0x60) as u8) as char),
+            0x6D => KeyCode::Char('-'),
+            0x6E => KeyCode::Char('.'),
+            0x70..=0x87 => KeyCode::F((vk_code - VirtualKey::F1.0 + 1) as u8),
+            _ => {
+                let named = NAMED_KEYS.iter().find(|(other, _)| *other == vk_code).map(|(_, key_code)| *key_code);
+                let punctuation = || US_PUNCTUATION.iter().find(|(other, _)| *other == vk_code).map(|(_, c)| KeyCode::Char(*c));
+                return named.or_else(punctuation).ok_or(UnmappedKey);
+            }
+        };
+        Ok(key_code)
+    }
+}
+
+// characters map to the key typing them on a US keyboard, numpad keys are never chosen
+impl TryFrom<KeyCode> for VirtualKey {
+    type Error = UnmappedKey;
+
+    fn try_from(key_code: KeyCode) -> Result<Self, Self::Error> {
+        let vk_code = match key_code {
+            KeyCode::Char(' ') => 0x20,
+            KeyCode::Char(c @ ('0'..='9' | 'A'..='Z')) => c as i32,
+            KeyCode::Char(c @ 'a'..='z') => c.to_ascii_uppercase() as i32,
+            KeyCode::Char(c) => US_PUNCTUATION.iter()
+                .find(|(_, other)| *other == c)
+                .map(|(vk_code, _)| *vk_code)
+                .ok_or(UnmappedKey)?,
+            KeyCode::F(n @ 1..=24) => VirtualKey::F1.0 + n as i32 - 1,
+            KeyCode::BackTab => VirtualKey::TAB.0,
+            _ => NAMED_KEYS.iter()
+                .find(|(_, other)| *other == key_code)
+                .map(|(vk_code, _)| *vk_code)
+                .ok_or(UnmappedKey)?
+        };
+        Ok(VirtualKey(vk_code))
+    }
+}
+
+impl From<Modifiers> for KeyModifiers {
+    fn from(modifiers: Modifiers) -> Self {
+        let mut key_modifiers = KeyModifiers::NONE;
+        key_modifiers.set(KeyModifiers::CONTROL, modifiers.ctrl);
+        key_modifiers.set(KeyModifiers::SHIFT, modifiers.shift);
+        key_modifiers.set(KeyModifiers::ALT, modifiers.alt);
+        key_modifiers.set(KeyModifiers::SUPER, modifiers.win);
+        key_modifiers
+    }
+}
+
+// presses (and Coalesced presses) and releases, with the modifiers held at the time
+pub fn to_crossterm(key_event: KeyEvent, modifiers: Modifiers) -> Result<CrosstermKeyEvent, UnmappedKey> {
+    let (vk, kind) = match key_event {
+        KeyEvent::Press(vk) | KeyEvent::Coalesced { key: vk, .. } => (vk, KeyEventKind::Press),
+        KeyEvent::Release(vk) => (vk, KeyEventKind::Release),
+        _ => return Err(UnmappedKey)
+    };
+    let key_code = match KeyCode::try_from(vk)? {
+        // crossterm's conventions for shifted keys
+        KeyCode::Char(c) if modifiers.shift && c.is_ascii_lowercase() => KeyCode::Char(c.to_ascii_uppercase()),
+        KeyCode::Tab if modifiers.shift => KeyCode::BackTab,
+        key_code => key_code
+    };
+    Ok(CrosstermKeyEvent::new_with_kind(key_code, modifiers.into(), kind))
+}
+
+impl TryFrom<KeyEvent> for CrosstermKeyEvent {
+    type Error = UnmappedKey;
+
+    // without modifiers, CrosstermEvents tracks them
+    fn try_from(key_event: KeyEvent) -> Result<Self, Self::Error> {
+        to_crossterm(key_event, Modifiers::default())
+    }
+}
+
+
+
+// a listener's events as crossterm key events, see KeyListener::crossterm_events. Modifiers are only known when
+// the listener watches them, as the default key list does for shift
+pub struct CrosstermEvents {
+    receiver: broadcast::Receiver<KeyEvent>,
+    pressed: HashSet<VirtualKey>
+}
+
+impl CrosstermEvents {
+    pub fn new(receiver: broadcast::Receiver<KeyEvent>) -> Self {
+        CrosstermEvents { receiver, pressed: HashSet::new() }
+    }
+
+    // None once the listener is gone, events without a crossterm counterpart are skipped
+    pub async fn next(&mut self) -> Option<CrosstermKeyEvent> {
+        loop {
+            let key_event = match self.receiver.recv().await {
+                Ok(key_event) => key_event,
+                // missed releases would leave modifiers stuck, so start over
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    self.pressed.clear();
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return None
+            };
+            match key_event {
+                KeyEvent::Press(vk) => {
+                    self.pressed.insert(vk);
+                }
+                KeyEvent::Release(vk) => {
+                    self.pressed.remove(&vk);
+                }
+                _ => {}
+            }
+            if let Ok(crossterm_event) = to_crossterm(key_event, Modifiers::from_any_side(&self.pressed)) {
+                return Some(crossterm_event);
+            }
+        }
+    }
+}
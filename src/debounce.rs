@@ -0,0 +1,66 @@
+/*
+
+Debouncing for chattering keys
+
+Worn switches can report a release and a second press within a few milliseconds of each other. A press that
+follows the previous release of the same key too closely is dropped, together with its release, before any
+other stage sees it.
+
+*/
+
+
+use crate::KeyEvent;
+use crate::pipeline::Stage;
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+
+
+#[derive(Default)]
+pub(crate) struct Debounce {
+    // for every key without one of its own
+    window: Option<Duration>,
+    key_windows: HashMap<i32, Duration>
+}
+
+impl Debounce {
+    pub(crate) fn set_window(&mut self, window: Duration) {
+        self.window = Some(window);
+    }
+
+    pub(crate) fn set_key_window(&mut self, vk_code: i32, window: Duration) {
+        self.key_windows.insert(vk_code, window);
+    }
+
+    pub(crate) fn stage(self) -> Option<Stage> {
+        if self.window.is_none() && self.key_windows.is_empty() {
+            return None;
+        }
+
+        let mut last_release = HashMap::<i32, Instant>::new();
+        // keys whose press was dropped, their next release is dropped too
+        let mut bouncing = HashSet::<i32>::new();
+        Some(Box::new(move |key_event| {
+            match key_event {
+                KeyEvent::Press(vk) => {
+                    let window = self.key_windows.get(&vk.code()).copied().or(self.window);
+                    if let Some(window) = window
+                        && last_release.get(&vk.code()).is_some_and(|released| released.elapsed() < window)
+                    {
+                        bouncing.insert(vk.code());
+                        return None;
+                    }
+                }
+                KeyEvent::Release(vk) => {
+                    if bouncing.remove(&vk.code()) {
+                        return None;
+                    }
+                    last_release.insert(vk.code(), Instant::now());
+                }
+                _ => {}
+            }
+            Some(key_event)
+        }))
+    }
+}
@@ -0,0 +1,133 @@
+/*
+
+Keyboard devices
+
+What Raw Input tells about the attached keyboards, for device pickers and per device filtering. USB (and most
+Bluetooth) keyboards have their vendor and product id in their device path and a product name, other keyboards
+(PS/2, the virtual keyboard of a remote desktop session, ...) do not.
+
+https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getrawinputdevicelist
+
+*/
+
+
+use crate::raw_input::{DeviceHandle, vid_pid};
+
+use windows::core::{Error, PCWSTR};
+use windows::Win32::Devices::HumanInterfaceDevice::HidD_GetProductString;
+use windows::Win32::Foundation::{CloseHandle, ERROR_INSUFFICIENT_BUFFER};
+use windows::Win32::Storage::FileSystem::{
+    CreateFileW, FILE_FLAGS_AND_ATTRIBUTES, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING
+};
+use windows::Win32::UI::Input::{GetRawInputDeviceList, RAWINPUTDEVICELIST, RIM_TYPEKEYBOARD};
+
+
+
+// a USB string descriptor holds up to 126 characters, plus the terminating 0
+const PRODUCT_NAME_CAPACITY: usize = 127;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyboardKind {
+    // USB or Bluetooth
+    Hid,
+    // built into the machine or on a PS/2 port
+    Ps2,
+    // created by software, e.g. the keyboard of a remote desktop session
+    Virtual,
+    Other
+}
+
+impl KeyboardKind {
+    fn from_path(path: &str) -> Self {
+        let path = path.to_ascii_uppercase();
+        if path.starts_with(r"\\?\HID#") {
+            KeyboardKind::Hid
+        } else if path.starts_with(r"\\?\ACPI#") {
+            KeyboardKind::Ps2
+        } else if path.starts_with(r"\\?\ROOT#") {
+            KeyboardKind::Virtual
+        } else {
+            KeyboardKind::Other
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub handle: DeviceHandle,
+    // the device interface path, e.g. \\?\HID#VID_046D&PID_C31C&MI_00#..., empty for a device never seen attached
+    pub path: String,
+    // the product name the device reports, HID keyboards only
+    pub name: Option<String>,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub kind: KeyboardKind
+}
+
+impl DeviceInfo {
+    // None once the device is gone
+    pub(crate) fn of(handle: DeviceHandle) -> Option<Self> {
+        let path = handle.path()?;
+        let (vid, pid) = vid_pid(&path).unzip();
+        let kind = KeyboardKind::from_path(&path);
+        let name = if kind == KeyboardKind::Hid { product_name(&path) } else { None };
+        Some(DeviceInfo { handle, path, name, vid, pid, kind })
+    }
+
+    pub(crate) fn unknown(handle: DeviceHandle) -> Self {
+        DeviceInfo { handle, path: String::new(), name: None, vid: None, pid: None, kind: KeyboardKind::Other }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceChange {
+    Connected(DeviceInfo),
+    // with the info the device had while it was attached
+    Disconnected(DeviceInfo)
+}
+
+
+
+// every keyboard attached right now
+pub fn list_keyboards() -> Result<Vec<DeviceInfo>, Error> {
+    let entry_size = size_of::<RAWINPUTDEVICELIST>() as u32;
+    let mut devices = Vec::new();
+    // devices can be attached between the two calls, in which case the list is too short and read again
+    loop {
+        let mut count = 0u32;
+        if unsafe { GetRawInputDeviceList(None, &mut count, entry_size) } == u32::MAX {
+            return Err(Error::from_win32());
+        }
+        devices.resize(count as usize, RAWINPUTDEVICELIST::default());
+        let read = unsafe { GetRawInputDeviceList(Some(devices.as_mut_ptr()), &mut count, entry_size) };
+        if read != u32::MAX {
+            devices.truncate(read as usize);
+            break;
+        }
+        let error = Error::from_win32();
+        if error.code() != ERROR_INSUFFICIENT_BUFFER.to_hresult() {
+            return Err(error);
+        }
+    }
+
+    Ok(devices.iter()
+        .filter(|device| device.dwType == RIM_TYPEKEYBOARD)
+        .filter_map(|device| DeviceInfo::of(DeviceHandle(device.hDevice.0 as isize)))
+        .collect())
+}
+
+// opened without access rights, which is enough for the strings even though the system holds keyboards exclusively
+fn product_name(path: &str) -> Option<String> {
+    let path: Vec<u16> = path.encode_utf16().chain([0]).collect();
+    unsafe {
+        let device = CreateFileW(
+            PCWSTR(path.as_ptr()), 0, FILE_SHARE_READ | FILE_SHARE_WRITE, None, OPEN_EXISTING,
+            FILE_FLAGS_AND_ATTRIBUTES(0), None
+        ).ok()?;
+        let mut name = [0u16; PRODUCT_NAME_CAPACITY];
+        let found = HidD_GetProductString(device, name.as_mut_ptr().cast(), size_of_val(&name) as u32);
+        let _ = CloseHandle(device);
+        let end = name.iter().position(|c| *c == 0).unwrap_or(name.len());
+        (found && end > 0).then(|| String::from_utf16_lossy(&name[..end]))
+    }
+}
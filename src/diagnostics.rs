@@ -0,0 +1,135 @@
+/*
+
+Latency diagnostics
+
+Injects key presses with SendInput and times how long the listener takes to report each, to tune the polling
+interval empirically. Polling adds up to one interval of latency, so presses are spread over the interval.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+use crate::builder::KeyListenerBuilder;
+use crate::error::KeyListenerError;
+use crate::inject::send_key;
+use crate::self_test::arrival;
+
+use tokio::time::{sleep, Duration};
+
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LatencyReport {
+    // presses and releases injected
+    pub injected: usize,
+    // injected events not reported within the timeout
+    pub missed: usize,
+    pub min: Duration,
+    pub median: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+    pub average: Duration
+}
+
+impl LatencyReport {
+    // share of injected events that were missed, from 0 to 1
+    pub fn miss_rate(&self) -> f64 {
+        if self.injected == 0 {
+            return 0.0;
+        }
+        self.missed as f64 / self.injected as f64
+    }
+
+    fn new(injected: usize, mut latencies: Vec<Duration>) -> Self {
+        latencies.sort();
+        let percentile = |percent: usize| match latencies.len() {
+            0 => Duration::ZERO,
+            len => latencies[(len - 1) * percent / 100]
+        };
+        let total: Duration = latencies.iter().sum();
+        LatencyReport {
+            injected,
+            missed: injected - latencies.len(),
+            min: percentile(0),
+            median: percentile(50),
+            p95: percentile(95),
+            max: percentile(100),
+            average: total.checked_div(latencies.len() as u32).unwrap_or_default()
+        }
+    }
+}
+
+
+
+// presses and releases vk samples times with the listener built by builder, which has to watch vk.
+// Injected keys reach the focused window, a key without an effect there (e.g. F13 - F24) is the safest to use
+pub async fn measure_latency(
+    builder: KeyListenerBuilder,
+    vk: impl Into<VirtualKey>,
+    samples: usize,
+    timeout: Duration
+) -> Result<LatencyReport, KeyListenerError> {
+    let vk = vk.into();
+    let (key_listener, mut receiver) = builder.spawn_with_receiver()?;
+
+    let mut latencies = Vec::with_capacity(samples * 2);
+    for sample in 0..samples {
+        for key_event in [KeyEvent::Press(vk), KeyEvent::Release(vk)] {
+            let up = key_event == KeyEvent::Release(vk);
+            send_key(vk, up);
+            match arrival(&mut receiver, key_event, timeout).await {
+                Some(latency) => latencies.push(latency),
+                // a missed press still has to be released, a missed release is sent again
+                None => send_key(vk, true)
+            }
+            // uneven pauses, so injections land at different points of the polling interval
+            sleep(Duration::from_micros(((sample * 7919) % 13_000) as u64)).await;
+        }
+    }
+
+    key_listener.lock().await.quit();
+    Ok(LatencyReport::new(samples * 2, latencies))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(latencies: &[u64]) -> Vec<Duration> {
+        latencies.iter().map(|&ms| Duration::from_millis(ms)).collect()
+    }
+
+    #[test]
+    fn percentiles_of_unsorted_latencies() {
+        let report = LatencyReport::new(10, millis(&[9, 1, 5, 3, 7, 2, 8, 4, 10, 6]));
+        assert_eq!(report.missed, 0);
+        assert_eq!(report.min, Duration::from_millis(1));
+        assert_eq!(report.median, Duration::from_millis(5));
+        assert_eq!(report.p95, Duration::from_millis(9));
+        assert_eq!(report.max, Duration::from_millis(10));
+        assert_eq!(report.average, Duration::from_micros(5500));
+    }
+
+    #[test]
+    fn misses_are_counted_apart_from_latencies() {
+        let report = LatencyReport::new(4, millis(&[2, 4, 6]));
+        assert_eq!(report.missed, 1);
+        assert_eq!(report.miss_rate(), 0.25);
+        assert_eq!(report.max, Duration::from_millis(6));
+    }
+
+    #[test]
+    fn everything_missed() {
+        let report = LatencyReport::new(6, Vec::new());
+        assert_eq!(report.missed, 6);
+        assert_eq!(report.miss_rate(), 1.0);
+        assert_eq!((report.min, report.max, report.average), (Duration::ZERO, Duration::ZERO, Duration::ZERO));
+    }
+
+    #[test]
+    fn nothing_injected() {
+        assert_eq!(LatencyReport::new(0, Vec::new()).miss_rate(), 0.0);
+    }
+}
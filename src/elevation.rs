@@ -0,0 +1,112 @@
+/*
+
+Elevation and UIPI
+
+User Interface Privilege Isolation keeps a process from observing input to windows of a higher integrity level:
+while an elevated window (an admin console, an installer, Task Manager, ...) has the focus, polling reads every key
+as up and hooks / Raw Input receive nothing, unless this process is elevated itself or runs with uiAccess.
+
+https://learn.microsoft.com/en-us/windows/win32/winauto/uiauto-securityoverview
+
+*/
+
+
+#[cfg(feature = "tokio")]
+use crate::WindowHandle;
+
+use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use windows::Win32::Security::{
+    GetTokenInformation, TokenElevation, TokenUIAccess, TOKEN_ELEVATION, TOKEN_INFORMATION_CLASS, TOKEN_QUERY
+};
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+#[cfg(feature = "tokio")]
+use windows::Win32::System::Threading::{OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+use std::ffi::c_void;
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptureLimitations {
+    // this process runs elevated
+    pub elevated: bool,
+    // this process has uiAccess (signed, installed in a secure location and asking for it in its manifest)
+    pub ui_access: bool
+}
+
+impl CaptureLimitations {
+    // input to elevated windows is invisible to this process
+    pub fn misses_elevated_windows(&self) -> bool {
+        !self.elevated && !self.ui_access
+    }
+}
+
+// what UIPI keeps this process from seeing, for every backend
+pub fn capture_limitations() -> CaptureLimitations {
+    let process = unsafe { GetCurrentProcess() };
+    let elevation = token_info::<TOKEN_ELEVATION>(process, TokenElevation);
+    let ui_access = token_info::<u32>(process, TokenUIAccess);
+    CaptureLimitations {
+        elevated: elevation.is_some_and(|elevation| elevation.TokenIsElevated != 0),
+        ui_access: ui_access.is_some_and(|ui_access| ui_access != 0)
+    }
+}
+
+fn token_info<T: Default>(process: HANDLE, class: TOKEN_INFORMATION_CLASS) -> Option<T> {
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(process, TOKEN_QUERY, &mut token).ok()?;
+        let mut info = T::default();
+        let mut length = 0;
+        let result = GetTokenInformation(
+            token, class, Some((&mut info as *mut T).cast::<c_void>()), size_of::<T>() as u32, &mut length
+        );
+        let _ = CloseHandle(token);
+        result.ok().map(|_| info)
+    }
+}
+
+// whether a window belongs to an elevated process. The tokens of elevated processes can't be opened without
+// being elevated, which counts as elevated as well
+#[cfg(feature = "tokio")]
+fn is_elevated_window(window: WindowHandle) -> bool {
+    unsafe {
+        let Ok(process) = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, window.process_id()) else {
+            return true;
+        };
+        let elevated = token_info::<TOKEN_ELEVATION>(process, TokenElevation)
+            .is_none_or(|elevation| elevation.TokenIsElevated != 0);
+        let _ = CloseHandle(process);
+        elevated
+    }
+}
+
+
+
+// watches the foreground window of a listener whose process misses elevated windows, the process is only
+// checked when the window changes
+#[cfg(feature = "tokio")]
+#[derive(Default)]
+pub(crate) struct ElevationWatcher {
+    window: Option<WindowHandle>
+}
+
+#[cfg(feature = "tokio")]
+impl ElevationWatcher {
+    // None when this process sees every window's input anyway
+    pub(crate) fn new() -> Option<Self> {
+        capture_limitations().misses_elevated_windows().then(ElevationWatcher::default)
+    }
+
+    // true the first round an elevated window has the focus
+    pub(crate) fn poll(&mut self) -> bool {
+        let Some(current) = WindowHandle::foreground() else {
+            return false;
+        };
+        if self.window == Some(current) {
+            return false;
+        }
+        self.window = Some(current);
+        is_elevated_window(current)
+    }
+}
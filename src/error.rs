@@ -0,0 +1,119 @@
+/*
+
+Listener errors
+
+Every listener constructor validates its settings up front and reports problems here, instead of spawning a
+listener that silently never emits anything.
+
+*/
+
+
+use std::fmt;
+use std::time::Duration;
+
+
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum KeyListenerError {
+    // virtual key codes range from 0x01 to 0xFE
+    InvalidVkCode(i32),
+    EmptyKeyList,
+    ZeroPollingInterval,
+    // priority_keys was given other_keys_every 0, which would never poll the other keys
+    ZeroScanInterval,
+    // a bounded event channel needs room for at least one event
+    ZeroCapacity,
+    // installing a low level hook failed
+    HookRegistration(windows::core::Error),
+    // registering for raw keyboard input failed
+    RawInputRegistration(windows::core::Error),
+    // creating the hidden window that hook and raw input listeners receive messages with failed
+    MessageWindow(windows::core::Error),
+    AlreadyRunning,
+    // a hook of the same kind already runs on this thread, see MessageLoop::CurrentThread
+    ThreadInUse,
+    // the listener's polling task ended (e.g. panicked) before the awaited event came
+    ListenerStopped
+}
+
+impl fmt::Display for KeyListenerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyListenerError::InvalidVkCode(vk_code) => write!(f, "invalid virtual key code 0x{:02X}", vk_code),
+            KeyListenerError::EmptyKeyList => write!(f, "no keys to listen to"),
+            KeyListenerError::ZeroPollingInterval => write!(f, "the polling interval is zero"),
+            KeyListenerError::ZeroScanInterval => write!(f, "the other keys are polled every 0 rounds"),
+            KeyListenerError::ZeroCapacity => write!(f, "the bounded event channel has a capacity of 0"),
+            KeyListenerError::HookRegistration(error) => write!(f, "failed to install the hook: {}", error),
+            KeyListenerError::RawInputRegistration(error) => write!(f, "failed to register for raw input: {}", error),
+            KeyListenerError::MessageWindow(error) => write!(f, "failed to create the message window: {}", error),
+            KeyListenerError::AlreadyRunning => write!(f, "the listener is already running"),
+            KeyListenerError::ThreadInUse => write!(f, "a hook of this kind already runs on this thread"),
+            KeyListenerError::ListenerStopped => write!(f, "the listener stopped unexpectedly")
+        }
+    }
+}
+
+impl std::error::Error for KeyListenerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KeyListenerError::HookRegistration(error)
+            | KeyListenerError::RawInputRegistration(error)
+            | KeyListenerError::MessageWindow(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+fn is_valid_vk_code(vk_code: i32) -> bool {
+    (0x01..=0xFE).contains(&vk_code)
+}
+
+// a key listed twice would be polled twice, and the second poll of a round never sees a change
+pub(crate) fn dedup_vk_codes(vk_codes: Vec<i32>) -> Vec<i32> {
+    let mut deduped = Vec::with_capacity(vk_codes.len());
+    for vk_code in vk_codes {
+        if !deduped.contains(&vk_code) {
+            deduped.push(vk_code);
+        }
+    }
+    deduped
+}
+
+// rejects out of range codes, or drops them with skip_invalid, and removes duplicates
+pub(crate) fn checked_vk_codes(vk_codes: Vec<i32>, skip_invalid: bool) -> Result<Vec<i32>, KeyListenerError> {
+    let vk_codes = match vk_codes.iter().find(|vk_code| !is_valid_vk_code(**vk_code)) {
+        Some(&vk_code) if !skip_invalid => return Err(KeyListenerError::InvalidVkCode(vk_code)),
+        Some(_) => vk_codes.into_iter().filter(|vk_code| is_valid_vk_code(*vk_code)).collect(),
+        None => vk_codes
+    };
+    if vk_codes.is_empty() {
+        return Err(KeyListenerError::EmptyKeyList);
+    }
+    Ok(dedup_vk_codes(vk_codes))
+}
+
+// listeners keep their interval in whole microseconds, anything shorter would poll without ever waiting
+pub(crate) fn validate_polling_interval(polling_interval: Duration) -> Result<(), KeyListenerError> {
+    if polling_interval.as_micros() == 0 {
+        return Err(KeyListenerError::ZeroPollingInterval);
+    }
+    Ok(())
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_below_a_microsecond_are_zero() {
+        for polling_interval in [Duration::ZERO, Duration::from_nanos(1), Duration::from_nanos(999)] {
+            assert!(matches!(validate_polling_interval(polling_interval), Err(KeyListenerError::ZeroPollingInterval)));
+        }
+        assert!(validate_polling_interval(Duration::from_micros(1)).is_ok());
+        assert!(validate_polling_interval(Duration::from_millis(10)).is_ok());
+    }
+}
@@ -0,0 +1,156 @@
+/*
+
+C compatible interface, enabled with the "ffi" feature
+
+Wraps the thread backend so C, C++ or C# applications can use the crate through the cdylib built by the
+win-key-event-ffi crate (ffi/) and include/win_key_event.h. Callbacks are called on the listener's dispatch thread
+with the key code and the user_data pointer given when starting the listener. Stopping a listener waits for that
+thread, so no callback runs once wke_listener_stop returned and user_data can be freed.
+
+*/
+
+
+use crate::KeyEvent;
+use crate::callbacks::Callbacks;
+use crate::error::KeyListenerError;
+use crate::thread_listener::{ThreadKeyListener, spawn_listener};
+
+use std::ffi::c_void;
+use std::sync::mpsc::channel;
+use std::thread::{self, JoinHandle};
+
+
+
+pub const WKE_OK: i32 = 0;
+pub const WKE_INVALID_VK_CODE: i32 = 1;
+pub const WKE_EMPTY_KEY_LIST: i32 = 2;
+pub const WKE_ZERO_POLLING_INTERVAL: i32 = 3;
+pub const WKE_NULL_POINTER: i32 = 4;
+pub const WKE_ERROR: i32 = -1;
+
+pub type WkeCallback = Option<extern "C" fn(vk_code: i32, user_data: *mut c_void)>;
+
+// opaque to C
+pub struct WkeListener {
+    key_listener: ThreadKeyListener,
+    dispatch_thread: JoinHandle<()>
+}
+
+// the caller promises user_data may be used from the dispatch thread
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+
+impl UserData {
+    // closures capture the whole UserData through this, not just the raw pointer field
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+
+
+/// Starts polling `vk_codes_len` key codes read from `vk_codes` every `polling_wait` ms and stores the listener in
+/// `listener_out`. Either callback may be null.
+///
+/// # Safety
+/// `vk_codes` must point to `vk_codes_len` readable key codes and `listener_out` must be writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wke_listener_start(
+    vk_codes: *const i32,
+    vk_codes_len: usize,
+    polling_wait: u64,
+    on_press: WkeCallback,
+    on_release: WkeCallback,
+    user_data: *mut c_void,
+    listener_out: *mut *mut WkeListener
+) -> i32 {
+    if vk_codes.is_null() || listener_out.is_null() {
+        return WKE_NULL_POINTER;
+    }
+    let vk_codes = unsafe { std::slice::from_raw_parts(vk_codes, vk_codes_len) }.to_vec();
+
+    let press_data = UserData(user_data);
+    let release_data = UserData(user_data);
+    let key_down_callback = move |key_event: KeyEvent| {
+        if let (Some(on_press), Some(vk)) = (on_press, key_event.vk()) {
+            on_press(vk.code(), press_data.get());
+        }
+    };
+    let key_up_callback = move |key_event: KeyEvent| {
+        if let (Some(on_release), Some(vk)) = (on_release, key_event.vk()) {
+            on_release(vk.code(), release_data.get());
+        }
+    };
+
+    let (sender, receiver) = channel();
+    let key_listener = match spawn_listener(vk_codes, polling_wait, move |key_event| sender.send(key_event).is_ok()) {
+        Ok(key_listener) => key_listener,
+        Err(error) => return status_code(&error)
+    };
+    let mut callbacks = Callbacks::new(key_down_callback, key_up_callback);
+    // ends once the polling thread quits and drops its sender
+    let dispatch_thread = thread::spawn(move || {
+        for key_event in receiver {
+            callbacks.call(key_event);
+        }
+    });
+    unsafe { *listener_out = Box::into_raw(Box::new(WkeListener { key_listener, dispatch_thread })) };
+    WKE_OK
+}
+
+/// Stops the listener and frees it. Waits for a callback that is already running (and up to one polling interval
+/// for the polling thread to notice), so no callback runs after this returns. When called from a callback it
+/// returns without waiting.
+///
+/// # Safety
+/// `listener` must come from `wke_listener_start` and not have been stopped yet, or be null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wke_listener_stop(listener: *mut WkeListener) {
+    if listener.is_null() {
+        return;
+    }
+    let listener = unsafe { Box::from_raw(listener) };
+    listener.key_listener.quit();
+    // joining its own thread would never return
+    if listener.dispatch_thread.thread().id() != thread::current().id() {
+        let _ = listener.dispatch_thread.join();
+    }
+}
+
+/// Whether a watched key was down at the last poll.
+///
+/// # Safety
+/// `listener` must be a running listener from `wke_listener_start`, or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wke_listener_is_pressed(listener: *const WkeListener, vk_code: i32) -> bool {
+    match unsafe { listener.as_ref() } {
+        Some(listener) => listener.key_listener.is_pressed(vk_code),
+        None => false
+    }
+}
+
+/// Takes effect from the next round of polling. Returns `WKE_ZERO_POLLING_INTERVAL` for 0, which leaves the
+/// polling wait as it was.
+///
+/// # Safety
+/// `listener` must be a running listener from `wke_listener_start`, or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn wke_listener_set_polling_wait(listener: *const WkeListener, polling_wait: u64) -> i32 {
+    let Some(listener) = (unsafe { listener.as_ref() }) else {
+        return WKE_NULL_POINTER;
+    };
+    match listener.key_listener.set_polling_wait(polling_wait) {
+        Ok(()) => WKE_OK,
+        Err(error) => status_code(&error)
+    }
+}
+
+fn status_code(error: &KeyListenerError) -> i32 {
+    match error {
+        KeyListenerError::InvalidVkCode(_) => WKE_INVALID_VK_CODE,
+        KeyListenerError::EmptyKeyList => WKE_EMPTY_KEY_LIST,
+        KeyListenerError::ZeroPollingInterval => WKE_ZERO_POLLING_INTERVAL,
+        _ => WKE_ERROR
+    }
+}
@@ -0,0 +1,130 @@
+/*
+
+XInput gamepad state
+
+Controllers are polled with XInputGetState like keys are with GetAsyncKeyState. Buttons become press / release
+events, triggers and sticks report their new value whenever it leaves the dead zone or changes outside of it.
+
+https://learn.microsoft.com/en-us/windows/win32/xinput/getting-started-with-xinput
+
+*/
+
+
+#[cfg(feature = "tokio")]
+use crate::KeyEvent;
+
+#[cfg(feature = "tokio")]
+use windows::Win32::Foundation::ERROR_SUCCESS;
+#[cfg(feature = "tokio")]
+use windows::Win32::UI::Input::XboxController::{
+    XInputGetState, XINPUT_GAMEPAD, XINPUT_STATE, XUSER_MAX_COUNT,
+    XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE, XINPUT_GAMEPAD_RIGHT_THUMB_DEADZONE, XINPUT_GAMEPAD_TRIGGER_THRESHOLD
+};
+
+
+
+// one of the XINPUT_GAMEPAD_* button flags
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GamepadButton(pub u16);
+
+impl GamepadButton {
+    pub const DPAD_UP: GamepadButton = GamepadButton(0x0001);
+    pub const DPAD_DOWN: GamepadButton = GamepadButton(0x0002);
+    pub const DPAD_LEFT: GamepadButton = GamepadButton(0x0004);
+    pub const DPAD_RIGHT: GamepadButton = GamepadButton(0x0008);
+    pub const START: GamepadButton = GamepadButton(0x0010);
+    pub const BACK: GamepadButton = GamepadButton(0x0020);
+    pub const LEFT_THUMB: GamepadButton = GamepadButton(0x0040);
+    pub const RIGHT_THUMB: GamepadButton = GamepadButton(0x0080);
+    pub const LEFT_SHOULDER: GamepadButton = GamepadButton(0x0100);
+    pub const RIGHT_SHOULDER: GamepadButton = GamepadButton(0x0200);
+    pub const A: GamepadButton = GamepadButton(0x1000);
+    pub const B: GamepadButton = GamepadButton(0x2000);
+    pub const X: GamepadButton = GamepadButton(0x4000);
+    pub const Y: GamepadButton = GamepadButton(0x8000);
+}
+
+pub const GAMEPAD_BUTTONS: [GamepadButton; 14] = [
+    GamepadButton::DPAD_UP, GamepadButton::DPAD_DOWN, GamepadButton::DPAD_LEFT, GamepadButton::DPAD_RIGHT,
+    GamepadButton::START, GamepadButton::BACK, GamepadButton::LEFT_THUMB, GamepadButton::RIGHT_THUMB,
+    GamepadButton::LEFT_SHOULDER, GamepadButton::RIGHT_SHOULDER,
+    GamepadButton::A, GamepadButton::B, GamepadButton::X, GamepadButton::Y
+];
+
+// triggers range from 0 to 255, stick axes from -32768 to 32767 (up and right are positive)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftTrigger,
+    RightTrigger,
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY
+}
+
+
+
+// axis values with the dead zones applied, anything inside a dead zone reads as 0
+#[cfg(feature = "tokio")]
+fn axis_values(gamepad: &XINPUT_GAMEPAD) -> [(GamepadAxis, i16); 6] {
+    let trigger = |value: u8| if value as u16 > XINPUT_GAMEPAD_TRIGGER_THRESHOLD.0 { value as i16 } else { 0 };
+    // the dead zone is round, so both axes of a stick are zeroed together
+    let stick = |x: i16, y: i16, dead_zone: u16| {
+        let distance = ((x as f64).powi(2) + (y as f64).powi(2)).sqrt();
+        if distance > dead_zone as f64 { (x, y) } else { (0, 0) }
+    };
+    let (left_x, left_y) = stick(gamepad.sThumbLX, gamepad.sThumbLY, XINPUT_GAMEPAD_LEFT_THUMB_DEADZONE.0);
+    let (right_x, right_y) = stick(gamepad.sThumbRX, gamepad.sThumbRY, XINPUT_GAMEPAD_RIGHT_THUMB_DEADZONE.0);
+
+    [
+        (GamepadAxis::LeftTrigger, trigger(gamepad.bLeftTrigger)),
+        (GamepadAxis::RightTrigger, trigger(gamepad.bRightTrigger)),
+        (GamepadAxis::LeftStickX, left_x),
+        (GamepadAxis::LeftStickY, left_y),
+        (GamepadAxis::RightStickX, right_x),
+        (GamepadAxis::RightStickY, right_y)
+    ]
+}
+
+// last seen state of each of the (up to 4) controllers, None while disconnected
+#[cfg(feature = "tokio")]
+#[derive(Default)]
+pub(crate) struct GamepadStates {
+    pads: [Option<XINPUT_GAMEPAD>; XUSER_MAX_COUNT as usize]
+}
+
+#[cfg(feature = "tokio")]
+impl GamepadStates {
+    pub(crate) fn poll(&mut self, mut emit: impl FnMut(KeyEvent)) {
+        for (index, last) in self.pads.iter_mut().enumerate() {
+            let pad = index as u8;
+            let mut state = XINPUT_STATE::default();
+            let connected = unsafe { XInputGetState(index as u32, &mut state) } == ERROR_SUCCESS.0;
+
+            let current = connected.then_some(state.Gamepad);
+            if last.is_some() != current.is_some() {
+                emit(KeyEvent::GamepadConnected { pad, connected });
+            }
+            // a disconnecting pad releases everything it held
+            let previous = last.unwrap_or_default();
+            let gamepad = current.unwrap_or_default();
+
+            for button in GAMEPAD_BUTTONS {
+                let was_pressed = previous.wButtons.0 & button.0 != 0;
+                let is_pressed = gamepad.wButtons.0 & button.0 != 0;
+                if is_pressed && !was_pressed {
+                    emit(KeyEvent::GamepadPress { pad, button });
+                } else if was_pressed && !is_pressed {
+                    emit(KeyEvent::GamepadRelease { pad, button });
+                }
+            }
+            for ((axis, value), (_, previous_value)) in axis_values(&gamepad).into_iter().zip(axis_values(&previous)) {
+                if value != previous_value {
+                    emit(KeyEvent::GamepadAxis { pad, axis, value });
+                }
+            }
+
+            *last = current;
+        }
+    }
+}
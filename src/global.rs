@@ -0,0 +1,44 @@
+/*
+
+Process-wide default listener
+
+A hub created on first use, for small utilities that would rather not pass an Arc<Mutex<KeyListener>> around.
+It is spawned on the tokio runtime of that first call and polls every key code.
+
+*/
+
+
+use crate::VirtualKey;
+use crate::builder::KeyListenerBuilder;
+use crate::error::KeyListenerError;
+use crate::hub::{InputHub, HubRegistration};
+
+use std::sync::OnceLock;
+
+
+
+static GLOBAL_LISTENER: OnceLock<InputHub> = OnceLock::new();
+
+// has to be called from within a tokio runtime the first time
+pub fn global_listener() -> &'static InputHub {
+    GLOBAL_LISTENER.get_or_init(|| {
+        InputHub::with_builder(KeyListenerBuilder::new().vk_codes(0x01..=0xFE))
+            .expect("every key code from 0x01 to 0xFE is valid")
+    })
+}
+
+// calls callback each time vk is pressed
+pub fn on_press<F>(vk: impl Into<VirtualKey>, mut callback: F) -> Result<HubRegistration, KeyListenerError>
+where
+    F: FnMut() + Send + 'static
+{
+    global_listener().register(vec![vk.into().code()], move |_| callback(), |_| {})
+}
+
+// calls callback each time vk is released
+pub fn on_release<F>(vk: impl Into<VirtualKey>, mut callback: F) -> Result<HubRegistration, KeyListenerError>
+where
+    F: FnMut() + Send + 'static
+{
+    global_listener().register(vec![vk.into().code()], |_| {}, move |_| callback())
+}
@@ -0,0 +1,108 @@
+/*
+
+USB HID usage mapping
+
+Keyboards report keys as HID usages, a page and an id on it. Most keys are on the Keyboard / Keypad page, the
+media and browser keys on the Consumer page. Usage ids name positions on a US keyboard, like scan codes do, so
+character keys map by position rather than by the character they type.
+
+https://usb.org/document-library/hid-usage-tables-16
+
+*/
+
+
+use crate::{UnmappedKey, VirtualKey};
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HidUsage {
+    pub page: u16,
+    pub id: u16
+}
+
+impl HidUsage {
+    pub const KEYBOARD_PAGE: u16 = 0x07;
+    pub const CONSUMER_PAGE: u16 = 0x0C;
+
+    pub fn keyboard(id: u16) -> Self {
+        HidUsage { page: HidUsage::KEYBOARD_PAGE, id }
+    }
+
+    pub fn consumer(id: u16) -> Self {
+        HidUsage { page: HidUsage::CONSUMER_PAGE, id }
+    }
+}
+
+
+
+// (virtual key code, page, id) for keys outside of the consecutive letter / digit / function key ranges.
+// Keypad enter and the sided modifiers come before enter's and the generic modifiers' second usage, so each
+// usage maps back to one key
+const USAGES: [(i32, u16, u16); 67] = [
+    (0x0D, HidUsage::KEYBOARD_PAGE, 0x28), (0x0D, HidUsage::KEYBOARD_PAGE, 0x58), (0x1B, HidUsage::KEYBOARD_PAGE, 0x29),
+    (0x08, HidUsage::KEYBOARD_PAGE, 0x2A), (0x09, HidUsage::KEYBOARD_PAGE, 0x2B), (0x20, HidUsage::KEYBOARD_PAGE, 0x2C),
+    (0xBD, HidUsage::KEYBOARD_PAGE, 0x2D), (0xBB, HidUsage::KEYBOARD_PAGE, 0x2E), (0xDB, HidUsage::KEYBOARD_PAGE, 0x2F),
+    (0xDD, HidUsage::KEYBOARD_PAGE, 0x30), (0xDC, HidUsage::KEYBOARD_PAGE, 0x31), (0xBA, HidUsage::KEYBOARD_PAGE, 0x33),
+    (0xDE, HidUsage::KEYBOARD_PAGE, 0x34), (0xC0, HidUsage::KEYBOARD_PAGE, 0x35), (0xBC, HidUsage::KEYBOARD_PAGE, 0x36),
+    (0xBE, HidUsage::KEYBOARD_PAGE, 0x37), (0xBF, HidUsage::KEYBOARD_PAGE, 0x38), (0x14, HidUsage::KEYBOARD_PAGE, 0x39),
+    (0x2C, HidUsage::KEYBOARD_PAGE, 0x46), (0x91, HidUsage::KEYBOARD_PAGE, 0x47), (0x13, HidUsage::KEYBOARD_PAGE, 0x48),
+    (0x2D, HidUsage::KEYBOARD_PAGE, 0x49), (0x24, HidUsage::KEYBOARD_PAGE, 0x4A), (0x21, HidUsage::KEYBOARD_PAGE, 0x4B),
+    (0x2E, HidUsage::KEYBOARD_PAGE, 0x4C), (0x23, HidUsage::KEYBOARD_PAGE, 0x4D), (0x22, HidUsage::KEYBOARD_PAGE, 0x4E),
+    (0x27, HidUsage::KEYBOARD_PAGE, 0x4F), (0x25, HidUsage::KEYBOARD_PAGE, 0x50), (0x28, HidUsage::KEYBOARD_PAGE, 0x51),
+    (0x26, HidUsage::KEYBOARD_PAGE, 0x52), (0x90, HidUsage::KEYBOARD_PAGE, 0x53), (0x6F, HidUsage::KEYBOARD_PAGE, 0x54),
+    (0x6A, HidUsage::KEYBOARD_PAGE, 0x55), (0x6D, HidUsage::KEYBOARD_PAGE, 0x56), (0x6B, HidUsage::KEYBOARD_PAGE, 0x57),
+    (0x60, HidUsage::KEYBOARD_PAGE, 0x62), (0x6E, HidUsage::KEYBOARD_PAGE, 0x63), (0xE2, HidUsage::KEYBOARD_PAGE, 0x64),
+    (0x5D, HidUsage::KEYBOARD_PAGE, 0x65), (0xA2, HidUsage::KEYBOARD_PAGE, 0xE0), (0xA0, HidUsage::KEYBOARD_PAGE, 0xE1),
+    (0xA4, HidUsage::KEYBOARD_PAGE, 0xE2), (0x5B, HidUsage::KEYBOARD_PAGE, 0xE3), (0xA3, HidUsage::KEYBOARD_PAGE, 0xE4),
+    (0xA1, HidUsage::KEYBOARD_PAGE, 0xE5), (0xA5, HidUsage::KEYBOARD_PAGE, 0xE6), (0x5C, HidUsage::KEYBOARD_PAGE, 0xE7),
+    (0x10, HidUsage::KEYBOARD_PAGE, 0xE1), (0x11, HidUsage::KEYBOARD_PAGE, 0xE0), (0x12, HidUsage::KEYBOARD_PAGE, 0xE2),
+    (0xAD, HidUsage::CONSUMER_PAGE, 0x0E2), (0xAE, HidUsage::CONSUMER_PAGE, 0x0EA), (0xAF, HidUsage::CONSUMER_PAGE, 0x0E9),
+    (0xB0, HidUsage::CONSUMER_PAGE, 0x0B5), (0xB1, HidUsage::CONSUMER_PAGE, 0x0B6), (0xB2, HidUsage::CONSUMER_PAGE, 0x0B7),
+    (0xB3, HidUsage::CONSUMER_PAGE, 0x0CD), (0xA6, HidUsage::CONSUMER_PAGE, 0x224), (0xA7, HidUsage::CONSUMER_PAGE, 0x225),
+    (0xA8, HidUsage::CONSUMER_PAGE, 0x227), (0xA9, HidUsage::CONSUMER_PAGE, 0x226), (0xAA, HidUsage::CONSUMER_PAGE, 0x221),
+    (0xAB, HidUsage::CONSUMER_PAGE, 0x22A), (0xAC, HidUsage::CONSUMER_PAGE, 0x223), (0xB4, HidUsage::CONSUMER_PAGE, 0x18A),
+    (0xB5, HidUsage::CONSUMER_PAGE, 0x183)
+];
+
+impl VirtualKey {
+    // the usage a keyboard reports for this key, generic modifier codes map to the left key's usage
+    pub fn hid_usage(&self) -> Option<HidUsage> {
+        let vk_code = self.0;
+        let id = match vk_code {
+            0x41..=0x5A => 0x04 + vk_code - 0x41, // a - z
+            0x31..=0x39 => 0x1E + vk_code - 0x31, // 1 - 9
+            0x30 => 0x27,
+            0x61..=0x69 => 0x59 + vk_code - 0x61, // keypad 1 - 9
+            0x70..=0x7B => 0x3A + vk_code - 0x70, // f1 - f12
+            0x7C..=0x87 => 0x68 + vk_code - 0x7C, // f13 - f24
+            _ => {
+                return USAGES.iter()
+                    .find(|(other, _, _)| *other == vk_code)
+                    .map(|(_, page, id)| HidUsage { page: *page, id: *id });
+            }
+        };
+        Some(HidUsage::keyboard(id as u16))
+    }
+}
+
+impl TryFrom<HidUsage> for VirtualKey {
+    type Error = UnmappedKey;
+
+    fn try_from(usage: HidUsage) -> Result<Self, Self::Error> {
+        let id = usage.id as i32;
+        let vk_code = match (usage.page, id) {
+            (HidUsage::KEYBOARD_PAGE, 0x04..=0x1D) => 0x41 + id - 0x04,
+            (HidUsage::KEYBOARD_PAGE, 0x1E..=0x26) => 0x31 + id - 0x1E,
+            (HidUsage::KEYBOARD_PAGE, 0x27) => 0x30,
+            (HidUsage::KEYBOARD_PAGE, 0x59..=0x61) => 0x61 + id - 0x59,
+            (HidUsage::KEYBOARD_PAGE, 0x3A..=0x45) => 0x70 + id - 0x3A,
+            (HidUsage::KEYBOARD_PAGE, 0x68..=0x73) => 0x7C + id - 0x68,
+            _ => USAGES.iter()
+                .find(|(_, page, other)| *page == usage.page && *other == usage.id)
+                .map(|(vk_code, _, _)| *vk_code)
+                .ok_or(UnmappedKey)?
+        };
+        Ok(VirtualKey(vk_code))
+    }
+}
@@ -0,0 +1,46 @@
+/*
+
+Recent event history
+
+A ring buffer of the last events a listener sent, for crash reporters or "what did I just press?" overlays that
+want to look back without taking events away from callbacks and subscribers.
+
+*/
+
+
+use crate::KeyEvent;
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Instant;
+
+
+
+pub(crate) struct History {
+    events: Mutex<VecDeque<(Instant, KeyEvent)>>,
+    capacity: usize
+}
+
+impl History {
+    pub(crate) fn new(capacity: usize) -> Self {
+        History { events: Mutex::new(VecDeque::with_capacity(capacity)), capacity }
+    }
+
+    // at polled_at, when the round that saw the event started
+    pub(crate) fn push(&self, key_event: KeyEvent, polled_at: Instant) {
+        let mut events = self.events.lock().unwrap();
+        events.push_back((polled_at, key_event));
+        if events.len() > self.capacity {
+            events.pop_front();
+        }
+    }
+
+    // oldest first
+    pub(crate) fn snapshot(&self) -> Vec<(Instant, KeyEvent)> {
+        self.events.lock().unwrap().iter().copied().collect()
+    }
+
+    pub(crate) fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+}
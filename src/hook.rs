@@ -0,0 +1,194 @@
+/*
+
+Low level hook thread
+
+Low level hooks are called on the thread that installed them, and only while that thread pumps messages, so each
+hook gets a message window (see message_loop), on a thread of its own or on the application's. Hook procedures are
+plain functions, so they reach their handler through a thread local set up on that thread before the hook is
+installed, which allows one hook of each kind per thread: a second one on the application's thread is refused
+rather than taking over the first one's handler.
+
+Windows silently removes a low level hook whose procedure does not return within LowLevelHooksTimeout. There is no
+notification, but the events that timed out still reach the procedure late: several calls within one check for events
+older than the default timeout count as a sign the hook may be gone, and a timer on the hook's window then installs
+it again. A single late call can be a stalled machine rather than a removed hook. Injected events are left out, their
+time stamps are whatever the injecting program set.
+
+https://learn.microsoft.com/en-us/windows/win32/winmsg/about-hooks
+https://learn.microsoft.com/en-us/windows/win32/winmsg/lowlevelkeyboardproc
+
+*/
+
+
+use crate::error::KeyListenerError;
+use crate::message_loop::{MessageLoop, MessageWindow, WindowHandlers, open_message_window};
+
+use windows::Win32::Foundation::{HINSTANCE, LPARAM, LRESULT, WPARAM};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::System::SystemInformation::GetTickCount;
+use windows::Win32::UI::WindowsAndMessaging::{KillTimer, SetTimer, SetWindowsHookExW, UnhookWindowsHookEx, HHOOK, WINDOWS_HOOK_ID, WM_TIMER};
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+
+
+// the default LowLevelHooksTimeout, a call this late means the event already timed out
+const LATE_CALL_MS: u32 = 300;
+// how often the hook's window checks for late calls
+const WATCHDOG_INTERVAL_MS: u32 = 1000;
+// late calls within one check that make the hook be installed again
+const LATE_CALLS_TO_REINSTALL: u32 = 2;
+
+thread_local! {
+    // the procedures of the hooks installed on this thread
+    static THREAD_HOOKS: RefCell<HashSet<usize>> = RefCell::new(HashSet::new());
+    // event time of the first late call since the last check
+    static LATE_CALL: Cell<Option<u32>> = const { Cell::new(None) };
+    // late calls since the last check
+    static LATE_CALLS: Cell<u32> = const { Cell::new(0) };
+}
+
+// called by hook procedures with each event's time stamp (KBDLLHOOKSTRUCT::time, ...) and whether it was injected
+// (LLKHF_INJECTED, LLMHF_INJECTED)
+pub(crate) fn note_event_time(event_time: u32, injected: bool) {
+    if injected {
+        return;
+    }
+    let delay = unsafe { GetTickCount() }.wrapping_sub(event_time);
+    // time stamps a little ahead of GetTickCount wrap around to huge delays
+    if delay > LATE_CALL_MS && delay < u32::MAX / 2 {
+        LATE_CALLS.set(LATE_CALLS.get() + 1);
+        if LATE_CALL.get().is_none() {
+            LATE_CALL.set(Some(event_time));
+        }
+    }
+}
+
+// the first late call's event time if there were enough of them since the last check, starts the next check
+fn take_late_calls() -> Option<u32> {
+    let event_time = LATE_CALL.take();
+    (LATE_CALLS.replace(0) >= LATE_CALLS_TO_REINSTALL).then_some(event_time).flatten()
+}
+
+// how often a hook had to be installed again, shared with the listener owning the hook thread
+#[derive(Default)]
+pub(crate) struct HookHealth {
+    reinstalls: AtomicU32,
+    // in milliseconds
+    last_gap: AtomicU64
+}
+
+impl HookHealth {
+    pub(crate) fn reinstalls(&self) -> u32 {
+        self.reinstalls.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn last_gap(&self) -> Option<Duration> {
+        (self.reinstalls() > 0).then(|| Duration::from_millis(self.last_gap.load(Ordering::Relaxed)))
+    }
+}
+
+
+
+pub(crate) type HookProc = unsafe extern "system" fn(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT;
+
+pub(crate) struct HookThread {
+    window: MessageWindow,
+    health: Arc<HookHealth>
+}
+
+impl HookThread {
+    // times the hook was installed again after Windows may have removed it
+    pub(crate) fn reinstalls(&self) -> u32 {
+        self.health.reinstalls()
+    }
+
+    // from the late event that gave the removal away to the hook being back, None before the first reinstall
+    pub(crate) fn last_gap(&self) -> Option<Duration> {
+        self.health.last_gap()
+    }
+
+    // removes the hook, and ends its thread unless it runs on the application's
+    pub(crate) fn stop(&self) {
+        self.window.close();
+    }
+}
+
+
+
+// setup runs on the hook's thread before the hook is installed, teardown after it is removed. Returns once the hook
+// is in place (or failed to be)
+pub(crate) fn spawn_hook_thread(
+    hook_id: WINDOWS_HOOK_ID,
+    hook_proc: HookProc,
+    message_loop: MessageLoop,
+    setup: impl FnOnce() + Send + 'static,
+    teardown: fn()
+) -> Result<HookThread, KeyListenerError> {
+    // a dedicated thread is new, only the calling thread can have one already
+    if message_loop == MessageLoop::CurrentThread && THREAD_HOOKS.with_borrow(|hooks| hooks.contains(&(hook_proc as usize))) {
+        return Err(KeyListenerError::ThreadInUse);
+    }
+    let health = Arc::new(HookHealth::default());
+
+    let thread_health = health.clone();
+    let window = open_message_window(message_loop, move |window| {
+        setup();
+        let hook = match install_hook(hook_id, hook_proc) {
+            Ok(hook) => Rc::new(Cell::new(hook)),
+            Err(error) => {
+                teardown();
+                return Err(KeyListenerError::HookRegistration(error));
+            }
+        };
+        THREAD_HOOKS.with_borrow_mut(|hooks| hooks.insert(hook_proc as usize));
+        // the timer's WM_TIMER goes to the window, so any message loop on the thread drives it
+        let watchdog = unsafe { SetTimer(Some(window), 0, WATCHDOG_INTERVAL_MS, None) };
+
+        let installed = hook.clone();
+        let on_message = move |_window, msg: u32, wparam: WPARAM, _lparam| {
+            if msg == WM_TIMER && wparam.0 == watchdog
+                && let Some(event_time) = take_late_calls()
+            {
+                reinstall_hook(&installed, hook_id, hook_proc, event_time, &thread_health);
+            }
+        };
+        let on_close = move || unsafe {
+            let _ = KillTimer(Some(window), watchdog);
+            let _ = UnhookWindowsHookEx(hook.get());
+            THREAD_HOOKS.with_borrow_mut(|hooks| hooks.remove(&(hook_proc as usize)));
+            teardown();
+        };
+        Ok(WindowHandlers { on_message: Some(Box::new(on_message)), on_close: Some(Box::new(on_close)) })
+    })?;
+    Ok(HookThread { window, health })
+}
+
+fn install_hook(hook_id: WINDOWS_HOOK_ID, hook_proc: HookProc) -> windows::core::Result<HHOOK> {
+    unsafe {
+        let module = GetModuleHandleW(None).map(|module| HINSTANCE(module.0)).ok();
+        SetWindowsHookExW(hook_id, Some(hook_proc), module, 0)
+    }
+}
+
+// the new hook goes in before the old one is removed, which fails harmlessly if Windows already removed it
+fn reinstall_hook(hook: &Cell<HHOOK>, hook_id: WINDOWS_HOOK_ID, hook_proc: HookProc, event_time: u32, health: &HookHealth) {
+    let Ok(new_hook) = install_hook(hook_id, hook_proc) else {
+        // the old one is kept, the next late calls try again
+        return;
+    };
+    unsafe {
+        let _ = UnhookWindowsHookEx(hook.replace(new_hook));
+    }
+
+    let gap = unsafe { GetTickCount() }.wrapping_sub(event_time);
+    health.reinstalls.fetch_add(1, Ordering::Relaxed);
+    health.last_gap.store(gap as u64, Ordering::Relaxed);
+    #[cfg(feature = "tracing")]
+    tracing::warn!(gap_ms = gap, "low level hook timed out and was installed again");
+}
@@ -0,0 +1,255 @@
+/*
+
+Hotkey parsing and matching
+
+A hotkey is written as modifiers and a single key joined by '+', e.g. "Ctrl+Shift+S", "Alt+F4" or "Win+E".
+Modifier and key names are case insensitive, keys without a name are written as their code (e.g. "Ctrl+0xBA").
+Windows reserves many Win chords (Win+L, Win+D, ...) for itself, they are still reported but also act as usual.
+With the serde feature hotkeys are (de)serialized as these strings.
+
+*/
+
+
+use crate::VirtualKey;
+
+use std::fmt;
+use std::str::FromStr;
+use std::collections::HashSet;
+
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub win: bool
+}
+
+impl Modifiers {
+    // generic modifier key codes, these are down when either the left or right key is down. The Win keys have
+    // no generic code
+    pub(crate) const VK_CODES: [i32; 5] = [
+        VirtualKey::CONTROL.0, VirtualKey::SHIFT.0, VirtualKey::MENU.0, VirtualKey::LWIN.0, VirtualKey::RWIN.0
+    ];
+
+    pub fn from_pressed(pressed: &HashSet<VirtualKey>) -> Self {
+        Modifiers {
+            ctrl: pressed.contains(&VirtualKey::CONTROL),
+            shift: pressed.contains(&VirtualKey::SHIFT),
+            alt: pressed.contains(&VirtualKey::MENU),
+            win: pressed.contains(&VirtualKey::LWIN) || pressed.contains(&VirtualKey::RWIN)
+        }
+    }
+
+    // like from_pressed, counting the left / right modifier codes as well as the generic ones
+    pub(crate) fn from_any_side(pressed: &HashSet<VirtualKey>) -> Self {
+        let any = |keys: [VirtualKey; 3]| keys.iter().any(|key| pressed.contains(key));
+        Modifiers {
+            ctrl: any([VirtualKey::CONTROL, VirtualKey::LCONTROL, VirtualKey::RCONTROL]),
+            shift: any([VirtualKey::SHIFT, VirtualKey::LSHIFT, VirtualKey::RSHIFT]),
+            alt: any([VirtualKey::MENU, VirtualKey::LMENU, VirtualKey::RMENU]),
+            win: pressed.contains(&VirtualKey::LWIN) || pressed.contains(&VirtualKey::RWIN)
+        }
+    }
+}
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Hotkey {
+    pub modifiers: Modifiers,
+    pub key: VirtualKey
+}
+
+impl Hotkey {
+    pub fn new(modifiers: Modifiers, key: impl Into<VirtualKey>) -> Self {
+        Hotkey { modifiers, key: key.into() }
+    }
+
+    // the held modifiers must match exactly, so "Ctrl+C" does not fire for ctrl + shift + c
+    pub fn matches(&self, key: VirtualKey, held: Modifiers) -> bool {
+        self.key == key && self.modifiers == held
+    }
+
+    // every key code that has to be watched to detect this hotkey
+    pub fn vk_codes(&self) -> Vec<i32> {
+        let mut vk_codes = Modifiers::VK_CODES.to_vec();
+        if !vk_codes.contains(&self.key.code()) {
+            vk_codes.push(self.key.code());
+        }
+        vk_codes
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = ParseHotkeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = Modifiers::default();
+        let mut key = None;
+
+        for token in s.split('+').map(str::trim) {
+            match token.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "shift" => modifiers.shift = true,
+                "alt" => modifiers.alt = true,
+                "win" | "windows" => modifiers.win = true,
+                name => {
+                    if key.is_some() {
+                        return Err(ParseHotkeyError::new(s, "more than one non-modifier key"));
+                    }
+                    key = Some(parse_key_name(name).ok_or_else(|| ParseHotkeyError::new(s, "unknown key name"))?);
+                }
+            }
+        }
+
+        match key {
+            Some(key) => Ok(Hotkey { modifiers, key }),
+            None => Err(ParseHotkeyError::new(s, "missing a non-modifier key"))
+        }
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.modifiers.alt {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.win {
+            write!(f, "Win+")?;
+        }
+        match key_name(self.key) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "0x{:02X}", self.key.code())
+        }
+    }
+}
+
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseHotkeyError {
+    pub hotkey: String,
+    pub reason: &'static str
+}
+
+impl ParseHotkeyError {
+    fn new(hotkey: &str, reason: &'static str) -> Self {
+        ParseHotkeyError { hotkey: hotkey.to_string(), reason }
+    }
+}
+
+impl fmt::Display for ParseHotkeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid hotkey '{}': {}", self.hotkey, self.reason)
+    }
+}
+
+impl std::error::Error for ParseHotkeyError {}
+
+
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Hotkey {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Hotkey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hotkey = String::deserialize(deserializer)?;
+        hotkey.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+
+
+// named (non letter / digit) keys accepted in hotkey strings, names are lowercase
+const NAMED_KEYS: [(&str, i32); 36] = [
+    ("space", 0x20),
+    ("enter", 0x0D),
+    ("tab", 0x09),
+    ("backspace", 0x08),
+    ("esc", 0x1B),
+    ("escape", 0x1B),
+    ("insert", 0x2D),
+    ("delete", 0x2E),
+    ("home", 0x24),
+    ("end", 0x23),
+    ("pageup", 0x21),
+    ("pagedown", 0x22),
+    ("left", 0x25),
+    ("up", 0x26),
+    ("right", 0x27),
+    ("down", 0x28),
+    ("plus", 0xBB),
+    ("minus", 0xBD),
+    ("apps", 0x5D),
+    ("printscreen", 0x2C),
+    ("pause", 0x13),
+    ("numlock", 0x90),
+    ("volumemute", 0xAD),
+    ("volumedown", 0xAE),
+    ("volumeup", 0xAF),
+    ("nexttrack", 0xB0),
+    ("prevtrack", 0xB1),
+    ("mediastop", 0xB2),
+    ("playpause", 0xB3),
+    ("browserback", 0xA6),
+    ("browserforward", 0xA7),
+    ("browserrefresh", 0xA8),
+    ("browserstop", 0xA9),
+    ("browsersearch", 0xAA),
+    ("browserfavorites", 0xAB),
+    ("browserhome", 0xAC)
+];
+
+pub(crate) fn parse_key_name(name: &str) -> Option<VirtualKey> {
+    if let Some(&(_, vk_code)) = NAMED_KEYS.iter().find(|(key_name, _)| *key_name == name) {
+        return Some(VirtualKey(vk_code));
+    }
+
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        // a - z and 0 - 9 share their virtual key code with the uppercase ascii value
+        if c.is_ascii_alphanumeric() {
+            return Some(VirtualKey(c.to_ascii_uppercase() as i32));
+        }
+    }
+
+    // 0x01 - 0xFE, how Display writes keys without a name
+    if let Some(hex) = name.strip_prefix("0x") {
+        let vk_code = i32::from_str_radix(hex, 16).ok()?;
+        return (0x01..=0xFE).contains(&vk_code).then_some(VirtualKey(vk_code));
+    }
+
+    // f1 - f24
+    let number: i32 = name.strip_prefix('f')?.parse().ok()?;
+    if (1..=24).contains(&number) {
+        return Some(VirtualKey(VirtualKey::F1.0 + number - 1));
+    }
+    None
+}
+
+fn key_name(vk: VirtualKey) -> Option<String> {
+    let vk_code = vk.code();
+    match vk_code {
+        0x30..=0x39 | 0x41..=0x5A => Some((vk_code as u8 as char).to_string()),
+        0x70..=0x87 => Some(format!("F{}", vk_code - VirtualKey::F1.0 + 1)),
+        _ => NAMED_KEYS.iter()
+            .find(|(_, code)| *code == vk_code)
+            .map(|(name, _)| {
+                let mut chars = name.chars();
+                chars.next().map(|c| c.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+            })
+    }
+}
@@ -0,0 +1,125 @@
+/*
+
+Hotkey routing
+
+Triggers and Profiles both keep track of the held keys to match hotkeys against, and hand every press and release
+to a state machine shared with the application through a std Mutex. Profile callbacks run while it is locked (trigger
+actions are handed to a thread of their own), so a panicking one poisons it: it is locked ignoring the poison, the
+state machine never stops half-way through an update of its own, only in the user code it calls. Modifiers count
+whichever side's code the builder's modifier mode reports.
+
+*/
+
+
+use crate::{KeyEvent, Modifiers, VirtualKey};
+use crate::builder::KeyListenerBuilder;
+use crate::error::KeyListenerError;
+use crate::tokio_listener::KeyListener;
+
+use tokio::sync::Mutex;
+use std::collections::HashSet;
+use std::sync::{Arc, MutexGuard, PoisonError};
+
+
+
+pub(crate) trait KeyRouter: Send + 'static {
+    // held are the modifiers down along with key
+    fn press(&mut self, key: VirtualKey, held: Modifiers);
+    fn release(&mut self, key: VirtualKey);
+}
+
+pub(crate) struct Routed<R> {
+    pub(crate) router: R,
+    pub(crate) pressed: HashSet<VirtualKey>
+}
+
+impl<R: KeyRouter> Routed<R> {
+    fn key_down(&mut self, key_event: KeyEvent) {
+        let KeyEvent::Press(key) = key_event else { return };
+        self.pressed.insert(key);
+        // with ModifierMode::Sided or Coalesced the generic codes may never be reported
+        let held = Modifiers::from_any_side(&self.pressed);
+        self.router.press(key, held);
+    }
+
+    fn key_up(&mut self, key_event: KeyEvent) {
+        let KeyEvent::Release(key) = key_event else { return };
+        self.pressed.remove(&key);
+        self.router.release(key);
+    }
+}
+
+pub(crate) type SharedRouter<R> = Arc<std::sync::Mutex<Routed<R>>>;
+
+pub(crate) fn lock<R>(shared: &std::sync::Mutex<Routed<R>>) -> MutexGuard<'_, Routed<R>> {
+    shared.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// spawns a listener with the builder's options, the builder's key set is replaced with the modifiers and vk_codes
+pub(crate) fn spawn_router<R: KeyRouter>(
+    builder: KeyListenerBuilder,
+    vk_codes: impl IntoIterator<Item = i32>,
+    router: R
+) -> Result<(Arc<Mutex<KeyListener>>, SharedRouter<R>), KeyListenerError> {
+    let mut watched = Modifiers::VK_CODES.to_vec();
+    for vk_code in vk_codes {
+        if !watched.contains(&vk_code) {
+            watched.push(vk_code);
+        }
+    }
+
+    let shared = Arc::new(std::sync::Mutex::new(Routed { router, pressed: HashSet::new() }));
+    let (down_shared, up_shared) = (shared.clone(), shared.clone());
+    let key_listener = builder.vk_codes(watched).spawn(
+        move |key_event| lock(&down_shared).key_down(key_event),
+        move |key_event| lock(&up_shared).key_up(key_event)
+    )?;
+    Ok((key_listener, shared))
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Hotkey;
+
+    // the modifiers held with each press
+    #[derive(Default)]
+    struct Recorded(Vec<(VirtualKey, Modifiers)>);
+
+    impl KeyRouter for Recorded {
+        fn press(&mut self, key: VirtualKey, held: Modifiers) {
+            self.0.push((key, held));
+        }
+
+        fn release(&mut self, _key: VirtualKey) {}
+    }
+
+    fn routed() -> Routed<Recorded> {
+        Routed { router: Recorded::default(), pressed: HashSet::new() }
+    }
+
+    #[test]
+    fn sided_modifiers_count() {
+        let hotkey: Hotkey = "Ctrl+Shift+S".parse().unwrap();
+        let mut routed = routed();
+        // what ModifierMode::Sided reports, without the generic codes
+        routed.key_down(KeyEvent::Press(VirtualKey::RCONTROL));
+        routed.key_down(KeyEvent::Press(VirtualKey::LSHIFT));
+        routed.key_down(KeyEvent::Press(VirtualKey(0x53)));
+        let (key, held) = *routed.router.0.last().unwrap();
+        assert!(hotkey.matches(key, held));
+    }
+
+    #[test]
+    fn released_modifiers_stop_counting() {
+        let hotkey: Hotkey = "Ctrl+S".parse().unwrap();
+        let mut routed = routed();
+        routed.key_down(KeyEvent::Press(VirtualKey::LCONTROL));
+        routed.key_up(KeyEvent::Release(VirtualKey::LCONTROL));
+        routed.key_down(KeyEvent::Press(VirtualKey(0x53)));
+        let (key, held) = *routed.router.0.last().unwrap();
+        assert!(!hotkey.matches(key, held));
+    }
+}
@@ -0,0 +1,125 @@
+/*
+
+Input hub
+
+Every init_* call polls GetAsyncKeyState in a loop of its own. A hub runs a single listener and hands each
+registration only the events of the keys it asked for, so many logical listeners share one polling loop.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+use crate::builder::KeyListenerBuilder;
+use crate::callbacks::Callbacks;
+use crate::error::{KeyListenerError, checked_vk_codes};
+use crate::sequence::SequencedKeyEvent;
+use crate::tokio_listener::KeyListener;
+
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+
+
+struct Registration {
+    vk_codes: HashSet<i32>,
+    sender: mpsc::UnboundedSender<KeyEvent>
+}
+
+pub struct InputHub {
+    key_listener: Arc<Mutex<KeyListener>>,
+    registrations: Arc<std::sync::Mutex<Vec<Registration>>>
+}
+
+impl InputHub {
+    pub fn new() -> Result<Self, KeyListenerError> {
+        InputHub::with_builder(KeyListenerBuilder::new())
+    }
+
+    // the builder decides which keys are polled, along with every other listener option
+    pub fn with_builder(builder: KeyListenerBuilder) -> Result<Self, KeyListenerError> {
+        let (key_listener, mut receiver) = builder.spawn_with_receiver()?;
+        let registrations = Arc::new(std::sync::Mutex::new(Vec::<Registration>::new()));
+
+        let dispatch_registrations = registrations.clone();
+        tokio::spawn(async move {
+            while let Some(SequencedKeyEvent { key_event, .. }) = receiver.recv().await {
+                // events not tied to a key are left to KeyListener::subscribe
+                let Some(vk) = key_event.vk() else { continue };
+                let mut registrations = dispatch_registrations.lock().unwrap();
+                // registrations whose subscription was dropped are removed on the way
+                registrations.retain(|registration| {
+                    !registration.vk_codes.contains(&vk.code()) || registration.sender.send(key_event).is_ok()
+                });
+            }
+        });
+
+        Ok(InputHub { key_listener, registrations })
+    }
+
+    // events of vk_codes only, keys the hub's listener does not poll are never reported
+    pub fn subscribe(&self, vk_codes: impl IntoIterator<Item = impl Into<VirtualKey>>) -> Result<HubSubscription, KeyListenerError> {
+        let vk_codes = checked_vk_codes(vk_codes.into_iter().map(|vk| vk.into().code()).collect(), false)?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.registrations.lock().unwrap().push(Registration { vk_codes: vk_codes.into_iter().collect(), sender });
+        Ok(HubSubscription { receiver })
+    }
+
+    // callbacks for the events of vk_codes, called from a task of their own
+    pub fn register<D, U>(&self, vk_codes: impl IntoIterator<Item = impl Into<VirtualKey>>, key_down_callback: D, key_up_callback: U) -> Result<HubRegistration, KeyListenerError>
+    where
+        D: FnMut(KeyEvent) + Send + 'static,
+        U: FnMut(KeyEvent) + Send + 'static
+    {
+        let mut subscription = self.subscribe(vk_codes)?;
+        let mut callbacks = Callbacks::new(key_down_callback, key_up_callback);
+        let task = tokio::spawn(async move {
+            while let Some(key_event) = subscription.recv().await {
+                callbacks.call(key_event);
+            }
+        });
+        Ok(HubRegistration { task })
+    }
+
+    // registrations that are still receiving events
+    pub fn registrations(&self) -> usize {
+        let mut registrations = self.registrations.lock().unwrap();
+        registrations.retain(|registration| !registration.sender.is_closed());
+        registrations.len()
+    }
+
+    // the shared listener, e.g. for its stats or to change the polling interval
+    pub fn key_listener(&self) -> Arc<Mutex<KeyListener>> {
+        self.key_listener.clone()
+    }
+
+    // stops the shared polling loop, every registration stops receiving events
+    pub async fn quit(&self) {
+        self.key_listener.lock().await.quit();
+    }
+}
+
+
+
+pub struct HubSubscription {
+    receiver: mpsc::UnboundedReceiver<KeyEvent>
+}
+
+impl HubSubscription {
+    // None once the hub's listener is gone
+    pub async fn recv(&mut self) -> Option<KeyEvent> {
+        self.receiver.recv().await
+    }
+}
+
+pub struct HubRegistration {
+    task: JoinHandle<()>
+}
+
+impl HubRegistration {
+    // stops calling the registration's callbacks, the other registrations are unaffected
+    pub fn unregister(self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,66 @@
+/*
+
+Idle detection
+
+GetLastInputInfo reports the tick of the last keyboard or mouse input in the session, so idle time covers all
+input and not only the keys a listener watches.
+
+https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getlastinputinfo
+
+*/
+
+
+use crate::KeyEvent;
+
+use windows::Win32::System::SystemInformation::GetTickCount;
+use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+use std::time::Duration;
+
+
+
+// time since the last keyboard or mouse input
+pub fn idle_time() -> Duration {
+    let mut info = LASTINPUTINFO { cbSize: size_of::<LASTINPUTINFO>() as u32, dwTime: 0 };
+    unsafe {
+        if !GetLastInputInfo(&mut info).as_bool() {
+            return Duration::ZERO;
+        }
+        // both are 32 bit tick counts, which wrap around after 49.7 days
+        Duration::from_millis(GetTickCount().wrapping_sub(info.dwTime) as u64)
+    }
+}
+
+
+
+// emits KeyEvent::IdleStarted once there has been no input for the threshold, and KeyEvent::IdleEnded on the next input
+pub struct IdleWatcher {
+    threshold: Duration,
+    idle: bool
+}
+
+impl IdleWatcher {
+    pub fn new(threshold: Duration) -> Self {
+        IdleWatcher { threshold, idle: false }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    // call periodically, the events are only as precise as the calls
+    pub fn poll(&mut self) -> Option<KeyEvent> {
+        let idle_time = idle_time();
+        match (self.idle, idle_time >= self.threshold) {
+            (false, true) => {
+                self.idle = true;
+                Some(KeyEvent::IdleStarted(idle_time))
+            }
+            (true, false) => {
+                self.idle = false;
+                Some(KeyEvent::IdleEnded)
+            }
+            _ => None
+        }
+    }
+}
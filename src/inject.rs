@@ -0,0 +1,50 @@
+/*
+
+Injecting key events with SendInput
+
+Injected events are tagged through dwExtraInfo so hooks installed by this crate can recognise (and skip) their own.
+
+https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-sendinput
+
+*/
+
+
+use crate::VirtualKey;
+
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYBD_EVENT_FLAGS, KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP,
+    VIRTUAL_KEY
+};
+
+
+
+// dwExtraInfo of every event injected by this crate ("WKEV")
+pub(crate) const INJECTED_EXTRA_INFO: usize = 0x574B_4556;
+
+pub(crate) fn send_key(vk: VirtualKey, up: bool) {
+    let scan_code = vk.scan_code();
+
+    let mut flags = KEYBD_EVENT_FLAGS(0);
+    if up {
+        flags |= KEYEVENTF_KEYUP;
+    }
+    if scan_code.is_some_and(|scan_code| scan_code.extended) {
+        flags |= KEYEVENTF_EXTENDEDKEY;
+    }
+
+    let input = INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VIRTUAL_KEY(vk.code() as u16),
+                wScan: scan_code.map_or(0, |scan_code| scan_code.code),
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: INJECTED_EXTRA_INFO
+            }
+        }
+    };
+    unsafe {
+        SendInput(&[input], size_of::<INPUT>() as i32);
+    }
+}
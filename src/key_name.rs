@@ -0,0 +1,78 @@
+/*
+
+Key names
+
+Human readable key names ("Shift", "Right Shift", "Num 5", ...) from GetKeyNameTextW. The names come from the
+keyboard layout of the calling thread, so they follow the user's language. A custom namer set with set_key_namer
+is asked first, for keys the system has no name for (mouse buttons, ...) or to replace the system's names.
+Names are per scan code. Polling only sees key codes, whose scan code is mapped from the layout, so keys sharing a
+code (enter and numpad enter, ...) get the same name; raw input events carry the real scan code and name the key
+that was actually pressed, see RawKeyEvent::key_name.
+
+https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getkeynametextw
+
+*/
+
+
+use crate::{ScanCode, VirtualKey};
+
+use windows::Win32::UI::Input::KeyboardAndMouse::GetKeyNameTextW;
+
+use std::sync::RwLock;
+
+
+
+type KeyNamer = Box<dyn Fn(VirtualKey) -> Option<String> + Send + Sync>;
+
+static KEY_NAMER: RwLock<Option<KeyNamer>> = RwLock::new(None);
+
+// names keys before the system does, returning None falls back to the system's name
+pub fn set_key_namer<F>(namer: F)
+where
+    F: Fn(VirtualKey) -> Option<String> + Send + Sync + 'static
+{
+    *KEY_NAMER.write().unwrap() = Some(Box::new(namer));
+}
+
+pub fn clear_key_namer() {
+    *KEY_NAMER.write().unwrap() = None;
+}
+
+
+
+impl VirtualKey {
+    // the custom namer's name for the key, or the system's, None for keys without either
+    pub fn name(&self) -> Option<String> {
+        self.custom_name().or_else(|| self.system_name())
+    }
+
+    // name, or the hex key code for keys without one, e.g. for log lines and UI labels
+    pub fn display_name(&self) -> String {
+        self.name().unwrap_or_else(|| self.to_string())
+    }
+
+    pub(crate) fn custom_name(&self) -> Option<String> {
+        KEY_NAMER.read().unwrap().as_ref().and_then(|namer| namer(*self))
+    }
+
+    // the name GetKeyNameTextW gives the key's scan code
+    pub fn system_name(&self) -> Option<String> {
+        // pause maps to the 0xE1 0x1D prefix, whose scan code alone is named like ctrl
+        match *self {
+            VirtualKey::PAUSE => ScanCode { code: 0x45, extended: false }.name(),
+            vk => vk.scan_code()?.name()
+        }
+    }
+}
+
+impl ScanCode {
+    // the name GetKeyNameTextW gives this scan code in the calling thread's layout
+    pub fn name(&self) -> Option<String> {
+        // scan code in bits 16 - 23, the extended flag in bit 24. Bit 25 stays clear, so left and right keys
+        // keep separate names
+        let lparam = ((self.code as i32 & 0xFF) << 16) | ((self.extended as i32) << 24);
+        let mut buffer = [0u16; 64];
+        let written = unsafe { GetKeyNameTextW(lparam, &mut buffer) };
+        (written > 0).then(|| String::from_utf16_lossy(&buffer[..written as usize]))
+    }
+}
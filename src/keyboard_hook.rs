@@ -0,0 +1,249 @@
+/*
+
+Key listener using a WH_KEYBOARD_LL hook
+
+Polling can only observe keys, a hook sees each key event before other applications do and can stop it from
+reaching them. Keys and hotkeys marked as consumed are still reported to the callbacks but are swallowed by the
+hook. The hook reports left / right modifier codes (VK_LSHIFT, ...) rather than the generic ones.
+
+The hook also sees input injected with SendInput (including this crate's own), which it flags as injected.
+
+https://learn.microsoft.com/en-us/windows/win32/winmsg/lowlevelkeyboardproc
+
+*/
+
+
+use crate::{Hotkey, KeyEvent, Modifiers, VirtualKey};
+use crate::callbacks::Callbacks;
+use crate::error::KeyListenerError;
+use crate::hook::{HookThread, note_event_time, spawn_hook_thread};
+use crate::message_loop::MessageLoop;
+
+use windows::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+use windows::Win32::UI::WindowsAndMessaging::{CallNextHookEx, HC_ACTION, KBDLLHOOKSTRUCT, LLKHF_INJECTED, LLKHF_UP, WH_KEYBOARD_LL};
+
+use std::cell::RefCell;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{Sender, channel};
+use std::thread;
+use std::time::Duration;
+
+
+
+// a key event with the flags only the hook can see
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HookKeyEvent {
+    pub key_event: KeyEvent,
+    // synthesized with SendInput / keybd_event rather than typed
+    pub injected: bool,
+    // a press the OS repeated for a held key, only set with RepeatMode::Tag
+    pub is_repeat: bool
+}
+
+// what happens to the key down events Windows repeats while a key is held
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RepeatMode {
+    // only the first press is reported, like with polling
+    #[default]
+    Suppress,
+    // every repeat is reported as another press
+    PassThrough,
+    // every repeat is reported as another press, with is_repeat set. Only spawn_with_flags sees the flag, the
+    // callbacks given to spawn get tagged repeats like PassThrough ones
+    Tag
+}
+
+#[derive(Default)]
+struct Consumed {
+    keys: HashSet<VirtualKey>,
+    hotkeys: HashSet<Hotkey>
+}
+
+pub struct HookKeyListener {
+    hook_thread: HookThread,
+    consumed: Arc<Mutex<Consumed>>
+}
+
+impl HookKeyListener {
+    pub fn builder() -> HookKeyListenerBuilder {
+        HookKeyListenerBuilder::default()
+    }
+
+    pub fn quit(&self) {
+        self.hook_thread.stop();
+    }
+
+    // times the hook was installed again after Windows removed it for being too slow, see hook_gap
+    pub fn hook_reinstalls(&self) -> u32 {
+        self.hook_thread.reinstalls()
+    }
+
+    // roughly how long events went unseen before the last reinstall
+    pub fn hook_gap(&self) -> Option<Duration> {
+        self.hook_thread.last_gap()
+    }
+
+    // the key no longer reaches other applications, whatever modifiers are held
+    pub fn consume_key(&self, vk: impl Into<VirtualKey>) {
+        self.consumed.lock().unwrap().keys.insert(vk.into());
+    }
+
+    pub fn stop_consuming_key(&self, vk: impl Into<VirtualKey>) {
+        self.consumed.lock().unwrap().keys.remove(&vk.into());
+    }
+
+    // only the hotkey's key is consumed, the modifiers still reach other applications
+    pub fn consume_hotkey(&self, hotkey: Hotkey) {
+        self.consumed.lock().unwrap().hotkeys.insert(hotkey);
+    }
+
+    pub fn stop_consuming_hotkey(&self, hotkey: Hotkey) {
+        self.consumed.lock().unwrap().hotkeys.remove(&hotkey);
+    }
+}
+
+
+
+struct KeyboardHookState {
+    sender: Sender<HookKeyEvent>,
+    ignore_injected: bool,
+    repeat_mode: RepeatMode,
+    consumed: Arc<Mutex<Consumed>>,
+    pressed: HashSet<VirtualKey>,
+    // keys whose press was consumed, so their repeats and release are consumed as well
+    swallowed: HashSet<VirtualKey>
+}
+
+thread_local! {
+    static KEYBOARD_HOOK_STATE: RefCell<Option<KeyboardHookState>> = const { RefCell::new(None) };
+}
+
+unsafe extern "system" fn keyboard_hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code == HC_ACTION as i32 {
+        let info = unsafe { &*(lparam.0 as *const KBDLLHOOKSTRUCT) };
+        note_event_time(info.time, info.flags.0 & LLKHF_INJECTED.0 != 0);
+        let consume = KEYBOARD_HOOK_STATE.with_borrow_mut(|state| {
+            state.as_mut().is_some_and(|state| state.handle(info))
+        });
+        if consume {
+            return LRESULT(1);
+        }
+    }
+    unsafe { CallNextHookEx(None, code, wparam, lparam) }
+}
+
+impl KeyboardHookState {
+    // reports the key event and returns whether it should be consumed
+    fn handle(&mut self, info: &KBDLLHOOKSTRUCT) -> bool {
+        let key = VirtualKey(info.vkCode as i32);
+        let injected = info.flags.0 & LLKHF_INJECTED.0 != 0;
+        if injected && self.ignore_injected {
+            return false;
+        }
+
+        if info.flags.0 & LLKHF_UP.0 != 0 {
+            self.pressed.remove(&key);
+            let _ = self.sender.send(HookKeyEvent { key_event: KeyEvent::Release(key), injected, is_repeat: false });
+            return self.swallowed.remove(&key);
+        }
+
+        // held keys repeat their key down, a repeat keeps the first press' consumed state
+        if self.pressed.insert(key) {
+            let _ = self.sender.send(HookKeyEvent { key_event: KeyEvent::Press(key), injected, is_repeat: false });
+
+            let held = Modifiers::from_any_side(&self.pressed);
+            let consumed = self.consumed.lock().unwrap();
+            if consumed.keys.contains(&key) || consumed.hotkeys.iter().any(|hotkey| hotkey.matches(key, held)) {
+                self.swallowed.insert(key);
+            }
+        } else if self.repeat_mode != RepeatMode::Suppress {
+            let is_repeat = self.repeat_mode == RepeatMode::Tag;
+            let _ = self.sender.send(HookKeyEvent { key_event: KeyEvent::Press(key), injected, is_repeat });
+        }
+        self.swallowed.contains(&key)
+    }
+}
+
+
+
+#[derive(Clone, Debug, Default)]
+pub struct HookKeyListenerBuilder {
+    ignore_injected: bool,
+    repeat_mode: RepeatMode,
+    message_loop: MessageLoop
+}
+
+impl HookKeyListenerBuilder {
+    // drop injected input entirely: it is neither reported nor consumed
+    pub fn ignore_injected(mut self, ignore_injected: bool) -> Self {
+        self.ignore_injected = ignore_injected;
+        self
+    }
+
+    pub fn repeat_mode(mut self, repeat_mode: RepeatMode) -> Self {
+        self.repeat_mode = repeat_mode;
+        self
+    }
+
+    // install the hook on the calling thread, driven by the application's message loop, see MessageLoop
+    pub fn message_loop(mut self, message_loop: MessageLoop) -> Self {
+        self.message_loop = message_loop;
+        self
+    }
+
+    // callbacks are called on a separate thread so they cannot slow down the hook. They get the bare KeyEvent,
+    // use spawn_with_flags to tell injected input or tagged repeats apart
+    pub fn spawn<D, U>(self, key_down_callback: D, key_up_callback: U) -> Result<HookKeyListener, KeyListenerError>
+    where
+        D: FnMut(KeyEvent) + Send + 'static,
+        U: FnMut(KeyEvent) + Send + 'static
+    {
+        let mut callbacks = Callbacks::new(key_down_callback, key_up_callback);
+        self.spawn_with_flags(move |hook_key_event| callbacks.call(hook_key_event.key_event))
+    }
+
+    // a single callback for presses and releases, along with their hook flags
+    pub fn spawn_with_flags<F>(self, mut callback: F) -> Result<HookKeyListener, KeyListenerError>
+    where
+        F: FnMut(HookKeyEvent) + Send + 'static
+    {
+        let (sender, receiver) = channel();
+        let consumed = Arc::new(Mutex::new(Consumed::default()));
+
+        let hook_consumed = consumed.clone();
+        let setup = move || {
+            KEYBOARD_HOOK_STATE.set(Some(KeyboardHookState {
+                sender,
+                ignore_injected: self.ignore_injected,
+                repeat_mode: self.repeat_mode,
+                consumed: hook_consumed,
+                pressed: HashSet::new(),
+                swallowed: HashSet::new()
+            }));
+        };
+        // dropping the state drops its sender, which ends the dispatch thread
+        let teardown = || KEYBOARD_HOOK_STATE.set(None);
+        let hook_thread = spawn_hook_thread(WH_KEYBOARD_LL, keyboard_hook_proc, self.message_loop, setup, teardown)?;
+
+        thread::spawn(move || {
+            for hook_key_event in receiver {
+                // keeps the dispatch thread alive, consumed keys would otherwise be swallowed unheard
+                let _ = catch_unwind(AssertUnwindSafe(|| callback(hook_key_event)));
+            }
+        });
+
+        Ok(HookKeyListener { hook_thread, consumed })
+    }
+}
+
+
+
+pub fn init_hook_key_listener<D, U>(key_down_callback: D, key_up_callback: U) -> Result<HookKeyListener, KeyListenerError>
+where
+    D: FnMut(KeyEvent) + Send + 'static,
+    U: FnMut(KeyEvent) + Send + 'static
+{
+    HookKeyListener::builder().spawn(key_down_callback, key_up_callback)
+}
@@ -9,229 +9,310 @@ https://learn.microsoft.com/en-us/windows/win32/inputdev/virtual-key-codes
 */
 
 
-use windows::Win32::UI::Input::KeyboardAndMouse::GetAsyncKeyState;
-
-use tokio::time::{sleep, Duration};
-use tokio::sync::mpsc::{UnboundedSender, UnboundedReceiver, unbounded_channel};
-use tokio::sync::Mutex;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
-
-
-
-const KEY_DOWN_MASK: i16 = -32768; // using 0x8000 gives an overflow error, so directly state the negative int
-
-enum KeyEvent {
-    Press(i32),
-    Release(i32)
-}
-
-enum KeyState {
-    KeyPress,
-    KeyRelease,
-    StaticDown,
-    StaticUp
-}
-
-
-
-pub struct KeyListener {
-    vk_codes: Vec<i32>,
-    unbounded_sender: UnboundedSender<KeyEvent>,
-    previous_key_states: Vec<bool>,
-    polling_wait: u64,
-    is_watching: Arc<AtomicBool>,
+#[cfg(not(any(feature = "tokio", feature = "thread")))]
+compile_error!("enable at least one of the \"tokio\" or \"thread\" features");
+
+mod error;
+mod virtual_key;
+mod hid;
+mod key_name;
+mod poll;
+mod callbacks;
+#[cfg(feature = "tokio")]
+mod callback_handle;
+#[cfg(feature = "tokio")]
+mod pipeline;
+#[cfg(feature = "tokio")]
+mod modifiers;
+#[cfg(feature = "tokio")]
+mod debounce;
+#[cfg(feature = "tokio")]
+mod rate_limit;
+#[cfg(feature = "tokio")]
+mod consumer;
+mod hotkey;
+#[cfg(feature = "tokio")]
+mod hotkey_router;
+mod numpad;
+#[cfg(feature = "tokio")]
+mod blocking_query;
+mod text;
+mod window;
+mod session;
+mod elevation;
+mod privacy;
+mod idle;
+mod message_loop;
+mod hook;
+mod mouse;
+mod keyboard_hook;
+mod raw_input;
+pub mod devices;
+mod inject;
+mod remap;
+mod gamepad;
+mod typed_text;
+mod chord;
+mod recorder;
+mod session_format;
+mod typing_stats;
+#[cfg(any(feature = "keyboard-types", feature = "winit"))]
+mod w3c_codes;
+#[cfg(feature = "keyboard-types")]
+mod web_keys;
+#[cfg(feature = "winit")]
+mod winit_keys;
+#[cfg(feature = "crossterm")]
+mod crossterm_events;
+#[cfg(feature = "tokio")]
+mod channel;
+#[cfg(feature = "tokio")]
+mod power;
+#[cfg(feature = "tokio")]
+mod timer;
+#[cfg(feature = "tokio")]
+mod priority;
+#[cfg(feature = "tokio")]
+mod stats;
+#[cfg(feature = "tokio")]
+mod history;
+#[cfg(feature = "tokio")]
+mod pressed_set;
+#[cfg(feature = "tokio")]
+mod sequence;
+#[cfg(feature = "tokio")]
+mod modifier_gestures;
+#[cfg(feature = "tokio")]
+mod warnings;
+#[cfg(feature = "tokio")]
+mod tokio_listener;
+#[cfg(feature = "tokio")]
+mod builder;
+#[cfg(feature = "tokio")]
+mod listener_handle;
+#[cfg(feature = "tokio")]
+mod supervisor;
+#[cfg(feature = "tokio")]
+mod wait;
+#[cfg(feature = "tokio")]
+mod xinput_listener;
+#[cfg(feature = "tokio")]
+mod profiles;
+#[cfg(feature = "tokio")]
+mod triggers;
+#[cfg(feature = "scripting")]
+mod scripting;
+#[cfg(feature = "tokio")]
+mod hub;
+#[cfg(feature = "tokio")]
+mod global;
+#[cfg(feature = "tokio")]
+mod replay;
+#[cfg(feature = "mock")]
+mod mock;
+#[cfg(feature = "named-pipe")]
+mod named_pipe;
+#[cfg(feature = "websocket")]
+mod websocket;
+#[cfg(feature = "tokio")]
+mod self_test;
+#[cfg(feature = "tokio")]
+mod diagnostics;
+#[cfg(feature = "thread")]
+mod thread_listener;
+#[cfg(feature = "executor-agnostic")]
+mod agnostic_listener;
+#[cfg(feature = "bevy")]
+mod bevy;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+use std::time::Duration;
+
+pub use error::KeyListenerError;
+pub use callbacks::CallbackPanic;
+pub use virtual_key::{VirtualKey, ScanCode, UnmappedKey, MOUSE_BUTTONS, WINDOWS_KEYS, MEDIA_KEYS, BROWSER_KEYS, FUNCTION_KEYS};
+pub use hid::HidUsage;
+pub use key_name::{set_key_namer, clear_key_namer};
+pub use hotkey::{Hotkey, Modifiers, ParseHotkeyError};
+pub use poll::is_toggled;
+pub use numpad::NumpadKey;
+pub use mouse::{MouseListener, WHEEL_DELTA, init_mouse_listener};
+pub use message_loop::{MessageLoop, pump_messages, run_message_loop};
+pub use keyboard_hook::{HookKeyListener, HookKeyListenerBuilder, HookKeyEvent, RepeatMode, init_hook_key_listener};
+pub use raw_input::{RawInputListener, RawInputListenerBuilder, RawKeyEvent, DeviceFilter, DeviceHandle};
+pub use devices::{DeviceInfo, DeviceChange, KeyboardKind};
+pub use remap::{Remapper, ActiveRemapper};
+pub use gamepad::{GamepadButton, GamepadAxis, GAMEPAD_BUTTONS};
+pub use recorder::{Recorder, RecorderBuilder, RedactedField, KeyRedaction, SessionFormat};
+pub use typing_stats::{TypingStats, INTERVAL_BUCKET_WIDTH, INTERVAL_BUCKETS};
+pub use text::{KeyboardLayout, translate_key};
+pub use window::{WindowHandle, WindowFilter, WindowContext};
+pub use session::is_secure_desktop_active;
+pub use elevation::{CaptureLimitations, capture_limitations};
+pub use privacy::is_password_field_focused;
+pub use idle::{IdleWatcher, idle_time};
+#[cfg(feature = "tokio")]
+pub use window::WindowKeyEvent;
+#[cfg(feature = "tokio")]
+pub use pressed_set::StateChanged;
+#[cfg(feature = "tokio")]
+pub use sequence::{SequencedKeyEvent, current_sequence_number};
+pub use typed_text::TextBuffer;
+#[cfg(feature = "tokio")]
+pub use typed_text::TypedTextStream;
+pub use chord::{Chord, ChordDetector};
+#[cfg(feature = "tokio")]
+pub use chord::ChordStream;
+#[cfg(feature = "crossterm")]
+pub use crossterm_events::{CrosstermEvents, to_crossterm};
+#[cfg(feature = "bevy")]
+pub use bevy::{WinKeyEventPlugin, GlobalKeyEvent, GlobalKeys};
+#[cfg(feature = "tokio")]
+pub use tokio_listener::{
+    KeyListener, AsyncCallbackMode,
+    init_default_key_listener, init_custom_key_listener, init_any_event_key_listener,
+    init_default_async_callback_listener, init_custom_async_callback_listener
+};
+#[cfg(feature = "tokio")]
+pub use builder::KeyListenerBuilder;
+#[cfg(feature = "tokio")]
+pub use listener_handle::{ListenerHandle, ListenerState};
+#[cfg(feature = "tokio")]
+pub use callback_handle::CallbackHandle;
+#[cfg(feature = "tokio")]
+pub use channel::{ChannelMode, OverflowPolicy};
+#[cfg(feature = "tokio")]
+pub use modifiers::ModifierMode;
+#[cfg(feature = "tokio")]
+pub use rate_limit::RateLimitPolicy;
+#[cfg(feature = "tokio")]
+pub use consumer::{EventConsumer, EventStatus};
+#[cfg(feature = "tokio")]
+pub use priority::ThreadPriority;
+#[cfg(feature = "tokio")]
+pub use stats::ListenerStats;
+#[cfg(feature = "tokio")]
+pub use warnings::ListenerWarning;
+#[cfg(feature = "tokio")]
+pub use hub::{InputHub, HubSubscription, HubRegistration};
+#[cfg(feature = "tokio")]
+pub use global::{global_listener, on_press, on_release};
+#[cfg(feature = "tokio")]
+pub use replay::SessionReplayer;
+#[cfg(feature = "mock")]
+pub use mock::MockBackend;
+#[cfg(feature = "named-pipe")]
+pub use named_pipe::PipeServerBuilder;
+#[cfg(feature = "websocket")]
+pub use websocket::WebSocketServerBuilder;
+#[cfg(feature = "tokio")]
+pub use self_test::{SelfTestError, self_test};
+#[cfg(feature = "tokio")]
+pub use diagnostics::{LatencyReport, measure_latency};
+#[cfg(feature = "tokio")]
+pub use wait::{DiscoveredKey, HotkeyWaitError, wait_for_key, wait_for_any, wait_for_hotkey, wait_for_hotkey_timeout, discover_key};
+#[cfg(feature = "tokio")]
+pub use profiles::{Profile, Profiles};
+#[cfg(feature = "tokio")]
+pub use triggers::{Triggers, ActiveTriggers, BindingError, save_bindings, load_bindings};
+#[cfg(feature = "scripting")]
+pub use scripting::{KeyScript, ScriptError};
+#[cfg(feature = "tokio")]
+pub use xinput_listener::{XInputListener, init_xinput_listener};
+#[cfg(feature = "thread")]
+pub use thread_listener::{
+    ThreadKeyListener, KeyEvents,
+    init_default_thread_key_listener, init_custom_thread_key_listener, init_any_event_thread_key_listener,
+    init_thread_key_listener_with_state,
+    init_default_key_events, init_custom_key_events
+};
+#[cfg(feature = "executor-agnostic")]
+pub use agnostic_listener::{
+    AsyncKeyEvents, SpawnedTask,
+    init_default_key_listener_on, init_custom_key_listener_on,
+    init_default_async_key_events, init_custom_async_key_events
+};
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum KeyEvent {
+    Press(VirtualKey),
+    Release(VirtualKey),
+    // CapsLock, NumLock or ScrollLock was switched on or off
+    ToggleChanged { key: VirtualKey, on: bool },
+    // a character typed by a press of key, follows the Press event in text mode
+    Char { key: VirtualKey, ch: char },
+    // the foreground window switched to a different keyboard layout / input language
+    LayoutChanged(KeyboardLayout),
+    // cursor moved to (x, y) in screen coordinates, by (dx, dy) since the last move
+    MouseMove { x: i32, y: i32, dx: i32, dy: i32 },
+    // wheel turned by delta (WHEEL_DELTA per notch), positive is forward / right
+    MouseWheel { delta: i16, horizontal: bool },
+    // a button on the XInput controller in slot pad (0 - 3) went down / up
+    GamepadPress { pad: u8, button: GamepadButton },
+    GamepadRelease { pad: u8, button: GamepadButton },
+    // a trigger or stick axis changed, see GamepadAxis for the ranges
+    GamepadAxis { pad: u8, axis: GamepadAxis, value: i16 },
+    GamepadConnected { pad: u8, connected: bool },
+    // another window came to the foreground, its title and process name are read with KeyEvent::window_title and
+    // KeyEvent::process_name
+    FocusChanged { window: WindowHandle, process_id: u32 },
+    // the lock screen (or a UAC prompt / ctrl+alt+del screen) took over input, and gave it back
+    SessionLocked,
+    SessionUnlocked,
+    // the system is going to sleep / woke up, polling pauses in between
+    Suspended,
+    Resumed,
+    // no keyboard or mouse input for the given time, which is at least the idle threshold
+    IdleStarted(Duration),
+    IdleEnded,
+    // key was pressed and released presses times while a slow receiver held up a Coalesce channel
+    Coalesced { key: VirtualKey, presses: u32 },
+    // a modifier was tapped on its own / tapped twice in a row, follows the release ending the tap
+    ModifierTap(VirtualKey),
+    ModifierDoubleTap(VirtualKey),
+    // in privacy mode, the focus moved into / out of a password field, no Char events are produced in between
+    SensitiveInputStarted,
+    SensitiveInputEnded
 }
 
-impl KeyListener {
-    fn new_default(unbounded_sender: UnboundedSender<KeyEvent>) -> Self {
-        KeyListener {
-            vk_codes: vec![
-                // 0 - 9
-                0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39,
-
-                // 0 - 9 (numpad)
-                0x60, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
-
-                // a - z
-                0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4A, 0x4B, 0x4C, 0x4D, 0x4E, 0x4F,
-                0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A,
-
-                // punctuation, symbols (not shift + num symbols)
-                // (shift normal)
-                0xBA, // : ;
-                0xBB, // + =
-                0xBC, // < ,
-                0xBD, // _ -
-                0xBE, // > .
-                0xBF, // ? /
-                0xC0, // ~ `
-                0xDB, // { [
-                0xDC, // | \
-                0xDD, // } ]
-                0xDE, // " '
-
-                0x20, // space
-                0x0D, // enter
-                0x08, // backspace
-                0x09, // tab
-
-                // numpad operators
-                0x6A, // *
-                0x6B, // +
-                0x6D, // -
-                0x6E, // .
-                0x6F, // /
-
-                // shift
-                0xA0, // left
-                0xA1, // right
-                0x10  // generic
-            ],
-            unbounded_sender,
-            previous_key_states: vec![false; 69],
-            polling_wait: 10,
-            is_watching: Arc::new(AtomicBool::new(false))
+impl KeyEvent {
+    // the key an event is about, None for events not tied to a key
+    pub fn vk(&self) -> Option<VirtualKey> {
+        match self {
+            KeyEvent::Press(vk) | KeyEvent::Release(vk) | KeyEvent::ModifierTap(vk) | KeyEvent::ModifierDoubleTap(vk) => Some(*vk),
+            KeyEvent::ToggleChanged { key, .. } | KeyEvent::Char { key, .. } | KeyEvent::Coalesced { key, .. } => Some(*key),
+            _ => None
         }
     }
 
-    fn new_custom(unbounded_sender: UnboundedSender<KeyEvent>, vk_codes: Vec<i32>, polling_wait: u64) -> Self {
-        let key_num = &vk_codes.len();
-        KeyListener {
-            vk_codes,
-            unbounded_sender,
-            previous_key_states: vec![false; *key_num],
-            polling_wait,
-            is_watching: Arc::new(AtomicBool::new(false))
-        }
+    // scan code and extended flag of the event's key. GetAsyncKeyState only reports key codes, so these are
+    // mapped from the key code and cannot tell apart keys sharing one (e.g. numpad enter and enter)
+    pub fn scan_code(&self) -> Option<ScanCode> {
+        self.vk().and_then(|vk| vk.scan_code())
     }
 
-    pub fn quit(&mut self) {
-        self.is_watching.store(false, Ordering::Relaxed);
+    // display name of the event's key, see VirtualKey::name
+    pub fn key_name(&self) -> Option<String> {
+        self.vk().and_then(|vk| vk.name())
     }
-}
-
-
 
-async fn listen(
-    sleep_time: Duration,
-    vk_codes: Vec<i32>,
-    mut previous_key_states: Vec<bool>,
-    sender: UnboundedSender<KeyEvent>,
-    is_watching: Arc<AtomicBool>
-) {
-    is_watching.store(true, Ordering::Relaxed);
-    while is_watching.load(Ordering::Relaxed) {
-        for i in 0..vk_codes.len() {
-            let vk_code = vk_codes[i];
-            let key_state = get_key_state(&vk_code, i, &mut previous_key_states);
-            match key_state {
-                KeyState::StaticUp => {}
-                KeyState::StaticDown => {}
-                KeyState::KeyRelease => {
-                    let _ = sender.send(KeyEvent::Release(vk_code));
-                }
-                KeyState::KeyPress => {
-                    let _ = sender.send(KeyEvent::Press(vk_code));
-                }
-
-            }
+    // title of the window a FocusChanged event is about, read when called, so it is None once the window is closed
+    pub fn window_title(&self) -> Option<String> {
+        match self {
+            KeyEvent::FocusChanged { window, .. } if window.is_window() => Some(window.title()),
+            _ => None
         }
-        sleep(sleep_time).await;
     }
-}
-
-
 
-fn get_key_state(vk_code: &i32, i: usize, previous_key_states: &mut Vec<bool>) -> KeyState {
-    let state = unsafe {
-        GetAsyncKeyState(*vk_code)
-    };
-    let is_down = (state & KEY_DOWN_MASK) != 0;
-    let was_down = previous_key_states[i];
-
-    previous_key_states[i] = is_down;
-
-    if is_down {
-        if was_down {
-            return KeyState::StaticDown;
-        } else {
-            return KeyState::KeyPress;
-        }
-    } else {
-        if was_down {
-            return KeyState::KeyRelease;
-        } else {
-            return KeyState::StaticUp;
+    // executable file name of the process that owned a FocusChanged event's window, looked up when called
+    pub fn process_name(&self) -> Option<String> {
+        match self {
+            KeyEvent::FocusChanged { process_id, .. } => window::process_name(*process_id),
+            _ => None
         }
     }
 }
-
-
-
-
-
-
-fn spawn_receiver(
-    mut receiver: UnboundedReceiver<KeyEvent>,
-    key_down_callback: Box<dyn Fn(i32) + Send + Sync + 'static>, key_up_callback: Box<dyn Fn(i32) + Send + Sync + 'static>
-) {
-    tokio::spawn(async move {
-        while let Some(key_event) = receiver.recv().await {
-            match key_event {
-                KeyEvent::Press(vk) => key_down_callback(vk),
-                KeyEvent::Release(vk) => key_up_callback(vk)
-            }
-        }
-    });
-}
-
-fn spawn_listener(listener: Arc<Mutex<KeyListener>>) {
-    tokio::spawn(async move {
-
-        let locked = listener.lock().await;
-
-        let sleep_time =  Duration::from_millis(locked.polling_wait);
-        let vk_codes = locked.vk_codes.clone();
-        let previous_key_states = locked.previous_key_states.clone();
-        let sender = locked.unbounded_sender.clone();
-        let is_watching = locked.is_watching.clone();
-
-        drop(locked); // drops locked so that the user instance of the listener can be locked and 'quit' can be called
-
-        listen(sleep_time, vk_codes, previous_key_states, sender, is_watching).await;
-    });
-}
-
-
-
-
-pub fn init_default_key_listener(
-    key_down_callback: Box<dyn Fn(i32) + Send + Sync + 'static>, key_up_callback: Box<dyn Fn(i32) + Send + Sync + 'static>
-) -> Arc<tokio::sync::Mutex<KeyListener>> {
-    let (sender, receiver) = unbounded_channel();
-    let key_listener = Arc::new(Mutex::new(KeyListener::new_default(sender)));
-
-    let listener = Arc::clone(&key_listener);
-    spawn_listener(listener);
-
-    spawn_receiver(receiver, key_down_callback, key_up_callback);
-
-    key_listener
-}
-
-pub fn init_custom_key_listener(
-    key_down_callback: Box<dyn Fn(i32) + Send + Sync + 'static>, key_up_callback: Box<dyn Fn(i32) + Send + Sync + 'static>,
-    vk_codes: Vec<i32>,
-    polling_wait: u64
-) -> Arc<tokio::sync::Mutex<KeyListener>> {
-    let (sender, receiver) = unbounded_channel();
-    let key_listener = Arc::new(Mutex::new(KeyListener::new_custom(sender, vk_codes, polling_wait)));
-
-    let listener = Arc::clone(&key_listener);
-    spawn_listener(listener);
-
-    spawn_receiver(receiver, key_down_callback, key_up_callback);
-
-    key_listener
-}
\ No newline at end of file
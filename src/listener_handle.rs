@@ -0,0 +1,198 @@
+/*
+
+Listener handles
+
+A ListenerHandle controls a KeyListener without locking it: running and paused are atomics shared with the
+polling task. The run state packs a generation counter with the running bit, so a polling task from before
+quit / start that is still finishing its last round can tell it is no longer the current one.
+Every transition is also published as a ListenerState on a watch channel, for code supervising the listener. The
+atomics change inside the watch channel's send_modify, under its lock, so concurrent transitions (a quit racing the
+polling task's start) are published in the order they happened and the last published state is the current one.
+
+*/
+
+
+use crate::stats::{ListenerStats, StatsCounters};
+
+use tokio::sync::watch;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ListenerState {
+    // spawned, the first round of polling has not started yet
+    Starting,
+    Running,
+    // running, but keys are not polled, see ListenerHandle::pause
+    Paused,
+    // after quit, or after the polling task ended on its own (the receiver is gone or it was cancelled)
+    Stopped,
+    // the polling task panicked, start (or a supervisor, see KeyListenerBuilder::supervise) brings it back
+    Crashed
+}
+
+
+
+// generation << 1 | running
+pub(crate) struct RunState {
+    state: AtomicU64,
+    paused: AtomicBool,
+    listener_state: watch::Sender<ListenerState>
+}
+
+impl Default for RunState {
+    fn default() -> Self {
+        RunState { state: AtomicU64::new(0), paused: AtomicBool::new(false), listener_state: watch::channel(ListenerState::Stopped).0 }
+    }
+}
+
+impl RunState {
+    // a new generation that is running, for the polling task about to be spawned
+    pub(crate) fn start(&self) -> u64 {
+        let mut generation = 0;
+        self.listener_state.send_modify(|listener_state| {
+            let previous = self.state.fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| Some(((state >> 1) + 1) << 1 | 1));
+            // the closure never returns None
+            generation = (previous.unwrap_or_default() >> 1) + 1;
+            *listener_state = ListenerState::Starting;
+        });
+        generation
+    }
+
+    // the polling task of generation began its first round
+    pub(crate) fn started(&self, generation: u64) {
+        self.listener_state.send_if_modified(|listener_state| {
+            if !self.is_current(generation) {
+                return false;
+            }
+            *listener_state = if self.is_paused() { ListenerState::Paused } else { ListenerState::Running };
+            true
+        });
+    }
+
+    pub(crate) fn quit(&self) {
+        self.listener_state.send_modify(|listener_state| {
+            self.state.fetch_and(!1, Ordering::AcqRel);
+            *listener_state = ListenerState::Stopped;
+        });
+    }
+
+    // whether the polling task of generation is the current one and should keep going
+    pub(crate) fn is_current(&self, generation: u64) -> bool {
+        self.state.load(Ordering::Acquire) == generation << 1 | 1
+    }
+
+    // a polling task ending on its own stops the listener, unless a newer one was started meanwhile
+    pub(crate) fn stop(&self, generation: u64) -> bool {
+        self.end(generation, ListenerState::Stopped)
+    }
+
+    fn end(&self, generation: u64, ended_state: ListenerState) -> bool {
+        self.listener_state.send_if_modified(|listener_state| {
+            let ended = self.state.compare_exchange(generation << 1 | 1, generation << 1, Ordering::AcqRel, Ordering::Acquire).is_ok();
+            if ended {
+                *listener_state = ended_state;
+            }
+            ended
+        })
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.state.load(Ordering::Acquire) & 1 == 1
+    }
+
+    pub(crate) fn set_paused(&self, paused: bool) {
+        // while Starting the polling task picks Paused or Running itself
+        self.listener_state.send_if_modified(|state| {
+            self.paused.store(paused, Ordering::Relaxed);
+            match state {
+                ListenerState::Running | ListenerState::Paused => {
+                    let previous = *state;
+                    *state = if paused { ListenerState::Paused } else { ListenerState::Running };
+                    *state != previous
+                }
+                _ => false
+            }
+        });
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn listener_state(&self) -> ListenerState {
+        *self.listener_state.borrow()
+    }
+
+    pub(crate) fn watch(&self) -> watch::Receiver<ListenerState> {
+        self.listener_state.subscribe()
+    }
+}
+
+// stops the listener when the polling task ends, marking it Crashed if the task panicked
+pub(crate) struct StopOnExit {
+    pub(crate) run_state: Arc<RunState>,
+    pub(crate) generation: u64
+}
+
+impl Drop for StopOnExit {
+    fn drop(&mut self) {
+        let listener_state = if std::thread::panicking() { ListenerState::Crashed } else { ListenerState::Stopped };
+        self.run_state.end(self.generation, listener_state);
+    }
+}
+
+
+
+// a cheap, cloneable way to stop, pause and watch a listener without .lock().await, from KeyListener::handle
+#[derive(Clone)]
+pub struct ListenerHandle {
+    run_state: Arc<RunState>,
+    stats: Arc<StatsCounters>,
+    dropped_events: Arc<AtomicU64>
+}
+
+impl ListenerHandle {
+    pub(crate) fn new(run_state: Arc<RunState>, stats: Arc<StatsCounters>, dropped_events: Arc<AtomicU64>) -> Self {
+        ListenerHandle { run_state, stats, dropped_events }
+    }
+
+    // like KeyListener::quit, start is still done through the listener
+    pub fn quit(&self) {
+        self.run_state.quit();
+    }
+
+    // keys are not polled while paused and keep their last state, keys pressed or released meanwhile send
+    // their event once polling resumes
+    pub fn pause(&self) {
+        self.run_state.set_paused(true);
+    }
+
+    pub fn resume(&self) {
+        self.run_state.set_paused(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.run_state.is_paused()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.run_state.is_running()
+    }
+
+    pub fn stats(&self) -> ListenerStats {
+        self.stats.snapshot(self.dropped_events.load(Ordering::Relaxed))
+    }
+
+    pub fn state(&self) -> ListenerState {
+        self.run_state.listener_state()
+    }
+
+    // the current state and every transition after it, see KeyListener::watch_state
+    pub fn watch_state(&self) -> watch::Receiver<ListenerState> {
+        self.run_state.watch()
+    }
+}
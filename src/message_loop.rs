@@ -0,0 +1,212 @@
+/*
+
+Message loops
+
+Hooks and Raw Input deliver their events through the message queue of the thread that installed / registered them,
+so that thread has to pump messages. Each backend gets a hidden message-only window on its thread: the window is
+where Raw Input and timers arrive, and closing it (WM_CLOSE, which can be posted from any thread) runs the
+backend's teardown on the thread that owns it.
+
+By default the window lives on a thread of its own that does nothing but pump messages, and ends with the window.
+With MessageLoop::CurrentThread the window is created on the calling thread instead, for applications that already
+run a message loop there: their loop then drives the backend. pump_messages and run_message_loop cover threads
+that peek for messages or have no loop yet.
+
+https://learn.microsoft.com/en-us/windows/win32/winmsg/about-messages-and-message-queues
+https://learn.microsoft.com/en-us/windows/win32/winmsg/window-features#message-only-windows
+
+*/
+
+
+use crate::error::KeyListenerError;
+
+use windows::core::w;
+use windows::Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW, PeekMessageW, PostMessageW,
+    PostQuitMessage, RegisterClassW, TranslateMessage, HWND_MESSAGE, MSG, PM_REMOVE, WINDOW_EX_STYLE, WINDOW_STYLE,
+    WM_CLOSE, WM_DESTROY, WM_QUIT, WNDCLASSW
+};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::mpsc::sync_channel;
+use std::thread;
+
+
+
+// which thread pumps the messages of a hook or Raw Input listener
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MessageLoop {
+    // a thread of its own, started with the listener and ending when it quits
+    #[default]
+    Dedicated,
+    // the calling thread, which has to keep pumping messages (its own loop, pump_messages or run_message_loop)
+    // for as long as the listener runs. Events are late or lost while it does not
+    CurrentThread
+}
+
+pub(crate) type MessageHandler = Box<dyn FnMut(HWND, u32, WPARAM, LPARAM)>;
+
+// what a backend does with its window, created on the thread owning it
+pub(crate) struct WindowHandlers {
+    // sees every message before DefWindowProc does
+    pub(crate) on_message: Option<MessageHandler>,
+    pub(crate) on_close: Option<Box<dyn FnOnce()>>
+}
+
+struct OpenWindow {
+    handlers: WindowHandlers,
+    // the thread only exists for this window
+    dedicated: bool
+}
+
+thread_local! {
+    static OPEN_WINDOWS: RefCell<HashMap<isize, OpenWindow>> = RefCell::new(HashMap::new());
+}
+
+unsafe extern "system" fn message_window_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    let key = hwnd.0 as isize;
+    match msg {
+        WM_CLOSE => {
+            let on_close = OPEN_WINDOWS.with_borrow_mut(|windows| windows.get_mut(&key).and_then(|window| window.handlers.on_close.take()));
+            if let Some(on_close) = on_close {
+                on_close();
+            }
+            unsafe {
+                let _ = DestroyWindow(hwnd);
+            }
+            return LRESULT(0);
+        }
+        WM_DESTROY => {
+            let window = OPEN_WINDOWS.with_borrow_mut(|windows| windows.remove(&key));
+            if window.is_some_and(|window| window.dedicated) {
+                unsafe { PostQuitMessage(0) };
+            }
+        }
+        _ => {
+            // taken out while it runs, a handler sending messages to its own window must not find it borrowed
+            let on_message = OPEN_WINDOWS.with_borrow_mut(|windows| windows.get_mut(&key).and_then(|window| window.handlers.on_message.take()));
+            if let Some(mut on_message) = on_message {
+                on_message(hwnd, msg, wparam, lparam);
+                OPEN_WINDOWS.with_borrow_mut(|windows| {
+                    if let Some(window) = windows.get_mut(&key) {
+                        window.handlers.on_message = Some(on_message);
+                    }
+                });
+            }
+        }
+    }
+    unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) }
+}
+
+fn create_message_window() -> windows::core::Result<HWND> {
+    unsafe {
+        let instance = GetModuleHandleW(None).map(|module| HINSTANCE(module.0))?;
+        let class_name = w!("win_key_event_message_window");
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(message_window_proc),
+            hInstance: instance,
+            lpszClassName: class_name,
+            ..Default::default()
+        };
+        // fails harmlessly when an earlier window registered the class already
+        RegisterClassW(&class);
+        CreateWindowExW(
+            WINDOW_EX_STYLE(0), class_name, None, WINDOW_STYLE(0), 0, 0, 0, 0, Some(HWND_MESSAGE), None,
+            Some(instance), None
+        )
+    }
+}
+
+// creates the window and runs start with it on the current thread
+fn open_on_current_thread<S>(start: S, dedicated: bool) -> Result<HWND, KeyListenerError>
+where
+    S: FnOnce(HWND) -> Result<WindowHandlers, KeyListenerError>
+{
+    let window = create_message_window().map_err(KeyListenerError::MessageWindow)?;
+    match start(window) {
+        Ok(handlers) => {
+            OPEN_WINDOWS.with_borrow_mut(|windows| windows.insert(window.0 as isize, OpenWindow { handlers, dedicated }));
+            Ok(window)
+        }
+        Err(error) => {
+            unsafe {
+                let _ = DestroyWindow(window);
+            }
+            Err(error)
+        }
+    }
+}
+
+
+
+// a backend's window, closed from any thread to stop the backend
+pub(crate) struct MessageWindow {
+    // HWND is not Send, the handle itself can be used from any thread
+    window: isize
+}
+
+impl MessageWindow {
+    pub(crate) fn close(&self) {
+        unsafe {
+            let _ = PostMessageW(Some(HWND(self.window as *mut c_void)), WM_CLOSE, WPARAM(0), LPARAM(0));
+        }
+    }
+}
+
+// returns once start has run, with its error if it failed
+pub(crate) fn open_message_window<S>(message_loop: MessageLoop, start: S) -> Result<MessageWindow, KeyListenerError>
+where
+    S: FnOnce(HWND) -> Result<WindowHandlers, KeyListenerError> + Send + 'static
+{
+    let window = match message_loop {
+        MessageLoop::CurrentThread => open_on_current_thread(start, false)?,
+        MessageLoop::Dedicated => {
+            let (result_sender, result_receiver) = sync_channel(1);
+            thread::spawn(move || {
+                let opened = open_on_current_thread(start, true);
+                let is_open = opened.is_ok();
+                let _ = result_sender.send(opened.map(|window| window.0 as isize));
+                if is_open {
+                    run_message_loop();
+                }
+            });
+            let window = result_receiver.recv().expect("the message thread reports before exiting")?;
+            HWND(window as *mut c_void)
+        }
+    };
+    Ok(MessageWindow { window: window.0 as isize })
+}
+
+
+
+// handles the messages waiting on the current thread without blocking, for loops that peek (games, ...) instead of
+// waiting for messages. false once WM_QUIT was received
+pub fn pump_messages() -> bool {
+    let mut msg = MSG::default();
+    unsafe {
+        while PeekMessageW(&mut msg, None, 0, 0, PM_REMOVE).as_bool() {
+            if msg.message == WM_QUIT {
+                return false;
+            }
+            let _ = TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+    true
+}
+
+// handles messages on the current thread until WM_QUIT, for a thread that does nothing else
+pub fn run_message_loop() {
+    let mut msg = MSG::default();
+    unsafe {
+        // 0 on WM_QUIT, -1 on error
+        while GetMessageW(&mut msg, None, 0, 0).0 > 0 {
+            let _ = TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+/*
+
+Mock backend, enabled with the "mock" feature
+
+Synthetic events pushed here go through the same pipeline as polled ones: filters, modifier modes, the
+foreground filter, privacy and text mode, modifier gestures, window context, state changes, callbacks, subscribers
+and everything built on them. Together with KeyListenerBuilder::simulated this allows tests that
+run without a keyboard, e.g. on CI.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+use crate::poll::KeyStates;
+use crate::tokio_listener::EventInjector;
+
+use std::sync::Arc;
+
+
+
+#[derive(Clone)]
+pub struct MockBackend {
+    injector: Arc<EventInjector>,
+    key_states: Arc<KeyStates>
+}
+
+impl MockBackend {
+    pub(crate) fn new(injector: EventInjector, key_states: Arc<KeyStates>) -> Self {
+        MockBackend { injector: Arc::new(injector), key_states }
+    }
+
+    // like a poll seeing vk go down: only keys the listener watches are reported, and only if they were up
+    pub async fn press(&self, vk: impl Into<VirtualKey>) {
+        let vk = vk.into();
+        if self.key_states.set(vk.code(), true) {
+            self.injector.inject(KeyEvent::Press(vk)).await;
+        }
+    }
+
+    pub async fn release(&self, vk: impl Into<VirtualKey>) {
+        let vk = vk.into();
+        if self.key_states.set(vk.code(), false) {
+            self.injector.inject(KeyEvent::Release(vk)).await;
+        }
+    }
+
+    pub async fn tap(&self, vk: impl Into<VirtualKey>) {
+        let vk = vk.into();
+        self.press(vk).await;
+        self.release(vk).await;
+    }
+
+    // any event as is, key state (is_pressed, pressed_keys) is left alone
+    pub async fn push(&self, key_event: KeyEvent) {
+        self.injector.inject(key_event).await;
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use crate::KeyListener;
+    use super::*;
+
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn injected_events_get_gestures_and_state_changes() {
+        let key_listener = KeyListener::builder()
+            .vk_codes([VirtualKey::LSHIFT, VirtualKey(0x41)])
+            .simulated()
+            .state_changes(true)
+            .modifier_gestures(Duration::from_secs(1))
+            .spawn(|_| {}, |_| {})
+            .unwrap();
+        let key_listener = key_listener.lock().await;
+        let mut key_events = key_listener.subscribe();
+        let mut state_changes = key_listener.subscribe_state_changes();
+        let mock_backend = key_listener.mock_backend();
+
+        mock_backend.tap(VirtualKey::LSHIFT).await;
+        assert_eq!(key_events.recv().await.unwrap(), KeyEvent::Press(VirtualKey::LSHIFT));
+        assert_eq!(key_events.recv().await.unwrap(), KeyEvent::Release(VirtualKey::LSHIFT));
+        assert_eq!(key_events.recv().await.unwrap(), KeyEvent::ModifierTap(VirtualKey::LSHIFT));
+        assert!(state_changes.recv().await.unwrap().0.contains(&VirtualKey::LSHIFT));
+        assert!(state_changes.recv().await.unwrap().0.is_empty());
+    }
+
+    #[tokio::test]
+    async fn injected_events_are_numbered() {
+        let key_listener = KeyListener::builder().vk_codes([0x41]).simulated().spawn(|_| {}, |_| {}).unwrap();
+        let key_listener = key_listener.lock().await;
+        let mut sequenced = key_listener.subscribe_sequenced();
+
+        key_listener.mock_backend().tap(0x41).await;
+        assert_eq!(sequenced.recv().await.unwrap().seq, 0);
+        assert_eq!(sequenced.recv().await.unwrap().seq, 1);
+        assert_eq!(key_listener.next_sequence_number(), 2);
+    }
+}
@@ -0,0 +1,131 @@
+/*
+
+Modifier-only gestures
+
+Taps of a modifier on its own: pressed and released within the gesture window with no other key pressed in
+between, like tapping Alt to open a launcher. A second tap of the same modifier within the window of the first
+one's release is a double tap, like double tapping Ctrl. Both come after the release they end with.
+The generic and sided codes of a modifier (VK_SHIFT and VK_LSHIFT, ...) are often watched together and report one
+physical press twice, so keys are compared by modifier group rather than by code.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+
+use std::time::{Duration, Instant};
+
+
+
+// the Win keys have no generic code, each is a group of its own
+const MODIFIER_GROUPS: [&[VirtualKey]; 5] = [
+    &[VirtualKey::SHIFT, VirtualKey::LSHIFT, VirtualKey::RSHIFT],
+    &[VirtualKey::CONTROL, VirtualKey::LCONTROL, VirtualKey::RCONTROL],
+    &[VirtualKey::MENU, VirtualKey::LMENU, VirtualKey::RMENU],
+    &[VirtualKey::LWIN],
+    &[VirtualKey::RWIN]
+];
+
+fn modifier_group(vk: VirtualKey) -> Option<usize> {
+    MODIFIER_GROUPS.iter().position(|group| group.contains(&vk))
+}
+
+fn same_group(a: VirtualKey, b: VirtualKey) -> bool {
+    modifier_group(a).is_some() && modifier_group(a) == modifier_group(b)
+}
+
+pub(crate) struct ModifierGestures {
+    window: Duration,
+    // the modifier held on its own so far and when it went down
+    candidate: Option<(VirtualKey, Instant)>,
+    // the last tap, a double tap if the same modifier taps again in time
+    last_tap: Option<(VirtualKey, Instant)>
+}
+
+impl ModifierGestures {
+    pub(crate) fn new(window: Duration) -> Self {
+        ModifierGestures { window, candidate: None, last_tap: None }
+    }
+
+    // follows each release ending a gesture with its ModifierTap / ModifierDoubleTap
+    pub(crate) fn process(&mut self, key_events: Vec<KeyEvent>) -> Vec<KeyEvent> {
+        let mut with_gestures = Vec::with_capacity(key_events.len());
+        for key_event in key_events {
+            with_gestures.push(key_event);
+            match key_event {
+                KeyEvent::Press(vk) if modifier_group(vk).is_some() && self.candidate.is_none() => {
+                    self.candidate = Some((vk, Instant::now()));
+                }
+                // the same press through another code of the group
+                KeyEvent::Press(vk) if self.candidate.is_some_and(|(candidate, _)| same_group(candidate, vk)) => {}
+                // any other press makes the held modifier part of a shortcut
+                KeyEvent::Press(_) => {
+                    self.candidate = None;
+                    self.last_tap = None;
+                }
+                KeyEvent::Release(vk) => {
+                    if let Some((candidate, pressed)) = self.candidate.take()
+                        && same_group(candidate, vk)
+                        && pressed.elapsed() <= self.window
+                    {
+                        with_gestures.extend(self.tap(candidate));
+                    }
+                }
+                _ => {}
+            }
+        }
+        with_gestures
+    }
+
+    fn tap(&mut self, vk: VirtualKey) -> Vec<KeyEvent> {
+        let now = Instant::now();
+        match self.last_tap.take() {
+            Some((last, at)) if same_group(last, vk) && now - at <= self.window => {
+                vec![KeyEvent::ModifierTap(vk), KeyEvent::ModifierDoubleTap(vk)]
+            }
+            _ => {
+                self.last_tap = Some((vk, now));
+                vec![KeyEvent::ModifierTap(vk)]
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poll::default_vk_codes;
+
+    // the codes of vk's group in the default key list, in the order they are polled
+    fn watched_codes(vk: VirtualKey) -> Vec<VirtualKey> {
+        default_vk_codes().into_iter().map(VirtualKey).filter(|watched| same_group(*watched, vk)).collect()
+    }
+
+    #[test]
+    fn taps_with_the_default_key_list() {
+        let mut gestures = ModifierGestures::new(Duration::from_secs(1));
+        // a left shift press is polled as VK_LSHIFT and VK_SHIFT
+        let codes: Vec<VirtualKey> = watched_codes(VirtualKey::SHIFT).into_iter().filter(|code| *code != VirtualKey::RSHIFT).collect();
+        assert_eq!(codes.len(), 2, "generic and sided codes are both watched by default");
+
+        gestures.process(codes.iter().map(|code| KeyEvent::Press(*code)).collect());
+        let first = gestures.process(codes.iter().map(|code| KeyEvent::Release(*code)).collect());
+        assert_eq!(first.iter().filter(|key_event| matches!(key_event, KeyEvent::ModifierTap(_))).count(), 1);
+
+        gestures.process(codes.iter().map(|code| KeyEvent::Press(*code)).collect());
+        let second = gestures.process(codes.iter().map(|code| KeyEvent::Release(*code)).collect());
+        assert_eq!(second.iter().filter(|key_event| matches!(key_event, KeyEvent::ModifierDoubleTap(_))).count(), 1);
+    }
+
+    #[test]
+    fn shortcut_is_not_a_tap() {
+        let mut gestures = ModifierGestures::new(Duration::from_secs(1));
+        let key_events = gestures.process(vec![
+            KeyEvent::Press(VirtualKey::LCONTROL), KeyEvent::Press(VirtualKey::CONTROL), KeyEvent::Press(VirtualKey(0x43)),
+            KeyEvent::Release(VirtualKey(0x43)), KeyEvent::Release(VirtualKey::LCONTROL), KeyEvent::Release(VirtualKey::CONTROL)
+        ]);
+        assert!(!key_events.iter().any(|key_event| matches!(key_event, KeyEvent::ModifierTap(_))));
+    }
+}
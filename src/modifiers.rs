@@ -0,0 +1,114 @@
+/*
+
+Left / right modifier handling
+
+Shift, Ctrl and Alt each have a generic key code alongside their left and right codes, so watching all three
+reports one physical press twice. ModifierMode rewrites the watched keys (and events) so each modifier is reported
+either only by side, or as a single logical key. The Win keys have no generic code, so VK_LWIN stands in for it.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+use crate::pipeline::Stage;
+
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ModifierMode {
+    // watch exactly the codes given, generic and sided codes both report
+    #[default]
+    AsWatched,
+    // generic codes are replaced by their left and right codes
+    Sided,
+    // one event per physical transition with the codes as watched otherwise: a generic code is only replaced by its
+    // left and right codes when a sided code of the same modifier is watched too
+    Deduplicated,
+    // left and right presses are merged into one logical key, pressed while either side is down
+    Coalesced
+}
+
+struct ModifierGroup {
+    logical: VirtualKey,
+    left: VirtualKey,
+    right: VirtualKey
+}
+
+const MODIFIER_GROUPS: [ModifierGroup; 4] = [
+    ModifierGroup { logical: VirtualKey::SHIFT, left: VirtualKey::LSHIFT, right: VirtualKey::RSHIFT },
+    ModifierGroup { logical: VirtualKey::CONTROL, left: VirtualKey::LCONTROL, right: VirtualKey::RCONTROL },
+    ModifierGroup { logical: VirtualKey::MENU, left: VirtualKey::LMENU, right: VirtualKey::RMENU },
+    ModifierGroup { logical: VirtualKey::LWIN, left: VirtualKey::LWIN, right: VirtualKey::RWIN }
+];
+
+fn find_group(vk: VirtualKey) -> Option<(usize, &'static ModifierGroup)> {
+    MODIFIER_GROUPS.iter()
+        .enumerate()
+        .find(|(_, group)| vk == group.logical || vk == group.left || vk == group.right)
+}
+
+
+
+impl ModifierMode {
+    // the key codes that actually have to be polled, without duplicates
+    pub(crate) fn apply_to_vk_codes(&self, vk_codes: Vec<i32>) -> Vec<i32> {
+        if *self == ModifierMode::AsWatched {
+            return vk_codes;
+        }
+
+        let mut applied = Vec::with_capacity(vk_codes.len());
+        for &vk_code in &vk_codes {
+            // Sided and Coalesced poll the sided codes, coalescing happens afterwards in the pipeline stage
+            let expanded = match find_group(VirtualKey(vk_code)) {
+                Some((_, group)) if *self == ModifierMode::Deduplicated => {
+                    let sided_watched = vk_codes.iter().any(|&watched| watched == group.left.0 || watched == group.right.0);
+                    // VK_LWIN stands in for the Win keys' generic code, and is a sided code itself
+                    if VirtualKey(vk_code) == group.logical && group.logical != group.left && sided_watched {
+                        vec![group.left.0, group.right.0]
+                    } else {
+                        vec![vk_code]
+                    }
+                }
+                Some((_, group)) => vec![group.left.0, group.right.0],
+                None => vec![vk_code]
+            };
+            for vk_code in expanded {
+                if !applied.contains(&vk_code) {
+                    applied.push(vk_code);
+                }
+            }
+        }
+        applied
+    }
+
+    pub(crate) fn stage(&self) -> Option<Stage> {
+        if *self != ModifierMode::Coalesced {
+            return None;
+        }
+
+        // bit 0 = left held, bit 1 = right held, per modifier group
+        let mut held = [0u8; MODIFIER_GROUPS.len()];
+        Some(Box::new(move |key_event| {
+            let Some(vk) = key_event.vk() else {
+                return Some(key_event);
+            };
+            let Some((i, group)) = find_group(vk) else {
+                return Some(key_event);
+            };
+            let side = if vk == group.right { 0b10 } else { 0b01 };
+            let was_held = held[i] != 0;
+
+            match key_event {
+                KeyEvent::Press(_) => held[i] |= side,
+                KeyEvent::Release(_) => held[i] &= !side,
+                _ => return Some(key_event)
+            }
+
+            match (was_held, held[i] != 0) {
+                (false, true) => Some(KeyEvent::Press(group.logical)),
+                (true, false) => Some(KeyEvent::Release(group.logical)),
+                _ => None
+            }
+        }))
+    }
+}
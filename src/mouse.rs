@@ -0,0 +1,132 @@
+/*
+
+Mouse listener using a WH_MOUSE_LL hook
+
+Reports cursor movement, wheel ticks and button presses. Unlike key polling this sees every mouse event, so the
+callback is called from a separate dispatch thread to keep the hook itself fast.
+
+https://learn.microsoft.com/en-us/windows/win32/winmsg/lowlevelmouseproc
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+use crate::hook::{HookThread, note_event_time, spawn_hook_thread};
+use crate::message_loop::MessageLoop;
+use crate::error::KeyListenerError;
+
+use windows::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CallNextHookEx, HC_ACTION, LLMHF_INJECTED, MSLLHOOKSTRUCT, WH_MOUSE_LL, XBUTTON1,
+    WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_MOUSEHWHEEL,
+    WM_LBUTTONDOWN, WM_LBUTTONUP, WM_RBUTTONDOWN, WM_RBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP, WM_XBUTTONDOWN, WM_XBUTTONUP
+};
+
+use std::cell::RefCell;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::mpsc::{Sender, channel};
+use std::thread;
+use std::time::Duration;
+
+
+
+// wheel delta of one notch, finer grained wheels / touchpads report fractions of it
+pub const WHEEL_DELTA: i16 = 120;
+
+pub struct MouseListener {
+    hook_thread: HookThread
+}
+
+impl MouseListener {
+    pub fn quit(&self) {
+        self.hook_thread.stop();
+    }
+
+    // times the hook was installed again after Windows removed it for being too slow, see hook_gap
+    pub fn hook_reinstalls(&self) -> u32 {
+        self.hook_thread.reinstalls()
+    }
+
+    // roughly how long events went unseen before the last reinstall
+    pub fn hook_gap(&self) -> Option<Duration> {
+        self.hook_thread.last_gap()
+    }
+}
+
+
+
+struct MouseHookState {
+    sender: Sender<KeyEvent>,
+    last_position: Option<(i32, i32)>
+}
+
+thread_local! {
+    static MOUSE_HOOK_STATE: RefCell<Option<MouseHookState>> = const { RefCell::new(None) };
+}
+
+unsafe extern "system" fn mouse_hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code == HC_ACTION as i32 {
+        let info = unsafe { &*(lparam.0 as *const MSLLHOOKSTRUCT) };
+        note_event_time(info.time, info.flags & LLMHF_INJECTED != 0);
+        MOUSE_HOOK_STATE.with_borrow_mut(|state| {
+            if let Some(state) = state.as_mut()
+                && let Some(key_event) = state.key_event_for(wparam.0 as u32, info)
+            {
+                let _ = state.sender.send(key_event);
+            }
+        });
+    }
+    unsafe { CallNextHookEx(None, code, wparam, lparam) }
+}
+
+impl MouseHookState {
+    fn key_event_for(&mut self, message: u32, info: &MSLLHOOKSTRUCT) -> Option<KeyEvent> {
+        // the high word of mouseData holds the wheel delta or which x button
+        let high_word = (info.mouseData >> 16) as u16;
+        let x_button = if high_word == XBUTTON1 { VirtualKey::XBUTTON1 } else { VirtualKey::XBUTTON2 };
+
+        match message {
+            WM_MOUSEMOVE => {
+                let (x, y) = (info.pt.x, info.pt.y);
+                // deltas come from the cursor position, so they stop at the screen edges
+                let (dx, dy) = self.last_position.map_or((0, 0), |(last_x, last_y)| (x - last_x, y - last_y));
+                self.last_position = Some((x, y));
+                Some(KeyEvent::MouseMove { x, y, dx, dy })
+            }
+            WM_MOUSEWHEEL => Some(KeyEvent::MouseWheel { delta: high_word as i16, horizontal: false }),
+            WM_MOUSEHWHEEL => Some(KeyEvent::MouseWheel { delta: high_word as i16, horizontal: true }),
+            WM_LBUTTONDOWN => Some(KeyEvent::Press(VirtualKey::LBUTTON)),
+            WM_LBUTTONUP => Some(KeyEvent::Release(VirtualKey::LBUTTON)),
+            WM_RBUTTONDOWN => Some(KeyEvent::Press(VirtualKey::RBUTTON)),
+            WM_RBUTTONUP => Some(KeyEvent::Release(VirtualKey::RBUTTON)),
+            WM_MBUTTONDOWN => Some(KeyEvent::Press(VirtualKey::MBUTTON)),
+            WM_MBUTTONUP => Some(KeyEvent::Release(VirtualKey::MBUTTON)),
+            WM_XBUTTONDOWN => Some(KeyEvent::Press(x_button)),
+            WM_XBUTTONUP => Some(KeyEvent::Release(x_button)),
+            _ => None
+        }
+    }
+}
+
+
+
+// callback receives MouseMove, MouseWheel and mouse button Press / Release events
+pub fn init_mouse_listener<F>(mut callback: F) -> Result<MouseListener, KeyListenerError>
+where
+    F: FnMut(KeyEvent) + Send + 'static
+{
+    let (sender, receiver) = channel();
+
+    let setup = move || MOUSE_HOOK_STATE.set(Some(MouseHookState { sender, last_position: None }));
+    let hook_thread = spawn_hook_thread(WH_MOUSE_LL, mouse_hook_proc, MessageLoop::Dedicated, setup, || MOUSE_HOOK_STATE.set(None))?;
+
+    // ends once the hook thread exits and drops its sender
+    thread::spawn(move || {
+        for key_event in receiver {
+            // a panicking callback must not end the dispatch thread, the hook would keep running unheard
+            let _ = catch_unwind(AssertUnwindSafe(|| callback(key_event)));
+        }
+    });
+
+    Ok(MouseListener { hook_thread })
+}
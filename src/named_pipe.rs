@@ -0,0 +1,157 @@
+/*
+
+Named pipe server, enabled with the "named-pipe" feature
+
+Serves a listener's events to other processes (Electron front ends, Python scripts, ...) over a Windows named
+pipe such as \\.\pipe\win-key-event. Every client gets every event from the time it connected, in either session
+format: a line of JSON per event, or the binary header followed by a record per event.
+Keystrokes are not for everyone on the machine: every pipe instance is created with a DACL granting access to the
+user the process runs as only, and clients connecting over the network are refused.
+
+https://learn.microsoft.com/en-us/windows/win32/ipc/named-pipe-security-and-access-rights
+
+*/
+
+
+use crate::KeyEvent;
+use crate::recorder::{RedactedField, Redaction, SessionFormat, encode_event};
+use crate::session_format::HEADER;
+use crate::tokio_listener::KeyListener;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use windows::core::{PCWSTR, PWSTR};
+use windows::Win32::Foundation::{CloseHandle, HANDLE, HLOCAL, LocalFree};
+use windows::Win32::Security::{
+    GetTokenInformation, PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES, TOKEN_QUERY, TOKEN_USER, TokenUser
+};
+use windows::Win32::Security::Authorization::{
+    ConvertSidToStringSidW, ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1
+};
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+use std::io;
+use std::sync::Arc;
+
+
+
+// a security descriptor allowing the current user only, in the form SECURITY_ATTRIBUTES points to
+struct UserOnlySecurity(PSECURITY_DESCRIPTOR);
+
+// the descriptor is never written to once built
+unsafe impl Send for UserOnlySecurity {}
+unsafe impl Sync for UserOnlySecurity {}
+
+impl UserOnlySecurity {
+    fn new() -> io::Result<Self> {
+        unsafe {
+            let mut token = HANDLE::default();
+            OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)?;
+            let mut length = 0;
+            // fails with the length needed
+            let _ = GetTokenInformation(token, TokenUser, None, 0, &mut length);
+            // u64s keep TOKEN_USER's pointers aligned
+            let mut buffer = vec![0u64; (length as usize).div_ceil(8)];
+            let read = GetTokenInformation(token, TokenUser, Some(buffer.as_mut_ptr().cast()), length, &mut length);
+            let _ = CloseHandle(token);
+            read?;
+
+            let user = &*buffer.as_ptr().cast::<TOKEN_USER>();
+            let mut sid = PWSTR::null();
+            ConvertSidToStringSidW(user.User.Sid, &mut sid)?;
+            let sid_string = sid.to_string();
+            let _ = LocalFree(Some(HLOCAL(sid.0.cast())));
+            let sid_string = sid_string.map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+            // protected (not inherited) DACL with a single entry: generic all for the user
+            let sddl: Vec<u16> = format!("D:P(A;;GA;;;{})", sid_string).encode_utf16().chain([0]).collect();
+            let mut descriptor = PSECURITY_DESCRIPTOR::default();
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(PCWSTR(sddl.as_ptr()), SDDL_REVISION_1, &mut descriptor, None)?;
+            Ok(UserOnlySecurity(descriptor))
+        }
+    }
+
+    fn create(&self, options: &ServerOptions, name: &str) -> io::Result<NamedPipeServer> {
+        let mut attributes = SECURITY_ATTRIBUTES {
+            nLength: size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: self.0.0,
+            bInheritHandle: false.into()
+        };
+        unsafe { options.create_with_security_attributes_raw(name, (&mut attributes as *mut SECURITY_ATTRIBUTES).cast()) }
+    }
+}
+
+impl Drop for UserOnlySecurity {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = LocalFree(Some(HLOCAL(self.0.0)));
+        }
+    }
+}
+
+
+
+pub struct PipeServerBuilder {
+    name: String,
+    format: SessionFormat,
+    redaction: Redaction
+}
+
+impl PipeServerBuilder {
+    // a full pipe name, e.g. r"\\.\pipe\win-key-event"
+    pub fn new(name: impl Into<String>) -> Self {
+        PipeServerBuilder { name: name.into(), format: SessionFormat::JsonLines, redaction: Redaction::default() }
+    }
+
+    pub fn format(mut self, format: SessionFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn redact(mut self, field: RedactedField) -> Self {
+        self.redaction.fields.insert(field);
+        self
+    }
+
+    // fails if the pipe cannot be created, e.g. because another server already owns the name. The returned
+    // task accepts clients until it is aborted or creating the next pipe instance fails
+    pub fn serve(self, key_listener: &KeyListener) -> io::Result<JoinHandle<io::Result<()>>> {
+        let security = UserOnlySecurity::new()?;
+        let mut options = ServerOptions::new();
+        options.reject_remote_clients(true);
+        let mut server = security.create(options.clone().first_pipe_instance(true), &self.name)?;
+        let events = key_listener.broadcast_sender();
+        let settings = Arc::new(self);
+
+        Ok(tokio::spawn(async move {
+            loop {
+                server.connect().await?;
+                let client = server;
+                // the next instance is ready before this client is served, so no client finds the pipe missing
+                server = security.create(&options, &settings.name)?;
+                tokio::spawn(serve_client(client, events.subscribe(), settings.clone()));
+            }
+        }))
+    }
+}
+
+// until the client disconnects or the listener is gone
+async fn serve_client(mut client: NamedPipeServer, mut receiver: broadcast::Receiver<KeyEvent>, settings: Arc<PipeServerBuilder>) {
+    if settings.format == SessionFormat::Binary && client.write_all(&HEADER).await.is_err() {
+        return;
+    }
+    let mut frame = Vec::new();
+    loop {
+        let key_event = match receiver.recv().await {
+            Ok(key_event) => key_event,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return
+        };
+        frame.clear();
+        encode_event(settings.format, &key_event, &settings.redaction, &mut frame);
+        if client.write_all(&frame).await.is_err() {
+            return;
+        }
+    }
+}
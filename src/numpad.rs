@@ -0,0 +1,111 @@
+/*
+
+NumLock aware numpad interpretation
+
+With NumLock on the numpad reports VK_NUMPAD0 - VK_NUMPAD9 / VK_DECIMAL, with it off (or while shift is held)
+the same keys report navigation codes (VK_INSERT, VK_END, VK_DOWN, ...). The navigation codes are shared with the
+dedicated navigation keys, so a VK_HOME press can't be attributed to the numpad from the key code alone: that takes
+the extended flag of a hook or raw input event, which is set for the dedicated keys only. A navigation code means
+navigation whatever the NumLock state, with NumLock on it comes from a dedicated key or from the numpad with shift.
+
+*/
+
+
+use crate::VirtualKey;
+use crate::poll::is_toggled;
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NumpadKey {
+    Digit(u8),
+    Decimal,
+    Navigation(VirtualKey)
+}
+
+// (NumLock on code, NumLock off code) for each numpad key
+const NUMPAD_PAIRS: [(VirtualKey, VirtualKey); 11] = [
+    (VirtualKey(0x60), VirtualKey(0x2D)), // 0, insert
+    (VirtualKey(0x61), VirtualKey(0x23)), // 1, end
+    (VirtualKey(0x62), VirtualKey(0x28)), // 2, down
+    (VirtualKey(0x63), VirtualKey(0x22)), // 3, page down
+    (VirtualKey(0x64), VirtualKey(0x25)), // 4, left
+    (VirtualKey(0x65), VirtualKey(0x0C)), // 5, clear
+    (VirtualKey(0x66), VirtualKey(0x27)), // 6, right
+    (VirtualKey(0x67), VirtualKey(0x24)), // 7, home
+    (VirtualKey(0x68), VirtualKey(0x26)), // 8, up
+    (VirtualKey(0x69), VirtualKey(0x21)), // 9, page up
+    (VirtualKey(0x6E), VirtualKey(0x2E))  // decimal, delete
+];
+
+fn numpad_pair(vk: VirtualKey) -> Option<(VirtualKey, VirtualKey)> {
+    NUMPAD_PAIRS.into_iter().find(|&(number, navigation)| vk == number || vk == navigation)
+}
+
+fn number_key(number: VirtualKey) -> NumpadKey {
+    match number.0 {
+        0x60..=0x69 => NumpadKey::Digit((number.0 - 0x60) as u8),
+        _ => NumpadKey::Decimal
+    }
+}
+
+
+
+impl VirtualKey {
+    // true for the NumLock on numpad codes (VK_NUMPAD0 - VK_NUMPAD9, VK_DECIMAL)
+    pub fn is_numpad_number(&self) -> bool {
+        NUMPAD_PAIRS.iter().any(|(number, _)| number == self)
+    }
+
+    // the code the same numpad key reports with NumLock in the other state, e.g. VK_NUMPAD7 <-> VK_HOME
+    pub fn numpad_counterpart(&self) -> Option<VirtualKey> {
+        numpad_pair(*self).map(|(number, navigation)| if number == *self { navigation } else { number })
+    }
+
+    // both readings of a numpad (or numpad navigation) code: (digit / decimal, navigation)
+    pub fn numpad_interpretations(&self) -> Option<(NumpadKey, NumpadKey)> {
+        numpad_pair(*self).map(|(number, navigation)| (number_key(number), NumpadKey::Navigation(navigation)))
+    }
+
+    // what a numpad key means with the given NumLock state. Numpad codes read as digits with NumLock on and as their
+    // navigation counterpart with it off, navigation codes always read as navigation (the dedicated Home key is
+    // never Digit(7))
+    pub fn numpad_key(&self, numlock_on: bool) -> Option<NumpadKey> {
+        self.numpad_interpretations().map(|(number, navigation)| match numlock_on && self.is_numpad_number() {
+            true => number,
+            false => navigation
+        })
+    }
+
+    // whether an event for this code came from the numpad, given the extended flag of a hook or raw input event
+    // (LLKHF_EXTENDED, RI_KEY_E0). Only the dedicated navigation keys are extended
+    pub fn is_from_numpad(&self, extended: bool) -> bool {
+        match numpad_pair(*self) {
+            Some((number, _)) => *self == number || *self == VirtualKey(0x0C) || !extended,
+            None => false
+        }
+    }
+
+    // numpad_key with the current NumLock state
+    pub fn current_numpad_key(&self) -> Option<NumpadKey> {
+        self.numpad_key(is_toggled(VirtualKey::NUMLOCK))
+    }
+}
+
+
+
+// adds the NumLock off codes of any watched numpad keys, so numpad presses are not missed while NumLock is off
+#[cfg(feature = "tokio")]
+pub(crate) fn with_numpad_navigation(vk_codes: Vec<i32>) -> Vec<i32> {
+    let mut with_navigation = vk_codes.clone();
+    for vk_code in vk_codes {
+        let vk = VirtualKey(vk_code);
+        if vk.is_numpad_number() {
+            let navigation = vk.numpad_counterpart().unwrap();
+            if !with_navigation.contains(&navigation.0) {
+                with_navigation.push(navigation.0);
+            }
+        }
+    }
+    with_navigation
+}
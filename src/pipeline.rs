@@ -0,0 +1,113 @@
+/*
+
+Event filtering / transforming stages, run in order between the poller and the event channel
+
+Each stage gets the event produced by the previous one and returns None to drop it, or Some to pass on a
+(possibly rewritten) event. Stages are called from the polling task, so they should be quick.
+The rate limiter runs after the last stage, event consumers after the rate limiter. Events a stage drops or a
+consumer takes count as suppressed, those the rate limiter holds back only once it discards them.
+
+*/
+
+
+use crate::KeyEvent;
+use crate::consumer::{Consumers, EventConsumer};
+use crate::rate_limit::RateLimiter;
+
+
+
+pub(crate) type Stage = Box<dyn FnMut(KeyEvent) -> Option<KeyEvent> + Send + 'static>;
+
+#[derive(Default)]
+pub(crate) struct Pipeline {
+    stages: Vec<Stage>,
+    rate_limiter: Option<RateLimiter>,
+    consumers: Consumers,
+    // events removed since take_suppressed
+    suppressed: u64
+}
+
+impl Pipeline {
+    pub(crate) fn push(&mut self, stage: Stage) {
+        self.stages.push(stage);
+    }
+
+    // for built in stages that must see raw polled events before any user stage
+    pub(crate) fn push_front(&mut self, stage: Stage) {
+        self.stages.insert(0, stage);
+    }
+
+    pub(crate) fn set_rate_limiter(&mut self, rate_limiter: RateLimiter) {
+        self.rate_limiter = Some(rate_limiter);
+    }
+
+    pub(crate) fn add_consumer(&mut self, priority: i32, consumer: Box<dyn EventConsumer>) {
+        self.consumers.add(priority, consumer);
+    }
+
+    pub(crate) fn process(&mut self, key_event: KeyEvent) -> Option<KeyEvent> {
+        let Some(key_event) = self.stages.iter_mut().try_fold(key_event, |key_event, stage| stage(key_event)) else {
+            self.suppressed += 1;
+            return None;
+        };
+        let key_event = match self.rate_limiter.as_mut() {
+            Some(rate_limiter) => rate_limiter.process(key_event)?,
+            None => key_event
+        };
+        let dispatched = self.consumers.dispatch(key_event);
+        self.suppressed += dispatched.is_none() as u64;
+        dispatched
+    }
+
+    // events held back by the rate limiter that can be sent now
+    pub(crate) fn flush(&mut self, mut emit: impl FnMut(KeyEvent)) {
+        if let Some(rate_limiter) = self.rate_limiter.as_mut() {
+            let (consumers, suppressed) = (&mut self.consumers, &mut self.suppressed);
+            rate_limiter.flush(|key_event| match consumers.dispatch(key_event) {
+                Some(key_event) => emit(key_event),
+                None => *suppressed += 1
+            });
+        }
+    }
+
+    pub(crate) fn take_suppressed(&mut self) -> u64 {
+        let discarded = self.rate_limiter.as_mut().map_or(0, RateLimiter::take_discarded);
+        std::mem::take(&mut self.suppressed) + discarded
+    }
+
+    // end of a polling round
+    pub(crate) fn tick(&mut self) {
+        self.consumers.tick();
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VirtualKey;
+    use crate::rate_limit::RateLimitPolicy;
+
+    #[test]
+    fn suppressed_counts_filtered_and_discarded_events_once() {
+        let mut pipeline = Pipeline::default();
+        pipeline.push(Box::new(|key_event| (key_event.vk() != Some(VirtualKey(0x42))).then_some(key_event)));
+        let mut rate_limiter = RateLimiter::default();
+        rate_limiter.set_policy(RateLimitPolicy::Coalesce);
+        rate_limiter.set_limit(1);
+        pipeline.set_rate_limiter(rate_limiter);
+
+        for vk_code in [0x41, 0x42, 0x41] {
+            pipeline.process(KeyEvent::Press(VirtualKey(vk_code)));
+            pipeline.process(KeyEvent::Release(VirtualKey(vk_code)));
+        }
+        // B's press and release, the second A tap is only held back
+        assert_eq!(pipeline.take_suppressed(), 2);
+
+        // flushing it (once the limit allows) suppresses nothing more
+        pipeline.flush(|_| {});
+        assert_eq!(pipeline.take_suppressed(), 0);
+    }
+}
@@ -0,0 +1,390 @@
+/*
+
+Runtime agnostic key polling
+
+KeyStates holds the watched keys and their last seen state, and is shared between a backend's
+polling loop and the listener handle given to the user. Backends only decide how to wait between polls.
+
+A few keys do not read like the others and are normalized here:
+- PrintScreen and Pause can go down and up between two polls (Windows takes screenshots on the press, and the
+  Pause key sends its release right after the press), so a press seen only through GetAsyncKeyState's "pressed
+  since the last call" bit is reported as a press and a release in the same round. The bit also remembers presses
+  from before the listener started, so it is not trusted on the first poll
+- ctrl + Pause reads as VK_CANCEL (Break) instead of VK_PAUSE, and is reported as Pause while ctrl is held
+- ctrl + NumLock reads as VK_PAUSE, because the Pause key's scan codes are those of ctrl + NumLock. A press read
+  through VK_PAUSE while ctrl is held is reported as NumLock, as is its release, one read through VK_CANCEL as Pause
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+
+use windows::Win32::UI::Input::KeyboardAndMouse::{GetAsyncKeyState, GetKeyState};
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+
+
+const KEY_DOWN_MASK: i16 = -32768; // using 0x8000 gives an overflow error, so directly state the negative int
+const KEY_TOGGLED_MASK: i16 = 0x0001;
+// GetAsyncKeyState only, shared with every other caller in the process, so only used for keys that need it
+const KEY_PRESSED_SINCE_MASK: i16 = 0x0001;
+
+// keys that can be pressed and released between two polls
+const TAP_KEYS: [VirtualKey; 2] = [VirtualKey::SNAPSHOT, VirtualKey::PAUSE];
+
+#[cfg(feature = "tokio")]
+pub(crate) const LOCK_KEYS: [VirtualKey; 3] = [VirtualKey::CAPITAL, VirtualKey::NUMLOCK, VirtualKey::SCROLL];
+
+pub(crate) const DEFAULT_POLLING_WAIT: u64 = 10;
+
+#[derive(Debug, PartialEq, Eq)]
+enum KeyState {
+    KeyPress,
+    KeyRelease,
+    // pressed and released since the last poll
+    KeyTap,
+    StaticDown,
+    StaticUp
+}
+
+
+
+pub(crate) fn default_vk_codes() -> Vec<i32> {
+    vec![
+        // 0 - 9
+        0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39,
+
+        // 0 - 9 (numpad)
+        0x60, 0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+
+        // a - z
+        0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4A, 0x4B, 0x4C, 0x4D, 0x4E, 0x4F,
+        0x50, 0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A,
+
+        // punctuation, symbols (not shift + num symbols)
+        // (shift normal)
+        0xBA, // : ;
+        0xBB, // + =
+        0xBC, // < ,
+        0xBD, // _ -
+        0xBE, // > .
+        0xBF, // ? /
+        0xC0, // ~ `
+        0xDB, // { [
+        0xDC, // | \
+        0xDD, // } ]
+        0xDE, // " '
+
+        0x20, // space
+        0x0D, // enter
+        0x08, // backspace
+        0x09, // tab
+
+        // numpad operators
+        0x6A, // *
+        0x6B, // +
+        0x6D, // -
+        0x6E, // .
+        0x6F, // /
+
+        // shift
+        0xA0, // left
+        0xA1, // right
+        0x10  // generic
+    ]
+}
+
+
+
+// priority keys are polled every round, the other watched keys only every other_keys_every rounds
+#[cfg(feature = "tokio")]
+#[derive(Clone, Debug)]
+pub(crate) struct PriorityScan {
+    pub(crate) vk_codes: Vec<i32>,
+    pub(crate) other_keys_every: u32
+}
+
+pub(crate) struct KeyStates {
+    vk_codes: Vec<i32>,
+    states: Vec<AtomicBool>,
+    // keys skipped in rounds that are not a multiple of other_keys_every
+    skippable: Vec<bool>,
+    other_keys_every: u32,
+    round: AtomicU32,
+    // the held Pause key was pressed with ctrl, i.e. is really NumLock
+    pause_is_num_lock: AtomicBool
+}
+
+impl KeyStates {
+    pub(crate) fn new(vk_codes: Vec<i32>) -> Self {
+        let states = vk_codes.iter().map(|_| AtomicBool::new(false)).collect();
+        let skippable = vec![false; vk_codes.len()];
+        KeyStates {
+            vk_codes, states, skippable, other_keys_every: 1, round: AtomicU32::new(0),
+            pause_is_num_lock: AtomicBool::new(false)
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn with_priority_scan(mut self, priority_scan: Option<PriorityScan>) -> Self {
+        if let Some(priority_scan) = priority_scan {
+            self.skippable = self.vk_codes.iter().map(|vk_code| !priority_scan.vk_codes.contains(vk_code)).collect();
+            self.other_keys_every = priority_scan.other_keys_every;
+        }
+        self
+    }
+
+    fn reported_key(&self, vk_code: i32) -> VirtualKey {
+        if vk_code == VirtualKey::PAUSE.0 && self.pause_is_num_lock.load(Ordering::Relaxed) {
+            return VirtualKey::NUMLOCK;
+        }
+        VirtualKey(vk_code)
+    }
+
+    // is_pressed and pressed_keys read the state last seen by the polling loop, so they only report keys in the
+    // watched list. Keys are named like in the events, e.g. NumLock for a Pause pressed with ctrl
+    pub(crate) fn is_pressed(&self, vk_code: i32) -> bool {
+        self.pressed_keys().contains(&VirtualKey(vk_code))
+    }
+
+    pub(crate) fn pressed_keys(&self) -> Vec<VirtualKey> {
+        self.vk_codes.iter()
+            .zip(self.states.iter())
+            .filter(|(_, is_down)| is_down.load(Ordering::Relaxed))
+            .map(|(&vk_code, _)| self.reported_key(vk_code))
+            .collect()
+    }
+
+    // compares every key's last seen state with its current one and emits only the changes, without trusting the
+    // "pressed since" bits, which still hold whatever happened before
+    #[cfg(feature = "tokio")]
+    pub(crate) fn resync(&self, mut emit: impl FnMut(KeyEvent)) {
+        for (vk_code, state) in self.vk_codes.iter().zip(self.states.iter()) {
+            let key_down = match VirtualKey(*vk_code) {
+                VirtualKey::PAUSE => is_down(VirtualKey::PAUSE.0) || is_down(VirtualKey::CANCEL.0),
+                _ => is_down(*vk_code)
+            };
+            if state.swap(key_down, Ordering::Relaxed) != key_down {
+                let key = self.reported_key(*vk_code);
+                emit(if key_down { KeyEvent::Press(key) } else { KeyEvent::Release(key) });
+            }
+        }
+    }
+
+    // emits a release for every key that is down and marks it as up, keys still held are pressed again on the next poll
+    #[cfg(feature = "tokio")]
+    pub(crate) fn release_all(&self, mut emit: impl FnMut(KeyEvent)) {
+        for (vk_code, state) in self.vk_codes.iter().zip(self.states.iter()) {
+            if state.swap(false, Ordering::Relaxed) {
+                emit(KeyEvent::Release(self.reported_key(*vk_code)));
+            }
+        }
+    }
+
+    // sets a watched key's state as if it had been polled, true if that changed it
+    #[cfg(feature = "mock")]
+    pub(crate) fn set(&self, vk_code: i32, is_down: bool) -> bool {
+        self.vk_codes.iter()
+            .position(|&code| code == vk_code)
+            .is_some_and(|i| self.states[i].swap(is_down, Ordering::Relaxed) != is_down)
+    }
+
+    // polls every watched key once, passing each press / release to emit
+    pub(crate) fn poll(&self, emit: impl FnMut(KeyEvent)) {
+        self.poll_with(|vk_code| unsafe { GetAsyncKeyState(vk_code) }, emit);
+    }
+
+    // read_state returns what GetAsyncKeyState would for a key code
+    fn poll_with(&self, read_state: impl Fn(i32) -> i16, mut emit: impl FnMut(KeyEvent)) {
+        let round = self.round.fetch_add(1, Ordering::Relaxed);
+        let all_keys = round.is_multiple_of(self.other_keys_every);
+        // the "pressed since" bits still hold presses from before the listener started
+        let first_poll = round == 0;
+        for (i, (vk_code, previous_key_state)) in self.vk_codes.iter().zip(self.states.iter()).enumerate() {
+            if self.skippable[i] && !all_keys {
+                continue;
+            }
+            let key_state = if *vk_code == VirtualKey::PAUSE.0 {
+                let (pause, cancel) = (read_state(VirtualKey::PAUSE.0), read_state(VirtualKey::CANCEL.0));
+                let key_state = key_state(pause | cancel, previous_key_state, true, first_poll);
+                if matches!(key_state, KeyState::KeyPress | KeyState::KeyTap) {
+                    let ctrl_down = read_state(VirtualKey::CONTROL.0) & KEY_DOWN_MASK != 0;
+                    self.pause_is_num_lock.store(pause_is_num_lock(pause, cancel, ctrl_down), Ordering::Relaxed);
+                }
+                key_state
+            } else {
+                let state = read_state(*vk_code);
+                key_state(state, previous_key_state, TAP_KEYS.contains(&VirtualKey(*vk_code)), first_poll)
+            };
+
+            let key = self.reported_key(*vk_code);
+            match key_state {
+                KeyState::StaticUp => {}
+                KeyState::StaticDown => {}
+                KeyState::KeyRelease => emit(KeyEvent::Release(key)),
+                KeyState::KeyPress => emit(KeyEvent::Press(key)),
+                KeyState::KeyTap => {
+                    emit(KeyEvent::Press(key));
+                    emit(KeyEvent::Release(key));
+                }
+            }
+        }
+    }
+}
+
+
+
+// state is what GetAsyncKeyState returned, can_tap whether its "pressed since" bit counts (TAP_KEYS)
+fn key_state(state: i16, previous_key_state: &AtomicBool, can_tap: bool, first_poll: bool) -> KeyState {
+    let is_down = (state & KEY_DOWN_MASK) != 0;
+    let was_down = previous_key_state.swap(is_down, Ordering::Relaxed);
+
+    match (is_down, was_down) {
+        (true, true) => KeyState::StaticDown,
+        (true, false) => KeyState::KeyPress,
+        (false, true) => KeyState::KeyRelease,
+        (false, false) if can_tap && !first_poll && (state & KEY_PRESSED_SINCE_MASK) != 0 => KeyState::KeyTap,
+        (false, false) => KeyState::StaticUp
+    }
+}
+
+// only ctrl + NumLock reads through VK_PAUSE with ctrl held, ctrl + Pause reads through VK_CANCEL
+fn pause_is_num_lock(pause: i16, cancel: i16, ctrl_down: bool) -> bool {
+    let reported = |state: i16| state & (KEY_DOWN_MASK | KEY_PRESSED_SINCE_MASK) != 0;
+    ctrl_down && reported(pause) && !reported(cancel)
+}
+
+#[cfg(feature = "tokio")]
+pub(crate) fn is_down(vk_code: i32) -> bool {
+    let state = unsafe {
+        GetAsyncKeyState(vk_code)
+    };
+    (state & KEY_DOWN_MASK) != 0
+}
+
+
+
+// whether a lock key (CapsLock, NumLock, ScrollLock) is currently switched on
+pub fn is_toggled(vk: impl Into<VirtualKey>) -> bool {
+    let state = unsafe {
+        GetKeyState(vk.into().code())
+    };
+    (state & KEY_TOGGLED_MASK) != 0
+}
+
+// last seen toggle state of each lock key, owned by the polling loop
+#[cfg(feature = "tokio")]
+pub(crate) struct ToggleStates {
+    states: Option<[bool; LOCK_KEYS.len()]>
+}
+
+#[cfg(feature = "tokio")]
+impl ToggleStates {
+    pub(crate) fn new() -> Self {
+        ToggleStates { states: None }
+    }
+
+    // the first poll only records the current states, later polls emit a ToggleChanged for every change
+    pub(crate) fn poll(&mut self, mut emit: impl FnMut(KeyEvent)) {
+        let current = LOCK_KEYS.map(is_toggled);
+        if let Some(previous) = self.states {
+            for ((key, on), was_on) in LOCK_KEYS.into_iter().zip(current).zip(previous) {
+                if on != was_on {
+                    emit(KeyEvent::ToggleChanged { key, on });
+                }
+            }
+        }
+        self.states = Some(current);
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UP: i16 = 0;
+    const DOWN: i16 = KEY_DOWN_MASK;
+    const PRESSED_SINCE: i16 = KEY_PRESSED_SINCE_MASK;
+
+    fn transition(state: i16, was_down: bool, can_tap: bool, first_poll: bool) -> KeyState {
+        key_state(state, &AtomicBool::new(was_down), can_tap, first_poll)
+    }
+
+    #[test]
+    fn presses_and_releases() {
+        assert_eq!(transition(DOWN, false, false, false), KeyState::KeyPress);
+        assert_eq!(transition(DOWN, true, false, false), KeyState::StaticDown);
+        assert_eq!(transition(UP, true, false, false), KeyState::KeyRelease);
+        assert_eq!(transition(UP, false, false, false), KeyState::StaticUp);
+    }
+
+    #[test]
+    fn print_screen_between_polls_is_a_tap() {
+        // Windows takes the screenshot on the press, the key is up again by the next poll
+        assert_eq!(transition(PRESSED_SINCE, false, true, false), KeyState::KeyTap);
+        // only for TAP_KEYS, the bit is shared with every other caller in the process
+        assert_eq!(transition(PRESSED_SINCE, false, false, false), KeyState::StaticUp);
+    }
+
+    #[test]
+    fn no_tap_on_the_first_poll() {
+        assert_eq!(transition(PRESSED_SINCE, false, true, true), KeyState::StaticUp);
+        assert_eq!(transition(DOWN | PRESSED_SINCE, false, true, true), KeyState::KeyPress);
+    }
+
+    #[test]
+    fn ctrl_num_lock_is_num_lock() {
+        assert!(pause_is_num_lock(DOWN, UP, true));
+        assert!(pause_is_num_lock(PRESSED_SINCE, UP, true));
+    }
+
+    #[test]
+    fn ctrl_pause_is_pause() {
+        // Break reads through VK_CANCEL
+        assert!(!pause_is_num_lock(UP, DOWN, true));
+        assert!(!pause_is_num_lock(UP, PRESSED_SINCE, true));
+    }
+
+    #[test]
+    fn pause_without_ctrl_is_pause() {
+        assert!(!pause_is_num_lock(DOWN, UP, false));
+    }
+
+    // one poll round with the given keys reading as the given states, every other key up
+    fn round(key_states: &KeyStates, states: &[(VirtualKey, i16)]) -> Vec<KeyEvent> {
+        let read_state = |vk_code| states.iter().find(|(vk, _)| vk.0 == vk_code).map_or(UP, |(_, state)| *state);
+        let mut key_events = Vec::new();
+        key_states.poll_with(read_state, |key_event| key_events.push(key_event));
+        key_events
+    }
+
+    #[test]
+    fn tap_keys_tap_between_polls() {
+        let key_states = KeyStates::new(vec![VirtualKey::SNAPSHOT.0, 0x41]);
+        // left from before the listener started
+        assert_eq!(round(&key_states, &[(VirtualKey::SNAPSHOT, PRESSED_SINCE)]), []);
+
+        let tapped = round(&key_states, &[(VirtualKey::SNAPSHOT, PRESSED_SINCE), (VirtualKey(0x41), PRESSED_SINCE)]);
+        assert_eq!(tapped, [KeyEvent::Press(VirtualKey::SNAPSHOT), KeyEvent::Release(VirtualKey::SNAPSHOT)]);
+        assert!(key_states.pressed_keys().is_empty());
+    }
+
+    #[test]
+    fn ctrl_num_lock_reports_and_reads_as_num_lock() {
+        let key_states = KeyStates::new(vec![VirtualKey::PAUSE.0, VirtualKey::CONTROL.0]);
+        round(&key_states, &[]);
+
+        let pressed = round(&key_states, &[(VirtualKey::CONTROL, DOWN), (VirtualKey::PAUSE, DOWN)]);
+        assert_eq!(pressed, [KeyEvent::Press(VirtualKey::NUMLOCK), KeyEvent::Press(VirtualKey::CONTROL)]);
+        assert_eq!(key_states.pressed_keys(), [VirtualKey::NUMLOCK, VirtualKey::CONTROL]);
+        assert!(key_states.is_pressed(VirtualKey::NUMLOCK.0));
+        assert!(!key_states.is_pressed(VirtualKey::PAUSE.0));
+
+        // released as NumLock too, even with ctrl let go first
+        let released = round(&key_states, &[]);
+        assert_eq!(released, [KeyEvent::Release(VirtualKey::NUMLOCK), KeyEvent::Release(VirtualKey::CONTROL)]);
+    }
+}
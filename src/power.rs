@@ -0,0 +1,100 @@
+/*
+
+System sleep / resume notifications
+
+Registers a suspend / resume callback with the power manager, the polling loop reads the flags it sets. Polling
+during suspend reads stale key state, so the loop pauses while suspended. On resume the key states are compared
+with the real ones once: keys let go during sleep are released and keys pressed meanwhile are pressed, keys held
+throughout stay down without a second press.
+
+https://learn.microsoft.com/en-us/windows/win32/api/powerbase/nf-powerbase-powerregistersuspendresumenotification
+
+*/
+
+
+use crate::KeyEvent;
+use crate::poll::KeyStates;
+
+use windows::Win32::Foundation::{ERROR_SUCCESS, HANDLE};
+use windows::Win32::System::Power::{
+    PowerRegisterSuspendResumeNotification, PowerUnregisterSuspendResumeNotification, DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS,
+    HPOWERNOTIFY
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    DEVICE_NOTIFY_CALLBACK, PBT_APMRESUMEAUTOMATIC, PBT_APMRESUMESUSPEND, PBT_APMSUSPEND
+};
+
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+
+#[derive(Default)]
+struct PowerFlags {
+    suspended: AtomicBool
+}
+
+unsafe extern "system" fn power_callback(context: *const c_void, event_type: u32, _setting: *const c_void) -> u32 {
+    let flags = unsafe { &*(context as *const PowerFlags) };
+    match event_type {
+        PBT_APMSUSPEND => flags.suspended.store(true, Ordering::Relaxed),
+        PBT_APMRESUMEAUTOMATIC | PBT_APMRESUMESUSPEND => flags.suspended.store(false, Ordering::Relaxed),
+        _ => {}
+    }
+    ERROR_SUCCESS.0
+}
+
+
+
+// owned by the polling loop, unregisters when dropped
+pub(crate) struct PowerWatcher {
+    registration: HPOWERNOTIFY,
+    // boxed so the callback's context pointer stays valid
+    flags: Box<PowerFlags>,
+    suspended: bool
+}
+
+impl PowerWatcher {
+    // None if the notification could not be registered, the listener then simply keeps polling through sleep
+    pub(crate) fn new() -> Option<Self> {
+        let flags = Box::new(PowerFlags::default());
+        let parameters = DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+            Callback: Some(power_callback),
+            Context: &*flags as *const PowerFlags as *mut c_void
+        };
+        let mut registration = std::ptr::null_mut();
+        let result = unsafe {
+            PowerRegisterSuspendResumeNotification(
+                DEVICE_NOTIFY_CALLBACK, HANDLE(&parameters as *const _ as *mut c_void), &mut registration
+            )
+        };
+        (result == ERROR_SUCCESS).then(|| PowerWatcher {
+            registration: HPOWERNOTIFY(registration as isize),
+            flags,
+            suspended: false
+        })
+    }
+
+    // returns whether the system is suspended
+    pub(crate) fn poll(&mut self, key_states: &KeyStates, mut emit: impl FnMut(KeyEvent)) -> bool {
+        let suspended = self.flags.suspended.load(Ordering::Relaxed);
+        if suspended != self.suspended {
+            if suspended {
+                emit(KeyEvent::Suspended);
+            } else {
+                emit(KeyEvent::Resumed);
+                key_states.resync(&mut emit);
+            }
+            self.suspended = suspended;
+        }
+        suspended
+    }
+}
+
+impl Drop for PowerWatcher {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = PowerUnregisterSuspendResumeNotification(self.registration);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+/*
+
+Pressed set changes
+
+The set of keys held down, sent whenever a press or release changes it. Chord logic and key overlays can look at
+the whole set instead of folding single presses and releases into one themselves.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+
+use std::collections::HashSet;
+
+
+
+// every key held down after a press or release, the listener has to be built with state_changes(true)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StateChanged(pub HashSet<VirtualKey>);
+
+// keys held according to the events sent so far, after remapping and filtering
+#[derive(Default)]
+pub(crate) struct PressedSet {
+    pressed: HashSet<VirtualKey>
+}
+
+impl PressedSet {
+    // the new set when key_event changed it
+    pub(crate) fn update(&mut self, key_event: KeyEvent) -> Option<StateChanged> {
+        let changed = match key_event {
+            KeyEvent::Press(vk) => self.pressed.insert(vk),
+            KeyEvent::Release(vk) => self.pressed.remove(&vk),
+            _ => false
+        };
+        changed.then(|| StateChanged(self.pressed.clone()))
+    }
+}
@@ -0,0 +1,84 @@
+/*
+
+Privacy mode
+
+UI Automation reports whether the focused control, in any process, is a password field: native edit controls with
+ES_PASSWORD as well as password inputs in browsers and WPF / UWP applications. Querying it is a cross process call that
+blocks until the focused application answers, so the listener only asks when a round polled a press, and asks from a
+thread of its own (see blocking_query).
+
+UI Automation is a COM API, but the calling thread is never initialized for COM: joining the multithreaded
+apartment there would make a later OleInitialize fail, e.g. when the thread turns out to be an application's GUI
+thread. Instead the process keeps the implicit multithreaded apartment alive, which threads without an apartment of
+their own use, while threads that joined one (single threaded or not) keep using theirs.
+
+https://learn.microsoft.com/en-us/windows/win32/api/uiautomationclient/nf-uiautomationclient-iuiautomationelement-get_currentispassword
+
+*/
+
+
+#[cfg(feature = "tokio")]
+use crate::KeyEvent;
+#[cfg(feature = "tokio")]
+use crate::blocking_query::BlockingQuery;
+
+use windows::Win32::System::Com::{CoCreateInstance, CoIncrementMTAUsage, CLSCTX_INPROC_SERVER};
+use windows::Win32::UI::Accessibility::{CUIAutomation, IUIAutomation};
+
+use std::sync::Once;
+
+
+
+static IMPLICIT_MTA: Once = Once::new();
+
+thread_local! {
+    // None if UI Automation is not available, e.g. on Windows Server Core
+    static AUTOMATION: Option<IUIAutomation> = unsafe {
+        // never released, the cookie only matters to code unloading COM before the process exits
+        IMPLICIT_MTA.call_once(|| {
+            let _ = CoIncrementMTAUsage();
+        });
+        CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER).ok()
+    };
+}
+
+// whether the control with the keyboard focus is a password field, false if that cannot be told
+pub fn is_password_field_focused() -> bool {
+    AUTOMATION.with(|automation| {
+        automation.as_ref().is_some_and(|automation| unsafe {
+            automation.GetFocusedElement().and_then(|element| element.CurrentIsPassword()).is_ok_and(|is_password| is_password.as_bool())
+        })
+    })
+}
+
+
+
+// puts SensitiveInputStarted / SensitiveInputEnded in front of the first press of a round once the focus moved
+// into / out of a password field, the char translator and recorders pause in between
+#[cfg(feature = "tokio")]
+pub(crate) struct PrivacyGuard {
+    sensitive: bool,
+    password_query: BlockingQuery
+}
+
+#[cfg(feature = "tokio")]
+impl Default for PrivacyGuard {
+    fn default() -> Self {
+        Self { sensitive: false, password_query: BlockingQuery::new(is_password_field_focused) }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl PrivacyGuard {
+    pub(crate) async fn process(&mut self, mut key_events: Vec<KeyEvent>) -> Vec<KeyEvent> {
+        let Some(first_press) = key_events.iter().position(|key_event| matches!(key_event, KeyEvent::Press(_))) else {
+            return key_events;
+        };
+        if self.password_query.ask().await != self.sensitive {
+            self.sensitive = !self.sensitive;
+            let marker = if self.sensitive { KeyEvent::SensitiveInputStarted } else { KeyEvent::SensitiveInputEnded };
+            key_events.insert(first_press, marker);
+        }
+        key_events
+    }
+}
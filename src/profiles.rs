@@ -0,0 +1,169 @@
+/*
+
+Per-application profiles
+
+Each profile has its own key set, hotkeys and callbacks, and the profile matching the foreground window's process
+is the one events are routed to. The listener polls every key any profile needs, keys outside of the active
+profile's key set are dropped. A key's release always goes to the profile that received its press, so switching
+applications while a key is held does not leave it stuck.
+
+*/
+
+
+use crate::{Hotkey, KeyEvent, Modifiers, VirtualKey, WindowHandle};
+use crate::builder::KeyListenerBuilder;
+use crate::error::KeyListenerError;
+use crate::hotkey_router::{KeyRouter, spawn_router};
+use crate::poll::default_vk_codes;
+use crate::tokio_listener::KeyListener;
+
+use tokio::sync::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+
+
+type KeyCallback = Box<dyn FnMut(KeyEvent) + Send + 'static>;
+type HotkeyCallback = Box<dyn FnMut() + Send + 'static>;
+
+pub struct Profile {
+    vk_codes: Vec<i32>,
+    hotkeys: Vec<(Hotkey, HotkeyCallback)>,
+    key_down_callback: Option<KeyCallback>,
+    key_up_callback: Option<KeyCallback>
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile { vk_codes: default_vk_codes(), hotkeys: Vec::new(), key_down_callback: None, key_up_callback: None }
+    }
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Profile::default()
+    }
+
+    // keys reported to this profile's callbacks, hotkey keys are always watched
+    pub fn vk_codes(mut self, vk_codes: impl IntoIterator<Item = impl Into<VirtualKey>>) -> Self {
+        self.vk_codes = vk_codes.into_iter().map(|vk| vk.into().code()).collect();
+        self
+    }
+
+    pub fn hotkey<F>(mut self, hotkey: Hotkey, callback: F) -> Self
+    where
+        F: FnMut() + Send + 'static
+    {
+        self.hotkeys.push((hotkey, Box::new(callback)));
+        self
+    }
+
+    pub fn on_key_down<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(KeyEvent) + Send + 'static
+    {
+        self.key_down_callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn on_key_up<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(KeyEvent) + Send + 'static
+    {
+        self.key_up_callback = Some(Box::new(callback));
+        self
+    }
+
+    fn watches(&self, key: VirtualKey) -> bool {
+        self.vk_codes.contains(&key.code())
+    }
+}
+
+
+
+pub struct Profiles {
+    // process names are compared ignoring case, the default profile is last
+    process_names: Vec<String>,
+    profiles: Vec<Profile>
+}
+
+impl Profiles {
+    // the default profile is used while no other profile's process is in the foreground
+    pub fn new(default_profile: Profile) -> Self {
+        Profiles { process_names: Vec::new(), profiles: vec![default_profile] }
+    }
+
+    // profile for the windows of a process, by executable file name, e.g. "notepad.exe"
+    pub fn profile(mut self, process_name: &str, profile: Profile) -> Self {
+        let default_profile = self.profiles.pop().expect("there is always a default profile");
+        self.process_names.push(process_name.to_lowercase());
+        self.profiles.push(profile);
+        self.profiles.push(default_profile);
+        self
+    }
+
+    // spawns a listener with the builder's options, the builder's key set is replaced with every profile's keys
+    pub fn spawn(self, builder: KeyListenerBuilder) -> Result<Arc<Mutex<KeyListener>>, KeyListenerError> {
+        let vk_codes: Vec<i32> = self.profiles.iter()
+            .flat_map(|profile| profile.vk_codes.iter().copied().chain(profile.hotkeys.iter().flat_map(|(hotkey, _)| hotkey.vk_codes())))
+            .collect();
+        let state = ProfileState { profiles: self, foreground: None, held_by: HashMap::new() };
+        spawn_router(builder, vk_codes, state).map(|(key_listener, _)| key_listener)
+    }
+}
+
+
+
+struct ProfileState {
+    profiles: Profiles,
+    // last foreground window and the index of its profile
+    foreground: Option<(WindowHandle, usize)>,
+    // held keys and the profile that received their press
+    held_by: HashMap<VirtualKey, usize>
+}
+
+impl ProfileState {
+    fn active_profile(&mut self) -> usize {
+        let default_index = self.profiles.profiles.len() - 1;
+        let Some(window) = WindowHandle::foreground() else {
+            return default_index;
+        };
+        if let Some((last, index)) = self.foreground && last == window {
+            return index;
+        }
+
+        let process_name = window.process_name().map(|process_name| process_name.to_lowercase());
+        let index = process_name
+            .and_then(|process_name| self.profiles.process_names.iter().position(|name| *name == process_name))
+            .unwrap_or(default_index);
+        self.foreground = Some((window, index));
+        index
+    }
+}
+
+impl KeyRouter for ProfileState {
+    fn press(&mut self, key: VirtualKey, held: Modifiers) {
+        let index = self.active_profile();
+        let profile = &mut self.profiles.profiles[index];
+
+        for (hotkey, callback) in profile.hotkeys.iter_mut() {
+            if hotkey.matches(key, held) {
+                callback();
+            }
+        }
+        if profile.watches(key) {
+            self.held_by.insert(key, index);
+            if let Some(callback) = profile.key_down_callback.as_mut() {
+                callback(KeyEvent::Press(key));
+            }
+        }
+    }
+
+    fn release(&mut self, key: VirtualKey) {
+        if let Some(index) = self.held_by.remove(&key)
+            && let Some(callback) = self.profiles.profiles[index].key_up_callback.as_mut()
+        {
+            callback(KeyEvent::Release(key));
+        }
+    }
+}
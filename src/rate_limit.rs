@@ -0,0 +1,188 @@
+/*
+
+Rate limiting of key presses
+
+Runs after every other stage. Presses beyond a key's limit or the global limit (per second) are either dropped or
+coalesced: a coalesced key reports a single press (and release) once it is under its limits again, however often
+it was pressed meanwhile. Releases always follow their press, so callbacks never see a key stuck down or a
+release without a press. Events are counted as discarded once they are known to never be sent: right away when
+dropped, and when merged into a coalesced press, not while they merely wait for the limits.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RateLimitPolicy {
+    // presses over the limit are lost
+    #[default]
+    Drop,
+    // presses over the limit are reported once, as soon as the limits allow
+    Coalesce
+}
+
+
+
+#[derive(Default)]
+pub(crate) struct RateLimiter {
+    policy: RateLimitPolicy,
+    limit: Option<usize>,
+    key_limits: HashMap<i32, usize>,
+    // press times within the last RATE_WINDOW
+    presses: VecDeque<Instant>,
+    key_presses: HashMap<i32, VecDeque<Instant>>,
+    // keys whose latest press was held back, their release is held back too
+    held_back: HashSet<i32>,
+    // coalesced keys waiting to be reported, in the order they were first held back
+    pending: Vec<i32>,
+    pending_released: HashSet<i32>,
+    // events dropped or merged away since take_discarded
+    discarded: u64
+}
+
+impl RateLimiter {
+    pub(crate) fn set_policy(&mut self, policy: RateLimitPolicy) {
+        self.policy = policy;
+    }
+
+    pub(crate) fn set_limit(&mut self, presses_per_second: usize) {
+        self.limit = Some(presses_per_second);
+    }
+
+    pub(crate) fn set_key_limit(&mut self, vk_code: i32, presses_per_second: usize) {
+        self.key_limits.insert(vk_code, presses_per_second);
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.limit.is_some() || !self.key_limits.is_empty()
+    }
+
+    pub(crate) fn process(&mut self, key_event: KeyEvent) -> Option<KeyEvent> {
+        match key_event {
+            KeyEvent::Press(vk) => {
+                let vk_code = vk.code();
+                if !self.pending.contains(&vk_code) && self.allows(vk_code) {
+                    self.held_back.remove(&vk_code);
+                    return Some(key_event);
+                }
+                self.held_back.insert(vk_code);
+                match self.policy {
+                    RateLimitPolicy::Drop => self.discarded += 1,
+                    // a press of a key already waiting merges into its press, and so does the release in between
+                    RateLimitPolicy::Coalesce if self.pending.contains(&vk_code) => {
+                        self.discarded += 1 + self.pending_released.remove(&vk_code) as u64;
+                    }
+                    RateLimitPolicy::Coalesce => self.pending.push(vk_code)
+                }
+                None
+            }
+            KeyEvent::Release(vk) if self.held_back.contains(&vk.code()) => {
+                if self.pending.contains(&vk.code()) {
+                    self.pending_released.insert(vk.code());
+                } else {
+                    self.held_back.remove(&vk.code());
+                    self.discarded += 1;
+                }
+                None
+            }
+            _ => Some(key_event)
+        }
+    }
+
+    // reports coalesced keys that are under their limits again, called every round of polling
+    pub(crate) fn flush(&mut self, mut emit: impl FnMut(KeyEvent)) {
+        let mut index = 0;
+        while index < self.pending.len() {
+            let vk_code = self.pending[index];
+            if !self.allows(vk_code) {
+                index += 1;
+                continue;
+            }
+            self.pending.remove(index);
+            emit(KeyEvent::Press(VirtualKey::from(vk_code)));
+            // a key still held is released through process as usual
+            self.held_back.remove(&vk_code);
+            if self.pending_released.remove(&vk_code) {
+                emit(KeyEvent::Release(VirtualKey::from(vk_code)));
+            }
+        }
+    }
+
+    pub(crate) fn take_discarded(&mut self) -> u64 {
+        std::mem::take(&mut self.discarded)
+    }
+
+    // counts the press if it is allowed
+    fn allows(&mut self, vk_code: i32) -> bool {
+        let now = Instant::now();
+        let key_limit = self.key_limits.get(&vk_code).copied();
+        let key_presses = self.key_presses.entry(vk_code).or_default();
+        forget_old(&mut self.presses, now);
+        forget_old(key_presses, now);
+
+        let allowed = self.limit.is_none_or(|limit| self.presses.len() < limit)
+            && key_limit.is_none_or(|limit| key_presses.len() < limit);
+        if allowed {
+            self.presses.push_back(now);
+            key_presses.push_back(now);
+        }
+        allowed
+    }
+}
+
+fn forget_old(presses: &mut VecDeque<Instant>, now: Instant) {
+    while presses.front().is_some_and(|&pressed| now.duration_since(pressed) >= RATE_WINDOW) {
+        presses.pop_front();
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: VirtualKey = VirtualKey(0x41);
+
+    fn limited(policy: RateLimitPolicy) -> RateLimiter {
+        let mut rate_limiter = RateLimiter::default();
+        rate_limiter.set_policy(policy);
+        rate_limiter.set_limit(1);
+        rate_limiter
+    }
+
+    fn taps(rate_limiter: &mut RateLimiter, taps: usize) -> Vec<KeyEvent> {
+        (0..taps)
+            .flat_map(|_| [KeyEvent::Press(KEY), KeyEvent::Release(KEY)])
+            .filter_map(|key_event| rate_limiter.process(key_event))
+            .collect()
+    }
+
+    #[test]
+    fn dropped_presses_and_their_releases_are_discarded() {
+        let mut rate_limiter = limited(RateLimitPolicy::Drop);
+        assert_eq!(taps(&mut rate_limiter, 3), [KeyEvent::Press(KEY), KeyEvent::Release(KEY)]);
+        assert_eq!(rate_limiter.take_discarded(), 4);
+        assert_eq!(rate_limiter.take_discarded(), 0);
+    }
+
+    #[test]
+    fn held_back_presses_are_not_discarded_until_merged() {
+        let mut rate_limiter = limited(RateLimitPolicy::Coalesce);
+        taps(&mut rate_limiter, 2);
+        // the second tap waits for the limit, it will still be sent
+        assert_eq!(rate_limiter.take_discarded(), 0);
+
+        // the third and fourth merge into it, along with the release in between
+        taps(&mut rate_limiter, 2);
+        assert_eq!(rate_limiter.take_discarded(), 4);
+    }
+}
@@ -0,0 +1,380 @@
+/*
+
+Key listener using Raw Input
+
+Raw Input reports which keyboard each key event came from, so a listener can follow one device, like a foot pedal
+or a macro pad, and ignore the others. Events are received by a hidden message-only window (see message_loop), on
+a thread of its own or on the application's, with RIDEV_INPUTSINK so they arrive whichever window has the focus. Like the hook, Raw Input reports left /
+right modifier codes rather than the generic ones.
+
+With a device change callback, keyboards being attached and removed are reported too (WM_INPUT_DEVICE_CHANGE).
+Windows reports every keyboard already attached as connected when the listener starts. Removals are watched either
+way: keys held on a keyboard that goes away never send their release, so the listener reports it instead. A key
+held on several keyboards is pressed once and released once the last of them lets go.
+
+Raw Input keyboard registration is per process: only one raw input listener can run at a time, a second one takes
+the events over from the first.
+
+https://learn.microsoft.com/en-us/windows/win32/inputdev/raw-input
+
+*/
+
+
+use crate::{KeyEvent, ScanCode, VirtualKey};
+use crate::callbacks::Callbacks;
+use crate::devices::{DeviceChange, DeviceInfo};
+use crate::error::KeyListenerError;
+use crate::message_loop::{MessageLoop, MessageWindow, WindowHandlers, open_message_window};
+
+use windows::Win32::Foundation::{HANDLE, HWND, LPARAM, WPARAM};
+use windows::Win32::UI::Input::{
+    GetRawInputData, GetRawInputDeviceInfoW, RegisterRawInputDevices, HRAWINPUT, RAWINPUT, RAWINPUTDEVICE,
+    RAWINPUTHEADER, RIDEV_DEVNOTIFY, RIDEV_INPUTSINK, RIDEV_REMOVE, RIDI_DEVICENAME, RID_INPUT, RIM_TYPEKEYBOARD
+};
+use windows::Win32::UI::WindowsAndMessaging::{GIDC_ARRIVAL, GIDC_REMOVAL, RI_KEY_BREAK, RI_KEY_E0, WM_INPUT, WM_INPUT_DEVICE_CHANGE};
+
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::mpsc::{Sender, channel};
+use std::thread;
+
+
+
+// generic desktop page, keyboard usage
+const HID_USAGE_PAGE_GENERIC: u16 = 0x01;
+const HID_USAGE_GENERIC_KEYBOARD: u16 = 0x06;
+
+// sent for the extra scan codes of some extended keys, not for a key of its own
+const FAKE_VK: u16 = 0xFF;
+const RIGHT_SHIFT_SCAN_CODE: u16 = 0x36;
+
+
+
+// the Raw Input handle of an attached device, valid until it is unplugged
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DeviceHandle(pub isize);
+
+impl DeviceHandle {
+    // the device interface path, e.g. \\?\HID#VID_046D&PID_C31C&MI_00#..., None once the device is gone
+    pub fn path(&self) -> Option<String> {
+        let device = Some(HANDLE(self.0 as *mut c_void));
+        let mut length = 0u32;
+        unsafe {
+            GetRawInputDeviceInfoW(device, RIDI_DEVICENAME, None, &mut length);
+            let mut path = vec![0u16; length as usize];
+            let written = GetRawInputDeviceInfoW(device, RIDI_DEVICENAME, Some(path.as_mut_ptr().cast()), &mut length);
+            if written == u32::MAX || written == 0 {
+                return None;
+            }
+            let path = &path[..written as usize];
+            let end = path.iter().position(|c| *c == 0).unwrap_or(path.len());
+            Some(String::from_utf16_lossy(&path[..end]))
+        }
+    }
+}
+
+// USB vendor and product id from a device path, None for devices without them (e.g. PS/2 keyboards)
+pub(crate) fn vid_pid(path: &str) -> Option<(u16, u16)> {
+    let path = path.to_ascii_uppercase();
+    let hex_after = |prefix: &str| {
+        let start = path.find(prefix)? + prefix.len();
+        u16::from_str_radix(path.get(start..start + 4)?, 16).ok()
+    };
+    Some((hex_after("VID_")?, hex_after("PID_")?))
+}
+
+
+
+// which keyboard a raw input listener reports
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceFilter {
+    // a device interface path as given by DeviceHandle::path, compared case insensitively
+    Path(String),
+    // every keyboard with this USB vendor and product id
+    VidPid { vid: u16, pid: u16 }
+}
+
+impl DeviceFilter {
+    pub fn matches(&self, path: &str) -> bool {
+        match self {
+            DeviceFilter::Path(filter_path) => filter_path.eq_ignore_ascii_case(path),
+            DeviceFilter::VidPid { vid, pid } => vid_pid(path) == Some((*vid, *pid))
+        }
+    }
+}
+
+// a key event and the keyboard it came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawKeyEvent {
+    pub key_event: KeyEvent,
+    // DeviceHandle(0) for input that came from no device, e.g. injected with SendInput
+    pub device: DeviceHandle,
+    // the key's own scan code, which tells apart keys that share a key code (enter and numpad enter, ...)
+    pub scan_code: ScanCode
+}
+
+impl RawKeyEvent {
+    // like VirtualKey::name, but from the scan code of the key that was pressed
+    pub fn key_name(&self) -> Option<String> {
+        let vk = self.key_event.vk()?;
+        match vk {
+            // pause comes with the E1 prefix, named by its key code
+            VirtualKey::PAUSE => vk.name(),
+            _ => vk.custom_name().or_else(|| self.scan_code.name())
+        }
+    }
+}
+
+
+
+pub struct RawInputListener {
+    window: MessageWindow
+}
+
+impl RawInputListener {
+    pub fn builder() -> RawInputListenerBuilder {
+        RawInputListenerBuilder::default()
+    }
+
+    // unregisters the keyboards, and ends the listener's thread unless it runs on the application's
+    pub fn quit(&self) {
+        self.window.close();
+    }
+}
+
+
+
+type DeviceChangeCallback = Box<dyn FnMut(DeviceChange) + Send + 'static>;
+
+// what the message loop hands to the dispatch thread
+enum Dispatch {
+    Key(RawKeyEvent),
+    Device(DeviceChange)
+}
+
+struct RawInputState {
+    sender: Sender<Dispatch>,
+    device_filter: Option<DeviceFilter>,
+    // whether each device seen so far passes the filter, paths are only looked up once
+    device_matches: HashMap<DeviceHandle, bool>,
+    // attached devices, their paths can't be looked up anymore once they are removed
+    devices: HashMap<DeviceHandle, DeviceInfo>,
+    // the devices holding each pressed key, with the scan code they pressed it with
+    pressed: HashMap<VirtualKey, HashMap<DeviceHandle, ScanCode>>
+}
+
+// WM_INPUT reaches DefWindowProc after this as well, which frees the input
+fn on_raw_input_message(state: &mut RawInputState, msg: u32, wparam: WPARAM, lparam: LPARAM) {
+    if msg == WM_INPUT
+        && let Some(input) = read_raw_input(lparam)
+    {
+        state.handle(&input);
+    }
+    if msg == WM_INPUT_DEVICE_CHANGE {
+        state.device_changed(DeviceHandle(lparam.0), wparam.0 as u32);
+    }
+}
+
+fn read_raw_input(lparam: LPARAM) -> Option<RAWINPUT> {
+    let mut input = RAWINPUT::default();
+    let mut size = size_of::<RAWINPUT>() as u32;
+    let read = unsafe {
+        GetRawInputData(
+            HRAWINPUT(lparam.0 as *mut c_void), RID_INPUT, Some((&mut input as *mut RAWINPUT).cast()), &mut size,
+            size_of::<RAWINPUTHEADER>() as u32
+        )
+    };
+    (read != u32::MAX && input.header.dwType == RIM_TYPEKEYBOARD.0).then_some(input)
+}
+
+impl RawInputState {
+    fn handle(&mut self, input: &RAWINPUT) {
+        let keyboard = unsafe { input.data.keyboard };
+        if keyboard.VKey == FAKE_VK {
+            return;
+        }
+        let device = DeviceHandle(input.header.hDevice.0 as isize);
+        if let Some(device_filter) = &self.device_filter {
+            let matches = *self.device_matches.entry(device)
+                .or_insert_with(|| device.path().is_some_and(|path| device_filter.matches(&path)));
+            if !matches {
+                return;
+            }
+        }
+
+        // raw input reports the generic modifier codes, the scan code / E0 flag tells the sides apart
+        let extended = keyboard.Flags as u32 & RI_KEY_E0 != 0;
+        let key = match VirtualKey(keyboard.VKey as i32) {
+            VirtualKey::SHIFT if keyboard.MakeCode == RIGHT_SHIFT_SCAN_CODE => VirtualKey::RSHIFT,
+            VirtualKey::SHIFT => VirtualKey::LSHIFT,
+            VirtualKey::CONTROL if extended => VirtualKey::RCONTROL,
+            VirtualKey::CONTROL => VirtualKey::LCONTROL,
+            VirtualKey::MENU if extended => VirtualKey::RMENU,
+            VirtualKey::MENU => VirtualKey::LMENU,
+            key => key
+        };
+
+        // held keys repeat their make code, only the first one is reported like with polling
+        let scan_code = ScanCode { code: keyboard.MakeCode, extended };
+        let held_by = self.pressed.entry(key).or_default();
+        let key_event = if keyboard.Flags as u32 & RI_KEY_BREAK != 0 {
+            let released = held_by.remove(&device).is_some() && held_by.is_empty();
+            released.then_some(KeyEvent::Release(key))
+        } else {
+            let first = held_by.is_empty();
+            held_by.insert(device, scan_code);
+            first.then_some(KeyEvent::Press(key))
+        };
+        if held_by.is_empty() {
+            self.pressed.remove(&key);
+        }
+        if let Some(key_event) = key_event {
+            let _ = self.sender.send(Dispatch::Key(RawKeyEvent { key_event, device, scan_code }));
+        }
+    }
+
+    // releases the keys only a removed device held
+    fn release_keys_of(&mut self, device: DeviceHandle) {
+        let mut released = Vec::new();
+        self.pressed.retain(|key, held_by| {
+            if let Some(scan_code) = held_by.remove(&device)
+                && held_by.is_empty()
+            {
+                released.push((*key, scan_code));
+            }
+            !held_by.is_empty()
+        });
+        for (key, scan_code) in released {
+            let _ = self.sender.send(Dispatch::Key(RawKeyEvent { key_event: KeyEvent::Release(key), device, scan_code }));
+        }
+    }
+
+    fn device_changed(&mut self, device: DeviceHandle, change: u32) {
+        // handles of removed devices can be reused, so whether they match is looked up again
+        self.device_matches.remove(&device);
+        let change = match change {
+            GIDC_ARRIVAL => {
+                let Some(info) = DeviceInfo::of(device) else {
+                    return;
+                };
+                self.devices.insert(device, info.clone());
+                DeviceChange::Connected(info)
+            }
+            GIDC_REMOVAL => {
+                self.release_keys_of(device);
+                DeviceChange::Disconnected(self.devices.remove(&device).unwrap_or_else(|| DeviceInfo::unknown(device)))
+            }
+            _ => return
+        };
+        let _ = self.sender.send(Dispatch::Device(change));
+    }
+}
+
+// the message window receives the keyboards' input in the background
+fn register_keyboards(window: HWND) -> windows::core::Result<()> {
+    unsafe {
+        let device = RAWINPUTDEVICE {
+            usUsagePage: HID_USAGE_PAGE_GENERIC,
+            usUsage: HID_USAGE_GENERIC_KEYBOARD,
+            dwFlags: RIDEV_INPUTSINK | RIDEV_DEVNOTIFY,
+            hwndTarget: window
+        };
+        RegisterRawInputDevices(&[device], size_of::<RAWINPUTDEVICE>() as u32)
+    }
+}
+
+fn unregister_keyboards() {
+    let device = RAWINPUTDEVICE {
+        usUsagePage: HID_USAGE_PAGE_GENERIC,
+        usUsage: HID_USAGE_GENERIC_KEYBOARD,
+        dwFlags: RIDEV_REMOVE,
+        hwndTarget: HWND::default()
+    };
+    unsafe {
+        let _ = RegisterRawInputDevices(&[device], size_of::<RAWINPUTDEVICE>() as u32);
+    }
+}
+
+
+
+#[derive(Default)]
+pub struct RawInputListenerBuilder {
+    device_filter: Option<DeviceFilter>,
+    device_change_callback: Option<DeviceChangeCallback>,
+    message_loop: MessageLoop
+}
+
+impl RawInputListenerBuilder {
+    // only report keys of matching keyboards, input without a device (e.g. injected) never matches
+    pub fn device_filter(mut self, device_filter: DeviceFilter) -> Self {
+        self.device_filter = Some(device_filter);
+        self
+    }
+
+    // called on the callback thread when a keyboard is attached or removed, whatever the device filter
+    pub fn on_device_change<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(DeviceChange) + Send + 'static
+    {
+        self.device_change_callback = Some(Box::new(callback));
+        self
+    }
+
+    // receive the input on the calling thread, driven by the application's message loop, see MessageLoop
+    pub fn message_loop(mut self, message_loop: MessageLoop) -> Self {
+        self.message_loop = message_loop;
+        self
+    }
+
+    // callbacks are called on a separate thread so they cannot hold up the message loop
+    pub fn spawn<D, U>(self, key_down_callback: D, key_up_callback: U) -> Result<RawInputListener, KeyListenerError>
+    where
+        D: FnMut(KeyEvent) + Send + 'static,
+        U: FnMut(KeyEvent) + Send + 'static
+    {
+        let mut callbacks = Callbacks::new(key_down_callback, key_up_callback);
+        self.spawn_with_device(move |raw_key_event| callbacks.call(raw_key_event.key_event))
+    }
+
+    // a single callback for presses and releases, along with the keyboard they came from
+    pub fn spawn_with_device<F>(self, mut callback: F) -> Result<RawInputListener, KeyListenerError>
+    where
+        F: FnMut(RawKeyEvent) + Send + 'static
+    {
+        let (sender, receiver) = channel();
+        let device_filter = self.device_filter;
+        let mut device_change_callback = self.device_change_callback;
+
+        let window = open_message_window(self.message_loop, move |window| {
+            register_keyboards(window).map_err(KeyListenerError::RawInputRegistration)?;
+            // owned by the window, dropping it with the window drops the sender, which ends the dispatch thread
+            let mut state = RawInputState {
+                sender,
+                device_filter,
+                device_matches: HashMap::new(),
+                devices: HashMap::new(),
+                pressed: HashMap::new()
+            };
+            Ok(WindowHandlers {
+                on_message: Some(Box::new(move |_window, msg, wparam, lparam| on_raw_input_message(&mut state, msg, wparam, lparam))),
+                on_close: Some(Box::new(unregister_keyboards))
+            })
+        })?;
+
+        thread::spawn(move || {
+            for dispatch in receiver {
+                let _ = catch_unwind(AssertUnwindSafe(|| match dispatch {
+                    Dispatch::Key(raw_key_event) => callback(raw_key_event),
+                    Dispatch::Device(change) => {
+                        if let Some(device_change_callback) = device_change_callback.as_mut() {
+                            device_change_callback(change);
+                        }
+                    }
+                }));
+            }
+        });
+
+        Ok(RawInputListener { window })
+    }
+}
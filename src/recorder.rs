@@ -0,0 +1,606 @@
+/*
+
+Session recorder
+
+Appends every event as a line of JSON with the time it was recorded, e.g.
+{"time_ms":1718000000000,"event":"press","vk":65}
+or as a record of the compact binary format in session_format, which SessionReplayer can play back.
+The file is rotated once it grows past a size or age limit: the current file becomes <path>.1, the previous
+<path>.1 becomes <path>.2 and so on, up to the number of files kept. Redacted fields are left out of each line, or
+zeroed in binary records.
+Key redaction keeps the timing of keystrokes: letter, digit and symbol keys are recorded as their category, which
+keeps what was typed unreadable, or every key code as a salted hash. A hash stands for the same key throughout the
+file, so hashed text is a substitution cipher that letter frequencies break even without the salt, it only keeps key
+codes out of plain sight. Binary records hold a single byte per key code, so they get stand-in keys instead (A, 0
+and / for the categories, the key codes ranked by their hash when hashing).
+Between SensitiveInputStarted and SensitiveInputEnded (privacy mode) only events not tied to a key are recorded.
+Recording into an existing file appends to it, which fails if the file holds the other format.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey, WindowHandle};
+use crate::session_format::{self, HEADER};
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "tokio")]
+use tokio::sync::broadcast;
+#[cfg(feature = "tokio")]
+use tokio::task::JoinHandle;
+
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SessionFormat {
+    // a line of JSON per event
+    #[default]
+    JsonLines,
+    // length prefixed binary records, smaller and faster to write, read back with SessionReplayer
+    Binary
+}
+
+// fields that can be left out of recordings, e.g. to keep typed text out of analytics
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RedactedField {
+    // key codes of key events, the event kind is still recorded
+    Key,
+    // characters typed in text mode
+    Char,
+    // cursor positions and movement
+    Position,
+    // foreground window handles and process ids
+    Window
+}
+
+// how key codes (and the characters they type) are recorded
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum KeyRedaction {
+    #[default]
+    None,
+    // letter, digit and symbol keys and characters become "letter", "digit" or "symbol",
+    // other keys (modifiers, enter, space, arrows, ...) are recorded as they are
+    Category,
+    // key codes become a hash of the salt and the code, equal for the same key and salt, which tells keys apart (e.g.
+    // for per key timing) without naming them. Not a way to hide text: the same key always gets the same hash, so
+    // letter frequencies give the keys away, salt or no salt. Characters are left out
+    Hashed(Vec<u8>)
+}
+
+
+
+pub struct RecorderBuilder {
+    path: PathBuf,
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+    kept_files: usize,
+    format: SessionFormat,
+    redaction: Redaction
+}
+
+impl RecorderBuilder {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        RecorderBuilder { path: path.into(), max_size: None, max_age: None, kept_files: 5, format: SessionFormat::default(), redaction: Redaction::default() }
+    }
+
+    pub fn format(mut self, format: SessionFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    // rotates once the file reaches max_size bytes
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    // rotates once the file has been written to for max_age
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    // rotated files kept next to the current one, older ones are deleted
+    pub fn kept_files(mut self, kept_files: usize) -> Self {
+        self.kept_files = kept_files;
+        self
+    }
+
+    pub fn redact(mut self, field: RedactedField) -> Self {
+        self.redaction.fields.insert(field);
+        self
+    }
+
+    // RedactedField::Key still leaves key codes out altogether
+    pub fn key_redaction(mut self, key_redaction: KeyRedaction) -> Self {
+        self.redaction.set_keys(key_redaction);
+        self
+    }
+
+    pub fn open(self) -> io::Result<Recorder> {
+        let header: &'static [u8] = match self.format {
+            SessionFormat::JsonLines => &[],
+            SessionFormat::Binary => &HEADER
+        };
+        check_existing_format(&self.path, self.format)?;
+        let file = RotatingFile::open(self.path, header, self.max_size, self.max_age, self.kept_files)?;
+        Ok(Recorder { file, format: self.format, redaction: self.redaction, paused: false, record: Vec::new() })
+    }
+}
+
+
+
+pub struct Recorder {
+    file: RotatingFile,
+    format: SessionFormat,
+    redaction: Redaction,
+    // a password field has the focus
+    paused: bool,
+    // reused for every record
+    record: Vec<u8>
+}
+
+impl Recorder {
+    pub fn builder(path: impl Into<PathBuf>) -> RecorderBuilder {
+        RecorderBuilder::new(path)
+    }
+
+    pub fn record(&mut self, key_event: &KeyEvent) -> io::Result<()> {
+        match key_event {
+            KeyEvent::SensitiveInputStarted => self.paused = true,
+            KeyEvent::SensitiveInputEnded => self.paused = false,
+            // the markers themselves are recorded, what was typed in between is not
+            _ if self.paused && key_event.vk().is_some() => return Ok(()),
+            _ => {}
+        }
+        self.record.clear();
+        encode_event(self.format, key_event, &self.redaction, &mut self.record);
+        self.file.write(&self.record)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+
+    // records every event received from a subscription (KeyListener::subscribe) until the listener is gone, on a
+    // blocking thread so file writes never stall a runtime worker. Ends early with the first write error, otherwise
+    // returns the number of events missed because the recorder fell behind the subscription
+    #[cfg(feature = "tokio")]
+    pub fn spawn(mut self, mut receiver: broadcast::Receiver<KeyEvent>) -> JoinHandle<io::Result<u64>> {
+        tokio::task::spawn_blocking(move || {
+            let mut dropped_events = 0;
+            loop {
+                match receiver.blocking_recv() {
+                    Ok(key_event) => self.record(&key_event)?,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(skipped, "recorder fell behind, events are missing from the recording");
+                        dropped_events += skipped;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break
+                }
+                // flushed once the recorder caught up, so nothing is lost when the process ends
+                if receiver.is_empty() {
+                    self.flush()?;
+                }
+            }
+            self.flush()?;
+            Ok(dropped_events)
+        })
+    }
+}
+
+
+
+// what encode_event leaves out or replaces
+#[derive(Clone, Default)]
+pub(crate) struct Redaction {
+    pub(crate) fields: HashSet<RedactedField>,
+    keys: KeyRedaction,
+    // for KeyRedaction::Hashed in binary records, the stand-in of each key code: its rank among the hashes of all
+    // 256 codes, so different keys keep different stand-ins
+    stand_ins: Option<Box<[u8; 256]>>
+}
+
+impl Redaction {
+    pub(crate) fn set_keys(&mut self, keys: KeyRedaction) {
+        self.stand_ins = match &keys {
+            KeyRedaction::Hashed(salt) => {
+                let mut codes: Vec<u8> = (0..=255).collect();
+                codes.sort_by_key(|code| key_hash(salt, *code));
+                let mut stand_ins = Box::new([0; 256]);
+                for (rank, code) in codes.into_iter().enumerate() {
+                    stand_ins[code as usize] = rank as u8;
+                }
+                Some(stand_ins)
+            }
+            _ => None
+        };
+        self.keys = keys;
+    }
+
+    fn is_redacted(&self, field: RedactedField) -> bool {
+        self.fields.contains(&field)
+    }
+
+    fn stand_in_key(&self, vk: VirtualKey) -> VirtualKey {
+        match &self.keys {
+            _ if self.is_redacted(RedactedField::Key) => VirtualKey::from(0),
+            KeyRedaction::None => vk,
+            KeyRedaction::Category => KeyCategory::of_key(vk).map_or(vk, KeyCategory::stand_in_key),
+            KeyRedaction::Hashed(_) => self.stand_ins.as_ref().map_or(vk, |stand_ins| VirtualKey::from(stand_ins[vk.code() as u8 as usize] as i32))
+        }
+    }
+
+    fn stand_in_char(&self, ch: char) -> char {
+        match &self.keys {
+            _ if self.is_redacted(RedactedField::Char) => '\0',
+            KeyRedaction::None => ch,
+            KeyRedaction::Category => KeyCategory::of_char(ch).map_or(ch, KeyCategory::stand_in_char),
+            KeyRedaction::Hashed(_) => '\0'
+        }
+    }
+
+    // the "vk" field, or what replaces it
+    fn write_key(&self, fields: &mut String, vk: VirtualKey) {
+        let _ = match &self.keys {
+            _ if self.is_redacted(RedactedField::Key) => Ok(()),
+            KeyRedaction::Category if let Some(category) = KeyCategory::of_key(vk) => write!(fields, ",\"category\":\"{}\"", category.name()),
+            KeyRedaction::Hashed(salt) => write!(fields, ",\"key_hash\":\"{:016x}\"", key_hash(salt, vk.code() as u8)),
+            _ => write!(fields, ",\"vk\":{}", vk.code())
+        };
+    }
+
+    // the "vk" and "ch" fields of a Char event, or what replaces them
+    fn write_char(&self, fields: &mut String, vk: VirtualKey, ch: char) {
+        let _ = match &self.keys {
+            KeyRedaction::Category if let Some(category) = KeyCategory::of_char(ch) => write!(fields, ",\"category\":\"{}\"", category.name()),
+            KeyRedaction::Hashed(_) => {
+                self.write_key(fields, vk);
+                Ok(())
+            }
+            _ => {
+                self.write_key(fields, vk);
+                if self.is_redacted(RedactedField::Char) {
+                    Ok(())
+                } else {
+                    write!(fields, ",\"ch\":{}", json_string(&ch.to_string()))
+                }
+            }
+        };
+    }
+}
+
+// FNV-1a, unlike std's DefaultHasher it gives the same hashes across Rust versions, so recordings stay comparable
+fn key_hash(salt: &[u8], code: u8) -> u64 {
+    salt.iter().chain([code].iter()).fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+#[derive(Clone, Copy)]
+enum KeyCategory {
+    Letter,
+    Digit,
+    Symbol
+}
+
+impl KeyCategory {
+    fn of_key(vk: VirtualKey) -> Option<KeyCategory> {
+        match vk.code() {
+            0x41..=0x5A => Some(KeyCategory::Letter),
+            // top row and numpad
+            0x30..=0x39 | 0x60..=0x69 => Some(KeyCategory::Digit),
+            // numpad operators and the OEM punctuation keys
+            0x6A..=0x6F | 0xBA..=0xC0 | 0xDB..=0xDF | 0xE2 => Some(KeyCategory::Symbol),
+            _ => None
+        }
+    }
+
+    // whitespace and control characters are kept
+    fn of_char(ch: char) -> Option<KeyCategory> {
+        if ch.is_alphabetic() {
+            Some(KeyCategory::Letter)
+        } else if ch.is_numeric() {
+            Some(KeyCategory::Digit)
+        } else if ch.is_whitespace() || ch.is_control() {
+            None
+        } else {
+            Some(KeyCategory::Symbol)
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            KeyCategory::Letter => "letter",
+            KeyCategory::Digit => "digit",
+            KeyCategory::Symbol => "symbol"
+        }
+    }
+
+    fn stand_in_key(self) -> VirtualKey {
+        match self {
+            KeyCategory::Letter => VirtualKey::from(0x41),
+            KeyCategory::Digit => VirtualKey::from(0x30),
+            // VK_OEM_2, / on US layouts
+            KeyCategory::Symbol => VirtualKey::from(0xBF)
+        }
+    }
+
+    fn stand_in_char(self) -> char {
+        match self {
+            KeyCategory::Letter => 'a',
+            KeyCategory::Digit => '0',
+            KeyCategory::Symbol => '/'
+        }
+    }
+}
+
+
+
+// one record of a session file, stamped with the current time
+pub(crate) fn encode_event(format: SessionFormat, key_event: &KeyEvent, redaction: &Redaction, record: &mut Vec<u8>) {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    match format {
+        SessionFormat::JsonLines => {
+            let line = format!("{{\"time_ms\":{},{}}}\n", time.as_millis(), json_fields(key_event, redaction));
+            record.extend(line.as_bytes());
+        }
+        SessionFormat::Binary => {
+            session_format::encode(time.as_micros() as u64, &redacted_event(*key_event, redaction), record);
+        }
+    }
+}
+
+fn redacted_event(mut key_event: KeyEvent, redaction: &Redaction) -> KeyEvent {
+    let is_redacted = |field| redaction.is_redacted(field);
+    match &mut key_event {
+        KeyEvent::Press(vk) | KeyEvent::Release(vk) | KeyEvent::ToggleChanged { key: vk, .. } | KeyEvent::Coalesced { key: vk, .. }
+        | KeyEvent::ModifierTap(vk) | KeyEvent::ModifierDoubleTap(vk) => {
+            *vk = redaction.stand_in_key(*vk);
+        }
+        KeyEvent::Char { key, ch } => {
+            // a category stand-in follows the character, the key may not have a category of its own (e.g. AltGr chords)
+            *key = match (&redaction.keys, KeyCategory::of_char(*ch)) {
+                (KeyRedaction::Category, Some(category)) if !is_redacted(RedactedField::Key) => category.stand_in_key(),
+                _ => redaction.stand_in_key(*key)
+            };
+            *ch = redaction.stand_in_char(*ch);
+        }
+        KeyEvent::MouseMove { x, y, dx, dy } if is_redacted(RedactedField::Position) => {
+            (*x, *y, *dx, *dy) = (0, 0, 0, 0);
+        }
+        KeyEvent::FocusChanged { window, process_id } if is_redacted(RedactedField::Window) => {
+            (*window, *process_id) = (WindowHandle(0), 0);
+        }
+        _ => {}
+    }
+    key_event
+}
+
+fn json_fields(key_event: &KeyEvent, redaction: &Redaction) -> String {
+    let position = !redaction.is_redacted(RedactedField::Position);
+    let window = !redaction.is_redacted(RedactedField::Window);
+
+    let mut fields = format!("\"event\":\"{}\"", event_name(key_event));
+    // writing to a String cannot fail
+    let _ = match *key_event {
+        KeyEvent::Press(vk) | KeyEvent::Release(vk) | KeyEvent::ModifierTap(vk) | KeyEvent::ModifierDoubleTap(vk) => {
+            redaction.write_key(&mut fields, vk);
+            Ok(())
+        }
+        KeyEvent::ToggleChanged { key: vk, on } => {
+            redaction.write_key(&mut fields, vk);
+            write!(fields, ",\"on\":{}", on)
+        }
+        KeyEvent::Char { key: vk, ch } => {
+            redaction.write_char(&mut fields, vk, ch);
+            Ok(())
+        }
+        KeyEvent::LayoutChanged(layout) => write!(fields, ",\"layout\":{}", layout.0),
+        KeyEvent::MouseMove { x, y, dx, dy } if position => write!(fields, ",\"x\":{},\"y\":{},\"dx\":{},\"dy\":{}", x, y, dx, dy),
+        KeyEvent::MouseWheel { delta, horizontal } => write!(fields, ",\"delta\":{},\"horizontal\":{}", delta, horizontal),
+        KeyEvent::GamepadPress { pad, button } | KeyEvent::GamepadRelease { pad, button } => {
+            write!(fields, ",\"pad\":{},\"button\":{}", pad, button.0)
+        }
+        KeyEvent::GamepadAxis { pad, axis, value } => write!(fields, ",\"pad\":{},\"axis\":\"{:?}\",\"value\":{}", pad, axis, value),
+        KeyEvent::GamepadConnected { pad, connected } => write!(fields, ",\"pad\":{},\"connected\":{}", pad, connected),
+        KeyEvent::FocusChanged { window: handle, process_id } if window => {
+            write!(fields, ",\"window\":{},\"process_id\":{}", handle.0, process_id)
+        }
+        KeyEvent::IdleStarted(idle) => write!(fields, ",\"idle_ms\":{}", idle.as_millis()),
+        KeyEvent::Coalesced { key: vk, presses } => {
+            redaction.write_key(&mut fields, vk);
+            write!(fields, ",\"presses\":{}", presses)
+        }
+        _ => Ok(())
+    };
+    fields
+}
+
+fn event_name(key_event: &KeyEvent) -> &'static str {
+    match key_event {
+        KeyEvent::Press(_) => "press",
+        KeyEvent::Release(_) => "release",
+        KeyEvent::ToggleChanged { .. } => "toggle_changed",
+        KeyEvent::Char { .. } => "char",
+        KeyEvent::LayoutChanged(_) => "layout_changed",
+        KeyEvent::MouseMove { .. } => "mouse_move",
+        KeyEvent::MouseWheel { .. } => "mouse_wheel",
+        KeyEvent::GamepadPress { .. } => "gamepad_press",
+        KeyEvent::GamepadRelease { .. } => "gamepad_release",
+        KeyEvent::GamepadAxis { .. } => "gamepad_axis",
+        KeyEvent::GamepadConnected { .. } => "gamepad_connected",
+        KeyEvent::FocusChanged { .. } => "focus_changed",
+        KeyEvent::SessionLocked => "session_locked",
+        KeyEvent::SessionUnlocked => "session_unlocked",
+        KeyEvent::Suspended => "suspended",
+        KeyEvent::Resumed => "resumed",
+        KeyEvent::IdleStarted(_) => "idle_started",
+        KeyEvent::IdleEnded => "idle_ended",
+        KeyEvent::Coalesced { .. } => "coalesced",
+        KeyEvent::ModifierTap(_) => "modifier_tap",
+        KeyEvent::ModifierDoubleTap(_) => "modifier_double_tap",
+        KeyEvent::SensitiveInputStarted => "sensitive_input_started",
+        KeyEvent::SensitiveInputEnded => "sensitive_input_ended"
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", ch as u32);
+            }
+            ch => json.push(ch)
+        }
+    }
+    json.push('"');
+    json
+}
+
+
+
+// appending must not mix JSON lines and binary records, a missing or empty file can take either
+fn check_existing_format(path: &Path, format: SessionFormat) -> io::Result<()> {
+    let mut start = Vec::with_capacity(HEADER.len());
+    match File::open(path) {
+        Ok(file) => file.take(HEADER.len() as u64).read_to_end(&mut start)?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error)
+    };
+    if start.is_empty() {
+        return Ok(());
+    }
+    let is_binary = start == HEADER;
+    match format {
+        SessionFormat::Binary if !is_binary => Err(io::Error::new(
+            io::ErrorKind::InvalidData, "the file is not a binary session, it cannot be appended to as one"
+        )),
+        SessionFormat::JsonLines if is_binary => Err(io::Error::new(
+            io::ErrorKind::InvalidData, "the file is a binary session, it cannot be appended to as JSON lines"
+        )),
+        _ => Ok(())
+    }
+}
+
+
+
+// a file that is moved aside and started over once it is too big or too old
+pub(crate) struct RotatingFile {
+    path: PathBuf,
+    // written at the start of every file
+    header: &'static [u8],
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+    kept_files: usize,
+    writer: BufWriter<File>,
+    size: u64,
+    opened: Instant
+}
+
+impl RotatingFile {
+    pub(crate) fn open(
+        path: PathBuf,
+        header: &'static [u8],
+        max_size: Option<u64>,
+        max_age: Option<Duration>,
+        kept_files: usize
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        let mut rotating_file = RotatingFile {
+            path, header, max_size, max_age, kept_files, writer: BufWriter::new(file), size, opened: Instant::now()
+        };
+        if size == 0 {
+            rotating_file.write_header()?;
+        }
+        Ok(rotating_file)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        self.writer.write_all(self.header)?;
+        self.size = self.header.len() as u64;
+        Ok(())
+    }
+
+    // records are never split between two files
+    pub(crate) fn write(&mut self, record: &[u8]) -> io::Result<()> {
+        let has_records = self.size > self.header.len() as u64;
+        let too_big = self.max_size.is_some_and(|max_size| has_records && self.size + record.len() as u64 > max_size);
+        let too_old = self.max_age.is_some_and(|max_age| self.opened.elapsed() >= max_age);
+        if too_big || too_old {
+            self.rotate()?;
+        }
+        self.writer.write_all(record)?;
+        self.size += record.len() as u64;
+        Ok(())
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        if self.kept_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            // the oldest file is overwritten by the one before it
+            for index in (1..self.kept_files).rev() {
+                let from = rotated_path(&self.path, index);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.writer = BufWriter::new(file);
+        self.opened = Instant::now();
+        self.write_header()
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", index));
+    PathBuf::from(rotated)
+}
+
+
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn spawned_recorder_counts_missed_events() {
+        let path = std::env::temp_dir().join(format!("win-key-event-missed-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let recorder = Recorder::builder(&path).open().unwrap();
+
+        let (sender, receiver) = broadcast::channel(1);
+        for vk_code in 0x41..=0x43 {
+            sender.send(KeyEvent::Press(VirtualKey(vk_code))).unwrap();
+        }
+        drop(sender);
+        let dropped_events = recorder.spawn(receiver).await.unwrap().unwrap();
+
+        let lines = fs::read_to_string(&path).unwrap().lines().count();
+        let _ = fs::remove_file(&path);
+        assert_eq!((dropped_events, lines), (2, 1));
+    }
+}
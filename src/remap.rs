@@ -0,0 +1,135 @@
+/*
+
+Key remapping with a WH_KEYBOARD_LL hook
+
+A remapped key is consumed by the hook and its target is injected with SendInput in its place, so other
+applications only ever see the target. The target chosen on press is the one released, and key repeats of a held
+source key are passed on as repeats of the target.
+
+*/
+
+
+use crate::VirtualKey;
+use crate::hook::{HookThread, note_event_time, spawn_hook_thread};
+use crate::message_loop::MessageLoop;
+use crate::error::KeyListenerError;
+use crate::inject::{INJECTED_EXTRA_INFO, send_key};
+
+use windows::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+use windows::Win32::UI::WindowsAndMessaging::{CallNextHookEx, HC_ACTION, KBDLLHOOKSTRUCT, LLKHF_INJECTED, LLKHF_UP, WH_KEYBOARD_LL};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Duration;
+
+
+
+// the hook reports left / right modifier codes, so modifiers are mapped by their sided codes (VK_LCONTROL, ...)
+#[derive(Clone, Debug, Default)]
+pub struct Remapper {
+    mappings: HashMap<VirtualKey, VirtualKey>,
+    message_loop: MessageLoop
+}
+
+impl Remapper {
+    pub fn new() -> Self {
+        Remapper::default()
+    }
+
+    pub fn map(mut self, from: impl Into<VirtualKey>, to: impl Into<VirtualKey>) -> Self {
+        self.mappings.insert(from.into(), to.into());
+        self
+    }
+
+    pub fn swap(self, a: impl Into<VirtualKey>, b: impl Into<VirtualKey>) -> Self {
+        let (a, b) = (a.into(), b.into());
+        self.map(a, b).map(b, a)
+    }
+
+    // install the hook on the calling thread, driven by the application's message loop, see MessageLoop
+    pub fn message_loop(mut self, message_loop: MessageLoop) -> Self {
+        self.message_loop = message_loop;
+        self
+    }
+
+    pub fn start(self) -> Result<ActiveRemapper, KeyListenerError> {
+        let mappings = self.mappings;
+        let setup = move || REMAP_HOOK_STATE.set(Some(RemapHookState { mappings, held: HashMap::new() }));
+        let hook_thread = spawn_hook_thread(WH_KEYBOARD_LL, remap_hook_proc, self.message_loop, setup, || REMAP_HOOK_STATE.set(None))?;
+        Ok(ActiveRemapper { hook_thread })
+    }
+}
+
+// removes the mappings when quit
+pub struct ActiveRemapper {
+    hook_thread: HookThread
+}
+
+impl ActiveRemapper {
+    pub fn quit(&self) {
+        self.hook_thread.stop();
+    }
+
+    // times the hook was installed again after Windows removed it for being too slow, see hook_gap
+    pub fn hook_reinstalls(&self) -> u32 {
+        self.hook_thread.reinstalls()
+    }
+
+    // roughly how long events went unseen before the last reinstall
+    pub fn hook_gap(&self) -> Option<Duration> {
+        self.hook_thread.last_gap()
+    }
+}
+
+
+
+struct RemapHookState {
+    mappings: HashMap<VirtualKey, VirtualKey>,
+    // held source keys and the target injected for them
+    held: HashMap<VirtualKey, VirtualKey>
+}
+
+thread_local! {
+    static REMAP_HOOK_STATE: RefCell<Option<RemapHookState>> = const { RefCell::new(None) };
+}
+
+unsafe extern "system" fn remap_hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code == HC_ACTION as i32 {
+        let info = unsafe { &*(lparam.0 as *const KBDLLHOOKSTRUCT) };
+        note_event_time(info.time, info.flags.0 & LLKHF_INJECTED.0 != 0);
+        // injected targets come back through the hook and must not be remapped again
+        let remapped = info.dwExtraInfo != INJECTED_EXTRA_INFO && REMAP_HOOK_STATE.with_borrow_mut(|state| {
+            state.as_mut().is_some_and(|state| state.remap(info))
+        });
+        if remapped {
+            return LRESULT(1);
+        }
+    }
+    unsafe { CallNextHookEx(None, code, wparam, lparam) }
+}
+
+impl RemapHookState {
+    // injects the target and returns whether the original key event should be consumed
+    fn remap(&mut self, info: &KBDLLHOOKSTRUCT) -> bool {
+        let key = VirtualKey(info.vkCode as i32);
+
+        if info.flags.0 & LLKHF_UP.0 != 0 {
+            return match self.held.remove(&key) {
+                Some(target) => {
+                    send_key(target, true);
+                    true
+                }
+                None => false
+            };
+        }
+
+        // repeats keep the target picked by the first press
+        let target = match self.held.get(&key).or(self.mappings.get(&key)) {
+            Some(target) => *target,
+            None => return false
+        };
+        self.held.insert(key, target);
+        send_key(target, false);
+        true
+    }
+}
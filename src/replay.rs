@@ -0,0 +1,72 @@
+/*
+
+Session replay
+
+Plays a binary session (SessionFormat::Binary) back through a listener: the events go through its filters and
+reach its callbacks and subscribers like polled ones, with the gaps between them as recorded.
+
+*/
+
+
+use crate::KeyEvent;
+use crate::session_format::read_session;
+use crate::tokio_listener::KeyListener;
+
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Duration};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+
+
+pub struct SessionReplayer {
+    // microseconds since the unix epoch
+    events: Vec<(u64, KeyEvent)>,
+    speed: f64
+}
+
+impl SessionReplayer {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        SessionReplayer::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader(reader: impl Read) -> io::Result<Self> {
+        Ok(SessionReplayer { events: read_session(reader)?, speed: 1.0 })
+    }
+
+    // 2.0 replays twice as fast as recorded, 0.5 half as fast. Panics unless speed is positive and finite
+    pub fn speed(mut self, speed: f64) -> Self {
+        assert!(speed > 0.0 && speed.is_finite(), "replay speed must be positive and finite, got {}", speed);
+        self.speed = speed;
+        self
+    }
+
+    pub fn events(&self) -> impl Iterator<Item = KeyEvent> + '_ {
+        self.events.iter().map(|(_, key_event)| *key_event)
+    }
+
+    // how long replaying takes at the original speed
+    pub fn duration(&self) -> Duration {
+        match (self.events.first(), self.events.last()) {
+            (Some((first, _)), Some((last, _))) => Duration::from_micros(last.saturating_sub(*first)),
+            _ => Duration::ZERO
+        }
+    }
+
+    // completes once the last event was sent. Polling carries on meanwhile (unless the listener was quit), so
+    // real and replayed events can mix
+    pub async fn replay(&self, key_listener: &Arc<Mutex<KeyListener>>) {
+        let injector = key_listener.lock().await.injector();
+        let mut previous_time = None;
+        for (time_us, key_event) in self.events.iter().copied() {
+            if let Some(previous_time) = previous_time {
+                let gap = Duration::from_micros(time_us.saturating_sub(previous_time));
+                sleep(gap.div_f64(self.speed)).await;
+            }
+            previous_time = Some(time_us);
+            injector.inject(key_event).await;
+        }
+    }
+}
@@ -0,0 +1,140 @@
+/*
+
+Rhai scripts as filter stages
+
+A script defines `fn on_key(event)`, which is called for every press and release with a map holding the key's
+virtual key code (`event.key`) and whether it went down (`event.down`). Its return value decides what happens
+to the event: nothing or true passes it on, false drops it and a key code passes it on as that key. Other events
+bypass the script.
+
+Scripts can also act on the system, `press(vk)`, `release(vk)` and `tap(vk)` inject keys with SendInput and
+`run(command)` starts a command with `cmd /C`. Injected keys are polled like typed ones, so a script seeing its
+own keys again has to let them through. `key(name)` returns the code of a key by its hotkey name, 0 if unknown.
+
+https://rhai.rs/book/
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+use crate::hotkey::parse_key_name;
+use crate::inject::send_key;
+
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ScriptError {
+    Io(std::io::Error),
+    Parse(rhai::ParseError),
+    // the script's top level statements failed
+    Run(String),
+    // the script does not define on_key(event)
+    MissingHandler
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Io(error) => write!(f, "the script could not be read: {}", error),
+            ScriptError::Parse(error) => write!(f, "the script could not be parsed: {}", error),
+            ScriptError::Run(error) => write!(f, "the script failed: {}", error),
+            ScriptError::MissingHandler => write!(f, "the script does not define on_key(event)")
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScriptError::Io(error) => Some(error),
+            ScriptError::Parse(error) => Some(error),
+            ScriptError::Run(_) | ScriptError::MissingHandler => None
+        }
+    }
+}
+
+
+
+pub struct KeyScript {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>
+}
+
+impl KeyScript {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ScriptError> {
+        let source = std::fs::read_to_string(path).map_err(ScriptError::Io)?;
+        KeyScript::from_source(&source)
+    }
+
+    // compiles the script and runs its top level statements once
+    pub fn from_source(source: &str) -> Result<Self, ScriptError> {
+        let engine = script_engine();
+        let ast = engine.compile(source).map_err(ScriptError::Parse)?;
+        if !ast.iter_functions().any(|function| function.name == "on_key" && function.params.len() == 1) {
+            return Err(ScriptError::MissingHandler);
+        }
+
+        let mut scope = Scope::new();
+        engine.run_ast_with_scope(&mut scope, &ast).map_err(|error| ScriptError::Run(error.to_string()))?;
+        Ok(KeyScript { engine, ast, scope })
+    }
+
+    pub(crate) fn process(&mut self, key_event: KeyEvent) -> Option<KeyEvent> {
+        let (key, down) = match key_event {
+            KeyEvent::Press(key) => (key, true),
+            KeyEvent::Release(key) => (key, false),
+            _ => return Some(key_event)
+        };
+
+        let mut event = Map::new();
+        event.insert("key".into(), Dynamic::from_int(key.code() as i64));
+        event.insert("down".into(), Dynamic::from_bool(down));
+
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self.engine.call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, "on_key", (event,));
+        let result = match result {
+            Ok(result) => result,
+            // a failing script must not swallow input, the event is passed on unchanged
+            Err(_error) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(?key_event, error = %_error, "on_key failed");
+                return Some(key_event);
+            }
+        };
+
+        if let Ok(pass) = result.as_bool() {
+            return pass.then_some(key_event);
+        }
+        match result.as_int() {
+            Ok(vk_code @ 0x01..=0xFE) => {
+                let key = VirtualKey::from(vk_code as i32);
+                Some(if down { KeyEvent::Press(key) } else { KeyEvent::Release(key) })
+            }
+            _ => Some(key_event)
+        }
+    }
+}
+
+fn script_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.register_fn("press", |vk_code: i64| send_key(VirtualKey::from(vk_code as i32), false));
+    engine.register_fn("release", |vk_code: i64| send_key(VirtualKey::from(vk_code as i32), true));
+    engine.register_fn("tap", |vk_code: i64| {
+        send_key(VirtualKey::from(vk_code as i32), false);
+        send_key(VirtualKey::from(vk_code as i32), true);
+    });
+    engine.register_fn("run", |command_line: &str| {
+        let _ = Command::new("cmd").arg("/C").arg(command_line).spawn();
+    });
+    engine.register_fn("key", |name: &str| {
+        parse_key_name(&name.to_lowercase()).map_or(0, |vk| vk.code() as i64)
+    });
+    engine
+}
@@ -0,0 +1,136 @@
+/*
+
+End to end self test
+
+Injects real key presses with SendInput and checks that a listener reports them, to verify a polling interval and
+backend choice capture a workload on the machine at hand. Injected keys reach the focused window like typed ones,
+so keys without an effect there (e.g. F13 - F24) are the safest to test with.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+use crate::builder::KeyListenerBuilder;
+use crate::channel::EventReceiver;
+use crate::error::KeyListenerError;
+use crate::inject::send_key;
+
+use tokio::time::{timeout, Duration, Instant};
+use std::fmt;
+
+
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SelfTestError {
+    Listener(KeyListenerError),
+    // the event was injected but the listener did not report it in time
+    Missed(KeyEvent)
+}
+
+impl fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelfTestError::Listener(error) => write!(f, "the listener could not be spawned: {}", error),
+            SelfTestError::Missed(key_event) => write!(f, "{:?} was injected but not reported", key_event)
+        }
+    }
+}
+
+impl std::error::Error for SelfTestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SelfTestError::Listener(error) => Some(error),
+            SelfTestError::Missed(_) => None
+        }
+    }
+}
+
+impl From<KeyListenerError> for SelfTestError {
+    fn from(error: KeyListenerError) -> Self {
+        SelfTestError::Listener(error)
+    }
+}
+
+
+
+// presses and releases each key in turn, every press and release has to be reported within timeout.
+// The keys have to be among the ones builder watches
+pub async fn self_test(builder: KeyListenerBuilder, keys: &[VirtualKey], timeout: Duration) -> Result<(), SelfTestError> {
+    let (key_listener, mut receiver) = builder.spawn_with_receiver()?;
+
+    let result = press_and_release(&mut receiver, keys, timeout).await;
+
+    key_listener.lock().await.quit();
+    result
+}
+
+async fn press_and_release(receiver: &mut EventReceiver, keys: &[VirtualKey], timeout: Duration) -> Result<(), SelfTestError> {
+    for &vk in keys {
+        for key_event in [KeyEvent::Press(vk), KeyEvent::Release(vk)] {
+            send_key(vk, key_event == KeyEvent::Release(vk));
+            if arrival(receiver, key_event, timeout).await.is_none() {
+                // the key is left up whatever happened
+                send_key(vk, true);
+                return Err(SelfTestError::Missed(key_event));
+            }
+        }
+    }
+    Ok(())
+}
+
+// how long until expected is received, None if not within duration. Other events received meanwhile are skipped
+pub(crate) async fn arrival(receiver: &mut EventReceiver, expected: KeyEvent, duration: Duration) -> Option<Duration> {
+    let start = Instant::now();
+    let arrival = async {
+        while let Some(sequenced) = receiver.recv().await {
+            if sequenced.key_event == expected {
+                return Some(start.elapsed());
+            }
+        }
+        None
+    };
+    timeout(duration, arrival).await.ok().flatten()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::{event_channel, ChannelMode};
+    use crate::sequence::SequencedKeyEvent;
+
+    use std::sync::Arc;
+
+    // a receiver with key_events queued, whose sender is gone like that of a stopped listener
+    async fn receiver_with(key_events: &[KeyEvent]) -> EventReceiver {
+        let (sender, receiver) = event_channel(ChannelMode::Unbounded, Arc::default());
+        for (seq, &key_event) in key_events.iter().enumerate() {
+            sender.send(SequencedKeyEvent { seq: seq as u64, key_event, polled_at: std::time::Instant::now() }).await;
+        }
+        receiver
+    }
+
+    #[tokio::test]
+    async fn other_events_are_skipped() {
+        let f13 = VirtualKey(0x7C);
+        let mut receiver = receiver_with(&[KeyEvent::Press(VirtualKey(0x41)), KeyEvent::Release(f13), KeyEvent::Press(f13)]).await;
+        let arrived = arrival(&mut receiver, KeyEvent::Press(f13), Duration::from_secs(1)).await;
+        assert!(arrived.is_some_and(|latency| latency < Duration::from_secs(1)));
+    }
+
+    #[tokio::test]
+    async fn missed_after_the_timeout() {
+        let (_sender, mut receiver) = event_channel(ChannelMode::Unbounded, Arc::default());
+        let arrived = arrival(&mut receiver, KeyEvent::Press(VirtualKey(0x7C)), Duration::from_millis(20)).await;
+        assert_eq!(arrived, None);
+    }
+
+    #[tokio::test]
+    async fn missed_when_the_listener_stops() {
+        let mut receiver = receiver_with(&[KeyEvent::Press(VirtualKey(0x41))]).await;
+        let arrived = arrival(&mut receiver, KeyEvent::Press(VirtualKey(0x7C)), Duration::from_secs(5)).await;
+        assert_eq!(arrived, None);
+    }
+}
@@ -0,0 +1,85 @@
+/*
+
+Event sequence numbers
+
+Every event a listener sends gets the next number of a per listener sequence, starting at 0. Events are numbered
+in the order they are sent, which within one polling round is the order the keys were polled in, so a press and
+a release of different keys polled in the same round keep their order. A subscriber that sees a number skipped
+knows exactly how many events it missed.
+
+The number is assigned before the event goes anywhere and travels with it through the listener's channel, so
+callbacks can read it with current_sequence_number while they run, and match events to subscribe_sequenced. The
+time of the polling round that saw the event travels along, for code that measures time between events (chords,
+...) and must not depend on how late a subscriber got to receive them.
+
+*/
+
+
+use crate::KeyEvent;
+
+use std::cell::Cell;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::broadcast;
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SequencedKeyEvent {
+    pub seq: u64,
+    pub key_event: KeyEvent,
+    // when the polling round that saw it started, or when it was injected
+    pub polled_at: Instant
+}
+
+// shared by the polling task and event injectors, so injected events join the same sequence
+#[derive(Clone)]
+pub(crate) struct Sequencer {
+    next_seq: Arc<Mutex<u64>>,
+    sender: broadcast::Sender<SequencedKeyEvent>
+}
+
+impl Sequencer {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Sequencer { next_seq: Arc::new(Mutex::new(0)), sender: broadcast::channel(capacity).0 }
+    }
+
+    // numbered and sent under the lock, so subscribers never receive numbers out of order. The numbered event is
+    // what the listener's channel carries on
+    pub(crate) fn send(&self, key_event: KeyEvent, polled_at: Instant) -> SequencedKeyEvent {
+        let mut next_seq = self.next_seq.lock().unwrap();
+        let sequenced = SequencedKeyEvent { seq: *next_seq, key_event, polled_at };
+        *next_seq += 1;
+        let _ = self.sender.send(sequenced); // only fails when there are no subscribers
+        sequenced
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<SequencedKeyEvent> {
+        self.sender.subscribe()
+    }
+
+    // how many events have been numbered so far, i.e. the number the next event gets
+    pub(crate) fn next_seq(&self) -> u64 {
+        *self.next_seq.lock().unwrap()
+    }
+}
+
+
+
+thread_local! {
+    static CURRENT_SEQ: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+// the sequence number of the event the running callback was called for, None outside of callbacks. Async
+// callbacks can read it when called, but not from the future they return
+pub fn current_sequence_number() -> Option<u64> {
+    CURRENT_SEQ.get()
+}
+
+// runs a callback with its event's sequence number readable through current_sequence_number
+pub(crate) fn with_sequence_number<R>(seq: u64, callback: impl FnOnce() -> R) -> R {
+    let outer = CURRENT_SEQ.replace(Some(seq));
+    let result = callback();
+    CURRENT_SEQ.set(outer);
+    result
+}
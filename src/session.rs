@@ -0,0 +1,66 @@
+/*
+
+Secure desktop detection
+
+The lock screen, UAC prompts and the ctrl+alt+del screen run on the secure "Winlogon" desktop. While it is active
+GetAsyncKeyState reports every key as up, so polled key state is meaningless. The input desktop is checked by
+name, and failing to open it at all (the usual result while locked) also counts as the secure desktop.
+
+https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-openinputdesktop
+
+*/
+
+
+#[cfg(feature = "tokio")]
+use crate::KeyEvent;
+
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::System::StationsAndDesktops::{
+    CloseDesktop, GetUserObjectInformationW, OpenInputDesktop, DESKTOP_CONTROL_FLAGS, DESKTOP_READOBJECTS, UOI_NAME
+};
+
+
+
+// true while the lock screen or another secure desktop receives input instead of the user's desktop
+pub fn is_secure_desktop_active() -> bool {
+    unsafe {
+        let Ok(desktop) = OpenInputDesktop(DESKTOP_CONTROL_FLAGS(0), false, DESKTOP_READOBJECTS) else {
+            return true;
+        };
+        let mut name = [0u16; 64];
+        let mut length = 0;
+        let result = GetUserObjectInformationW(
+            HANDLE(desktop.0), UOI_NAME, Some(name.as_mut_ptr().cast()), size_of_val(&name) as u32, Some(&mut length)
+        );
+        let _ = CloseDesktop(desktop);
+
+        // the name is null terminated
+        result.is_ok() && String::from_utf16_lossy(&name).trim_end_matches('\0').eq_ignore_ascii_case("winlogon")
+    }
+}
+
+
+
+// last seen secure desktop state, owned by the polling loop
+#[cfg(feature = "tokio")]
+pub(crate) struct SessionWatcher {
+    emit_events: bool,
+    locked: Option<bool>
+}
+
+#[cfg(feature = "tokio")]
+impl SessionWatcher {
+    pub(crate) fn new(emit_events: bool) -> Self {
+        SessionWatcher { emit_events, locked: None }
+    }
+
+    // returns whether the secure desktop is active, the first poll only records the state
+    pub(crate) fn poll(&mut self, mut emit: impl FnMut(KeyEvent)) -> bool {
+        let locked = is_secure_desktop_active();
+        if self.emit_events && self.locked.is_some_and(|was_locked| was_locked != locked) {
+            emit(if locked { KeyEvent::SessionLocked } else { KeyEvent::SessionUnlocked });
+        }
+        self.locked = Some(locked);
+        locked
+    }
+}
@@ -0,0 +1,209 @@
+/*
+
+Binary session format
+
+A file starts with the magic bytes "WKES" and a format version, followed by one record per event:
+    [record length: u8] [time: u64 microseconds since the unix epoch] [event tag: u8] [event fields]
+Numbers are little endian. The length prefix lets readers skip records of event kinds they do not know.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey, GamepadAxis};
+#[cfg(feature = "tokio")]
+use crate::{KeyboardLayout, GamepadButton, WindowHandle};
+
+#[cfg(feature = "tokio")]
+use std::io::{self, Read};
+#[cfg(feature = "tokio")]
+use std::time::Duration;
+
+
+
+pub(crate) const HEADER: [u8; 5] = [b'W', b'K', b'E', b'S', 1];
+
+const AXES: [GamepadAxis; 6] = [
+    GamepadAxis::LeftTrigger, GamepadAxis::RightTrigger,
+    GamepadAxis::LeftStickX, GamepadAxis::LeftStickY,
+    GamepadAxis::RightStickX, GamepadAxis::RightStickY
+];
+
+
+
+pub(crate) fn encode(time_us: u64, key_event: &KeyEvent, record: &mut Vec<u8>) {
+    let start = record.len();
+    record.push(0); // length, filled in below
+    record.extend(time_us.to_le_bytes());
+
+    match *key_event {
+        KeyEvent::Press(vk) => push_tag_vk(record, 1, vk),
+        KeyEvent::Release(vk) => push_tag_vk(record, 2, vk),
+        KeyEvent::ToggleChanged { key, on } => {
+            push_tag_vk(record, 3, key);
+            record.push(on as u8);
+        }
+        KeyEvent::Char { key, ch } => {
+            push_tag_vk(record, 4, key);
+            record.extend((ch as u32).to_le_bytes());
+        }
+        KeyEvent::LayoutChanged(layout) => {
+            record.push(5);
+            record.extend((layout.0 as i64).to_le_bytes());
+        }
+        KeyEvent::MouseMove { x, y, dx, dy } => {
+            record.push(6);
+            for value in [x, y, dx, dy] {
+                record.extend(value.to_le_bytes());
+            }
+        }
+        KeyEvent::MouseWheel { delta, horizontal } => {
+            record.push(7);
+            record.extend(delta.to_le_bytes());
+            record.push(horizontal as u8);
+        }
+        KeyEvent::GamepadPress { pad, button } | KeyEvent::GamepadRelease { pad, button } => {
+            record.push(if matches!(key_event, KeyEvent::GamepadPress { .. }) { 8 } else { 9 });
+            record.push(pad);
+            record.extend(button.0.to_le_bytes());
+        }
+        KeyEvent::GamepadAxis { pad, axis, value } => {
+            record.push(10);
+            record.push(pad);
+            record.push(AXES.iter().position(|known| *known == axis).unwrap_or_default() as u8);
+            record.extend(value.to_le_bytes());
+        }
+        KeyEvent::GamepadConnected { pad, connected } => {
+            record.extend([11, pad, connected as u8]);
+        }
+        KeyEvent::FocusChanged { window, process_id } => {
+            record.push(12);
+            record.extend((window.0 as i64).to_le_bytes());
+            record.extend(process_id.to_le_bytes());
+        }
+        KeyEvent::SessionLocked => record.push(13),
+        KeyEvent::SessionUnlocked => record.push(14),
+        KeyEvent::Suspended => record.push(15),
+        KeyEvent::Resumed => record.push(16),
+        KeyEvent::IdleStarted(idle) => {
+            record.push(17);
+            record.extend((idle.as_millis() as u64).to_le_bytes());
+        }
+        KeyEvent::IdleEnded => record.push(18),
+        KeyEvent::Coalesced { key, presses } => {
+            push_tag_vk(record, 19, key);
+            record.extend(presses.to_le_bytes());
+        }
+        KeyEvent::ModifierTap(vk) => push_tag_vk(record, 20, vk),
+        KeyEvent::ModifierDoubleTap(vk) => push_tag_vk(record, 21, vk),
+        KeyEvent::SensitiveInputStarted => record.push(22),
+        KeyEvent::SensitiveInputEnded => record.push(23)
+    }
+
+    record[start] = (record.len() - start - 1) as u8;
+}
+
+fn push_tag_vk(record: &mut Vec<u8>, tag: u8, vk: VirtualKey) {
+    record.extend([tag, vk.code() as u8]);
+}
+
+
+
+// None for unknown event kinds and malformed records
+#[cfg(feature = "tokio")]
+fn decode(record: &[u8]) -> Option<(u64, KeyEvent)> {
+    let mut fields = Fields(record);
+    let time_us = u64::from_le_bytes(fields.take()?);
+    let [tag] = fields.take()?;
+
+    let key_event = match tag {
+        1 => KeyEvent::Press(fields.vk()?),
+        2 => KeyEvent::Release(fields.vk()?),
+        3 => KeyEvent::ToggleChanged { key: fields.vk()?, on: fields.bool()? },
+        4 => KeyEvent::Char { key: fields.vk()?, ch: char::from_u32(u32::from_le_bytes(fields.take()?))? },
+        5 => KeyEvent::LayoutChanged(KeyboardLayout(i64::from_le_bytes(fields.take()?) as isize)),
+        6 => KeyEvent::MouseMove {
+            x: i32::from_le_bytes(fields.take()?),
+            y: i32::from_le_bytes(fields.take()?),
+            dx: i32::from_le_bytes(fields.take()?),
+            dy: i32::from_le_bytes(fields.take()?)
+        },
+        7 => KeyEvent::MouseWheel { delta: i16::from_le_bytes(fields.take()?), horizontal: fields.bool()? },
+        8 | 9 => {
+            let [pad] = fields.take()?;
+            let button = GamepadButton(u16::from_le_bytes(fields.take()?));
+            match tag {
+                8 => KeyEvent::GamepadPress { pad, button },
+                _ => KeyEvent::GamepadRelease { pad, button }
+            }
+        }
+        10 => {
+            let [pad, axis] = fields.take()?;
+            KeyEvent::GamepadAxis { pad, axis: *AXES.get(axis as usize)?, value: i16::from_le_bytes(fields.take()?) }
+        }
+        11 => {
+            let [pad] = fields.take()?;
+            KeyEvent::GamepadConnected { pad, connected: fields.bool()? }
+        }
+        12 => KeyEvent::FocusChanged {
+            window: WindowHandle(i64::from_le_bytes(fields.take()?) as isize),
+            process_id: u32::from_le_bytes(fields.take()?)
+        },
+        13 => KeyEvent::SessionLocked,
+        14 => KeyEvent::SessionUnlocked,
+        15 => KeyEvent::Suspended,
+        16 => KeyEvent::Resumed,
+        17 => KeyEvent::IdleStarted(Duration::from_millis(u64::from_le_bytes(fields.take()?))),
+        18 => KeyEvent::IdleEnded,
+        19 => KeyEvent::Coalesced { key: fields.vk()?, presses: u32::from_le_bytes(fields.take()?) },
+        20 => KeyEvent::ModifierTap(fields.vk()?),
+        21 => KeyEvent::ModifierDoubleTap(fields.vk()?),
+        22 => KeyEvent::SensitiveInputStarted,
+        23 => KeyEvent::SensitiveInputEnded,
+        _ => return None
+    };
+    Some((time_us, key_event))
+}
+
+#[cfg(feature = "tokio")]
+struct Fields<'a>(&'a [u8]);
+
+#[cfg(feature = "tokio")]
+impl Fields<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (field, rest) = self.0.split_first_chunk::<N>()?;
+        self.0 = rest;
+        Some(*field)
+    }
+
+    fn vk(&mut self) -> Option<VirtualKey> {
+        let [vk] = self.take()?;
+        Some(VirtualKey::from(vk as i32))
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        let [value] = self.take()?;
+        Some(value != 0)
+    }
+}
+
+
+
+// every event of a session with its time in microseconds since the unix epoch, unknown records are skipped
+#[cfg(feature = "tokio")]
+pub(crate) fn read_session(mut reader: impl Read) -> io::Result<Vec<(u64, KeyEvent)>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let Some(mut rest) = bytes.strip_prefix(&HEADER[..]) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a win-key-event session file"));
+    };
+    let mut events = Vec::new();
+    while let Some((&length, after_length)) = rest.split_first() {
+        let Some((record, after_record)) = after_length.split_at_checked(length as usize) else {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated session record"));
+        };
+        events.extend(decode(record));
+        rest = after_record;
+    }
+    Ok(events)
+}
@@ -0,0 +1,80 @@
+/*
+
+Listener statistics
+
+Counters are updated by the polling task and read at any time through KeyListener::stats, for long running
+programs that want to keep an eye on the listener's health.
+
+*/
+
+
+use tokio::time::{Duration, Instant};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ListenerStats {
+    // events sent to callbacks and subscribers
+    pub events_emitted: u64,
+    // rounds of polling performed
+    pub polls: u64,
+    // how much later than asked for polling resumed after each wait, on average
+    pub average_jitter: Duration,
+    // events lost to a bounded channel's overflow policy
+    pub dropped_events: u64,
+    // events removed by filters (the foreground window filter included), consumers and the rate limiter. Presses
+    // the rate limiter only holds back count once they are merged away, not while they wait
+    pub suppressed_events: u64,
+    // time since the listener was spawned
+    pub uptime: Duration
+}
+
+pub(crate) struct StatsCounters {
+    started: Instant,
+    events_emitted: AtomicU64,
+    polls: AtomicU64,
+    total_jitter_micros: AtomicU64,
+    waits: AtomicU64,
+    suppressed_events: AtomicU64
+}
+
+impl StatsCounters {
+    pub(crate) fn new() -> Self {
+        StatsCounters {
+            started: Instant::now(),
+            events_emitted: AtomicU64::new(0),
+            polls: AtomicU64::new(0),
+            total_jitter_micros: AtomicU64::new(0),
+            waits: AtomicU64::new(0),
+            suppressed_events: AtomicU64::new(0)
+        }
+    }
+
+    pub(crate) fn polled(&self, suppressed_events: u64) {
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.suppressed_events.fetch_add(suppressed_events, Ordering::Relaxed);
+    }
+
+    pub(crate) fn emitted(&self) {
+        self.events_emitted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn waited(&self, jitter: Duration) {
+        self.total_jitter_micros.fetch_add(jitter.as_micros() as u64, Ordering::Relaxed);
+        self.waits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, dropped_events: u64) -> ListenerStats {
+        let waits = self.waits.load(Ordering::Relaxed);
+        let total_jitter = self.total_jitter_micros.load(Ordering::Relaxed);
+        ListenerStats {
+            events_emitted: self.events_emitted.load(Ordering::Relaxed),
+            polls: self.polls.load(Ordering::Relaxed),
+            average_jitter: Duration::from_micros(total_jitter.checked_div(waits).unwrap_or(0)),
+            dropped_events,
+            suppressed_events: self.suppressed_events.load(Ordering::Relaxed),
+            uptime: self.started.elapsed()
+        }
+    }
+}
@@ -0,0 +1,60 @@
+/*
+
+Listener supervision
+
+Restarts a listener whose polling task panicked, waiting initial_backoff before the first restart and twice as
+long before each one after it, up to max_backoff. A listener that kept running for max_backoff since its last
+restart starts over with initial_backoff. Ends with the listener.
+
+*/
+
+
+use crate::tokio_listener::KeyListener;
+use crate::listener_handle::ListenerState;
+use crate::warnings::{ListenerWarning, WarningSender};
+
+use tokio::sync::{watch, Mutex};
+use tokio::time::{sleep, Duration, Instant};
+use std::sync::Weak;
+
+
+
+#[derive(Clone, Copy)]
+pub(crate) struct Supervision {
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration
+}
+
+impl Supervision {
+    pub(crate) fn spawn(self, key_listener: Weak<Mutex<KeyListener>>, mut states: watch::Receiver<ListenerState>, warning_sender: WarningSender) {
+        tokio::spawn(async move {
+            let mut backoff = self.initial_backoff;
+            let mut last_restart: Option<Instant> = None;
+            let mut restarts = 0;
+            while states.changed().await.is_ok() {
+                if *states.borrow_and_update() != ListenerState::Crashed {
+                    continue;
+                }
+                if last_restart.is_some_and(|last_restart| last_restart.elapsed() >= self.max_backoff) {
+                    backoff = self.initial_backoff;
+                }
+                sleep(backoff).await;
+
+                let Some(key_listener) = key_listener.upgrade() else {
+                    break;
+                };
+                let mut key_listener = key_listener.lock().await;
+                // quit or started by hand meanwhile
+                if key_listener.state() != ListenerState::Crashed {
+                    continue;
+                }
+                if key_listener.start().is_ok() {
+                    restarts += 1;
+                    warning_sender.warn(ListenerWarning::Restarted { restarts });
+                }
+                last_restart = Some(Instant::now());
+                backoff = (backoff * 2).min(self.max_backoff);
+            }
+        });
+    }
+}
@@ -0,0 +1,326 @@
+/*
+
+Character translation
+
+Translates a key press into the text it produces with the current modifier state (shift, caps lock, AltGr) and
+the keyboard layout of the foreground window, via ToUnicodeEx. The layout is looked up on every translation, so
+switching input language is picked up immediately.
+
+Translation never changes the keyboard state, so dead keys are buffered here: a dead key produces nothing, and the
+next key's text is composed with its accent (´ + e = é). Text typed through an IME is composed by the IME, while it
+converts keys into native text (kana, hangul, ...) the keys say nothing about the text and no characters are
+produced. An open IME in alphanumeric mode types keys as they are. The IME belongs to the foreground window's
+process and is asked from a thread of its own (see blocking_query), only when the foreground window or layout
+changed or after an IME key switched modes.
+
+https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-tounicodeex
+
+*/
+
+
+use crate::VirtualKey;
+#[cfg(feature = "tokio")]
+use crate::KeyEvent;
+#[cfg(feature = "tokio")]
+use crate::blocking_query::BlockingQuery;
+
+#[cfg(feature = "tokio")]
+use windows::Win32::Foundation::{LPARAM, WPARAM};
+#[cfg(feature = "tokio")]
+use windows::Win32::Globalization::{NormalizeString, NormalizationC};
+#[cfg(feature = "tokio")]
+use windows::Win32::UI::Input::Ime::ImmGetDefaultIMEWnd;
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    GetAsyncKeyState, GetKeyState, GetKeyboardLayout, MapVirtualKeyExW, ToUnicodeEx, HKL, MAPVK_VK_TO_VSC
+};
+use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId};
+#[cfg(feature = "tokio")]
+use windows::Win32::UI::WindowsAndMessaging::{SendMessageTimeoutW, SMTO_ABORTIFHUNG, WM_IME_CONTROL};
+
+
+
+// a keyboard layout handle (HKL), the low word is the input language identifier
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyboardLayout(pub isize);
+
+impl KeyboardLayout {
+    // layout of the foreground window's thread
+    pub fn current() -> Self {
+        KeyboardLayout::from(foreground_layout())
+    }
+
+    pub fn language_id(&self) -> u16 {
+        (self.0 & 0xFFFF) as u16
+    }
+
+    pub fn hkl(&self) -> HKL {
+        HKL(self.0 as *mut _)
+    }
+}
+
+impl From<HKL> for KeyboardLayout {
+    fn from(hkl: HKL) -> Self {
+        KeyboardLayout(hkl.0 as isize)
+    }
+}
+
+
+
+// last seen foreground layout, owned by the polling loop
+#[cfg(feature = "tokio")]
+pub(crate) struct LayoutWatcher {
+    layout: Option<KeyboardLayout>
+}
+
+#[cfg(feature = "tokio")]
+impl LayoutWatcher {
+    pub(crate) fn new() -> Self {
+        LayoutWatcher { layout: None }
+    }
+
+    // the first poll only records the current layout, later polls emit a LayoutChanged when it differs
+    pub(crate) fn poll(&mut self, mut emit: impl FnMut(KeyEvent)) {
+        let current = KeyboardLayout::current();
+        if self.layout.is_some_and(|layout| layout != current) {
+            emit(KeyEvent::LayoutChanged(current));
+        }
+        self.layout = Some(current);
+    }
+}
+
+
+
+const KEY_STATE_DOWN: u8 = 0x80;
+const KEY_STATE_TOGGLED: u8 = 0x01;
+
+// leaves the keyboard state (including pending dead keys) untouched, windows 10 1607 and later
+const TO_UNICODE_NO_STATE_CHANGE: u32 = 0x4;
+
+// WM_IME_CONTROL requests for the conversion mode and whether the IME is open, missing from windows-rs
+#[cfg(feature = "tokio")]
+const IMC_GETCONVERSIONMODE: usize = 0x1;
+#[cfg(feature = "tokio")]
+const IMC_GETOPENSTATUS: usize = 0x5;
+// conversion mode bit for native text (kana, hangul, ...) rather than alphanumeric
+#[cfg(feature = "tokio")]
+const IME_CMODE_NATIVE: isize = 0x1;
+// the IME window belongs to another process, which may be hung
+#[cfg(feature = "tokio")]
+const IME_QUERY_TIMEOUT_MS: u32 = 20;
+
+const MODIFIER_KEYS: [VirtualKey; 9] = [
+    VirtualKey::SHIFT, VirtualKey::LSHIFT, VirtualKey::RSHIFT,
+    VirtualKey::CONTROL, VirtualKey::LCONTROL, VirtualKey::RCONTROL,
+    VirtualKey::MENU, VirtualKey::LMENU, VirtualKey::RMENU
+];
+
+
+
+// keyboard layout of the thread owning the foreground window, which is the layout the user is typing with
+pub(crate) fn foreground_layout() -> HKL {
+    unsafe {
+        let thread_id = GetWindowThreadProcessId(GetForegroundWindow(), None);
+        GetKeyboardLayout(thread_id)
+    }
+}
+
+// the text a press of vk produces right now, None for keys that produce no text and for dead keys
+pub fn translate_key(vk: impl Into<VirtualKey>) -> Option<String> {
+    translate_key_with_layout(vk.into(), foreground_layout())
+}
+
+pub(crate) fn translate_key_with_layout(vk: VirtualKey, layout: HKL) -> Option<String> {
+    match translate(vk, layout) {
+        Translation::Text(text) => Some(text),
+        _ => None
+    }
+}
+
+enum Translation {
+    Text(String),
+    // the spacing form of the dead key's accent, e.g. ´
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    DeadKey(char),
+    Nothing
+}
+
+fn translate(vk: VirtualKey, layout: HKL) -> Translation {
+    let mut key_state = [0u8; 256];
+    for modifier in MODIFIER_KEYS {
+        if unsafe { GetAsyncKeyState(modifier.0) } < 0 {
+            key_state[modifier.0 as usize] |= KEY_STATE_DOWN;
+        }
+    }
+    if unsafe { GetKeyState(VirtualKey::CAPITAL.0) } & 1 != 0 {
+        key_state[VirtualKey::CAPITAL.0 as usize] |= KEY_STATE_TOGGLED;
+    }
+    key_state[(vk.0 & 0xFF) as usize] |= KEY_STATE_DOWN;
+
+    let mut buffer = [0u16; 8];
+    let written = unsafe {
+        let scan_code = MapVirtualKeyExW(vk.0 as u32, MAPVK_VK_TO_VSC, Some(layout));
+        ToUnicodeEx(vk.0 as u32, scan_code, &key_state, &mut buffer, TO_UNICODE_NO_STATE_CHANGE, Some(layout))
+    };
+
+    // negative for dead keys, 0 when the key produces nothing
+    if written < 0 {
+        return char::from_u32(buffer[0] as u32).map_or(Translation::Nothing, Translation::DeadKey);
+    }
+    let text = String::from_utf16_lossy(&buffer[..written as usize]);
+    // ctrl + key chords produce control characters, which are not typed text
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    if text.is_empty() { Translation::Nothing } else { Translation::Text(text) }
+}
+
+
+
+// whether the foreground window's IME composes text from the keys rather than typing them: open and converting
+// into native text. The composition itself lives in the other process's input context, which cannot be read here
+#[cfg(feature = "tokio")]
+fn is_ime_composing() -> bool {
+    unsafe {
+        let ime_window = ImmGetDefaultIMEWnd(GetForegroundWindow());
+        if ime_window.is_invalid() {
+            return false;
+        }
+        let query = |request| {
+            let mut result = 0;
+            let sent = SendMessageTimeoutW(
+                ime_window, WM_IME_CONTROL, WPARAM(request), LPARAM(0), SMTO_ABORTIFHUNG, IME_QUERY_TIMEOUT_MS,
+                Some(&mut result)
+            );
+            (sent.0 != 0).then_some(result as isize)
+        };
+        query(IMC_GETOPENSTATUS).is_some_and(|open| open != 0)
+            && query(IMC_GETCONVERSIONMODE).is_some_and(|mode| mode & IME_CMODE_NATIVE != 0)
+    }
+}
+
+// kana, hangul, kanji, convert, ... and the DBE keys of japanese keyboards, all switch the IME's mode
+#[cfg(feature = "tokio")]
+fn is_ime_key(vk: VirtualKey) -> bool {
+    matches!(vk.0, 0x15..=0x1F | 0xF0..=0xF6)
+}
+
+// the combining form of a dead key accent
+#[cfg(feature = "tokio")]
+fn combining_mark(accent: char) -> Option<char> {
+    let mark = match accent {
+        '`' => '\u{0300}',
+        '´' | '\'' => '\u{0301}',
+        '^' | 'ˆ' => '\u{0302}',
+        '~' | '˜' => '\u{0303}',
+        '¯' => '\u{0304}',
+        '˘' => '\u{0306}',
+        '˙' => '\u{0307}',
+        '¨' | '"' => '\u{0308}',
+        '˚' | '°' => '\u{030A}',
+        '˝' => '\u{030B}',
+        'ˇ' => '\u{030C}',
+        '¸' => '\u{0327}',
+        '˛' => '\u{0328}',
+        _ => return None
+    };
+    Some(mark)
+}
+
+// what typing text after the dead key gives: the composed character when there is one, otherwise the accent
+// followed by the text, like Windows does
+#[cfg(feature = "tokio")]
+fn compose(accent: char, text: &str) -> String {
+    if text == " " {
+        return accent.to_string();
+    }
+    let mut chars = text.chars();
+    if let (Some(base), Some(mark)) = (chars.next(), combining_mark(accent)) {
+        let decomposed: Vec<u16> = [base, mark].iter().collect::<String>().encode_utf16().collect();
+        let mut buffer = [0u16; 8];
+        let written = unsafe { NormalizeString(NormalizationC, &decomposed, Some(&mut buffer)) };
+        if written > 0 {
+            let composed = String::from_utf16_lossy(&buffer[..written as usize]);
+            if composed.chars().count() == 1 {
+                return composed + chars.as_str();
+            }
+        }
+    }
+    format!("{}{}", accent, text)
+}
+
+
+
+// follows presses with the characters they type in text mode, owned by the polling loop
+#[cfg(feature = "tokio")]
+pub(crate) struct CharTranslator {
+    dead_key: Option<char>,
+    // between SensitiveInputStarted and SensitiveInputEnded
+    suspended: bool,
+    ime_query: BlockingQuery,
+    // foreground window and layout the IME was last asked for, None once an IME key may have switched its mode
+    ime_asked_for: Option<(isize, isize)>
+}
+
+#[cfg(feature = "tokio")]
+impl Default for CharTranslator {
+    fn default() -> Self {
+        Self { dead_key: None, suspended: false, ime_query: BlockingQuery::new(is_ime_composing), ime_asked_for: None }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl CharTranslator {
+    pub(crate) async fn process(&mut self, key_events: Vec<KeyEvent>) -> Vec<KeyEvent> {
+        let mut with_chars = Vec::with_capacity(key_events.len());
+        for key_event in key_events {
+            with_chars.push(key_event);
+            match key_event {
+                KeyEvent::SensitiveInputStarted => {
+                    self.suspended = true;
+                    self.dead_key = None;
+                }
+                KeyEvent::SensitiveInputEnded => self.suspended = false,
+                KeyEvent::Press(key) if !self.suspended => {
+                    let text = self.translate(key).await;
+                    with_chars.extend(text.chars().map(|ch| KeyEvent::Char { key, ch }));
+                }
+                _ => {}
+            }
+        }
+        with_chars
+    }
+
+    async fn translate(&mut self, vk: VirtualKey) -> String {
+        let composing = self.is_ime_composing().await;
+        if is_ime_key(vk) {
+            self.ime_asked_for = None;
+        }
+        if composing {
+            self.dead_key = None;
+            return String::new();
+        }
+        match translate(vk, foreground_layout()) {
+            // a dead key after a dead key types both accents
+            Translation::DeadKey(accent) => match self.dead_key.replace(accent) {
+                Some(previous) => {
+                    self.dead_key = None;
+                    format!("{}{}", previous, accent)
+                }
+                None => String::new()
+            },
+            Translation::Text(text) => match self.dead_key.take() {
+                Some(accent) => compose(accent, &text),
+                None => text
+            },
+            // modifiers (shift for a capital after the accent, ...) keep the dead key pending
+            Translation::Nothing => String::new()
+        }
+    }
+
+    async fn is_ime_composing(&mut self) -> bool {
+        let asked_for = Some((unsafe { GetForegroundWindow() }.0 as isize, foreground_layout().0 as isize));
+        if asked_for == self.ime_asked_for && !self.ime_query.is_pending() {
+            return self.ime_query.last();
+        }
+        self.ime_asked_for = asked_for;
+        self.ime_query.ask().await
+    }
+}
@@ -0,0 +1,214 @@
+/*
+
+Plain std::thread backend, enabled with the "thread" feature
+
+Polling runs on one thread and key event callbacks are called on a second thread, mirroring the tokio listener
+without needing an async runtime. Alternatively the events can be read directly from a blocking KeyEvents receiver.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+use crate::callbacks::{Callbacks, stateful_callbacks};
+use crate::error::{KeyListenerError, checked_vk_codes, validate_polling_interval};
+use crate::poll::{KeyStates, DEFAULT_POLLING_WAIT, default_vk_codes};
+
+use std::sync::mpsc::{Receiver, TryRecvError, RecvTimeoutError, channel};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+
+
+#[derive(Clone)]
+pub struct ThreadKeyListener {
+    key_states: Arc<KeyStates>,
+    polling_wait: Arc<AtomicU64>,
+    is_watching: Arc<AtomicBool>
+}
+
+impl ThreadKeyListener {
+    // unlike the tokio listener this does not need to be locked, quitting only flips the shared flag
+    pub fn quit(&self) {
+        self.is_watching.store(false, Ordering::Relaxed);
+    }
+
+    // takes effect from the next round of polling, zero is rejected like it is when spawning
+    pub fn set_polling_wait(&self, polling_wait: u64) -> Result<(), KeyListenerError> {
+        validate_polling_interval(Duration::from_millis(polling_wait))?;
+        self.polling_wait.store(polling_wait, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn polling_wait(&self) -> u64 {
+        self.polling_wait.load(Ordering::Relaxed)
+    }
+
+    pub fn is_pressed(&self, vk: impl Into<VirtualKey>) -> bool {
+        self.key_states.is_pressed(vk.into().code())
+    }
+
+    pub fn pressed_keys(&self) -> Vec<VirtualKey> {
+        self.key_states.pressed_keys()
+    }
+}
+
+
+
+// blocking receiver of key events, iteration ends once the listener quits
+pub struct KeyEvents {
+    receiver: Receiver<KeyEvent>
+}
+
+impl KeyEvents {
+    pub fn iter(&self) -> impl Iterator<Item = KeyEvent> + '_ {
+        self.receiver.iter()
+    }
+
+    // None once the listener has quit and every remaining event was received
+    pub fn recv(&self) -> Option<KeyEvent> {
+        self.receiver.recv().ok()
+    }
+
+    pub fn try_recv(&self) -> Result<KeyEvent, TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<KeyEvent, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+}
+
+impl IntoIterator for KeyEvents {
+    type Item = KeyEvent;
+    type IntoIter = std::sync::mpsc::IntoIter<KeyEvent>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.receiver.into_iter()
+    }
+}
+
+
+
+// send returns false once nothing is left to receive events, which stops the polling thread
+fn listen(key_listener: ThreadKeyListener, mut send: impl FnMut(KeyEvent) -> bool) {
+    let mut is_disconnected = false;
+    while key_listener.is_watching.load(Ordering::Relaxed) && !is_disconnected {
+        key_listener.key_states.poll(|key_event| {
+            is_disconnected |= !send(key_event);
+        });
+        thread::sleep(Duration::from_millis(key_listener.polling_wait()));
+    }
+}
+
+pub(crate) fn spawn_listener(
+    vk_codes: Vec<i32>,
+    polling_wait: u64,
+    send: impl FnMut(KeyEvent) -> bool + Send + 'static
+) -> Result<ThreadKeyListener, KeyListenerError> {
+    let vk_codes = checked_vk_codes(vk_codes, false)?;
+    validate_polling_interval(Duration::from_millis(polling_wait))?;
+
+    let key_listener = ThreadKeyListener {
+        key_states: Arc::new(KeyStates::new(vk_codes)),
+        polling_wait: Arc::new(AtomicU64::new(polling_wait)),
+        is_watching: Arc::new(AtomicBool::new(true))
+    };
+
+    let listener = key_listener.clone();
+    thread::spawn(move || listen(listener, send));
+
+    Ok(key_listener)
+}
+
+fn spawn_receiver<D, U>(
+    receiver: Receiver<KeyEvent>,
+    mut callbacks: Callbacks<D, U>
+)
+where
+    D: FnMut(KeyEvent) + Send + 'static,
+    U: FnMut(KeyEvent) + Send + 'static
+{
+    // ends once the polling thread quits and drops its sender
+    thread::spawn(move || {
+        for key_event in receiver {
+            callbacks.call(key_event);
+        }
+    });
+}
+
+
+
+pub fn init_default_thread_key_listener<D, U>(
+    key_down_callback: D, key_up_callback: U
+) -> Result<ThreadKeyListener, KeyListenerError>
+where
+    D: FnMut(KeyEvent) + Send + 'static,
+    U: FnMut(KeyEvent) + Send + 'static
+{
+    init_custom_thread_key_listener(key_down_callback, key_up_callback, default_vk_codes(), DEFAULT_POLLING_WAIT)
+}
+
+pub fn init_custom_thread_key_listener<D, U>(
+    key_down_callback: D, key_up_callback: U,
+    vk_codes: Vec<i32>,
+    polling_wait: u64
+) -> Result<ThreadKeyListener, KeyListenerError>
+where
+    D: FnMut(KeyEvent) + Send + 'static,
+    U: FnMut(KeyEvent) + Send + 'static
+{
+    let (sender, receiver) = channel();
+    let key_listener = spawn_listener(vk_codes, polling_wait, move |key_event| sender.send(key_event).is_ok())?;
+
+    spawn_receiver(receiver, Callbacks::new(key_down_callback, key_up_callback));
+
+    Ok(key_listener)
+}
+
+// one callback for every event kind instead of a key down and a key up callback
+pub fn init_any_event_thread_key_listener<F>(callback: F) -> Result<ThreadKeyListener, KeyListenerError>
+where
+    F: FnMut(KeyEvent) + Send + 'static
+{
+    let (sender, receiver) = channel();
+    let key_listener = spawn_listener(default_vk_codes(), DEFAULT_POLLING_WAIT, move |key_event| sender.send(key_event).is_ok())?;
+
+    spawn_receiver(receiver, Callbacks::any(callback));
+
+    Ok(key_listener)
+}
+
+// key down and key up callbacks sharing state, each gets &mut state with the event
+pub fn init_thread_key_listener_with_state<S, D, U>(
+    state: S,
+    key_down_callback: D, key_up_callback: U
+) -> Result<ThreadKeyListener, KeyListenerError>
+where
+    S: Send + 'static,
+    D: FnMut(&mut S, KeyEvent) + Send + 'static,
+    U: FnMut(&mut S, KeyEvent) + Send + 'static
+{
+    let (sender, receiver) = channel();
+    let key_listener = spawn_listener(default_vk_codes(), DEFAULT_POLLING_WAIT, move |key_event| sender.send(key_event).is_ok())?;
+
+    spawn_receiver(receiver, stateful_callbacks(state, key_down_callback, key_up_callback));
+
+    Ok(key_listener)
+}
+
+pub fn init_default_key_events() -> Result<(ThreadKeyListener, KeyEvents), KeyListenerError> {
+    init_custom_key_events(default_vk_codes(), DEFAULT_POLLING_WAIT)
+}
+
+pub fn init_custom_key_events(
+    vk_codes: impl IntoIterator<Item = impl Into<VirtualKey>>,
+    polling_wait: u64
+) -> Result<(ThreadKeyListener, KeyEvents), KeyListenerError> {
+    let vk_codes = vk_codes.into_iter().map(|vk| vk.into().code()).collect();
+    let (sender, receiver) = channel();
+    let key_listener = spawn_listener(vk_codes, polling_wait, move |key_event| sender.send(key_event).is_ok())?;
+
+    Ok((key_listener, KeyEvents { receiver }))
+}
@@ -0,0 +1,56 @@
+/*
+
+High resolution waiting for the tokio poller
+
+Windows timers tick every 15.6 ms by default, so short sleeps overshoot by up to a full tick. timeBeginPeriod(1)
+raises the (system wide) timer resolution to 1 ms for as long as the listener runs, and the last millisecond
+before each deadline is spent yielding in a loop. That makes sub-millisecond intervals possible at the cost of
+keeping a core busy for most of each interval shorter than ~1 ms.
+
+https://learn.microsoft.com/en-us/windows/win32/api/timeapi/nf-timeapi-timebeginperiod
+
+*/
+
+
+use windows::Win32::Media::{timeBeginPeriod, timeEndPeriod};
+
+use tokio::time::{sleep_until, Duration, Instant};
+
+
+
+const TIMER_PERIOD: u32 = 1;
+
+// sleeping is only trusted up to this close to the deadline
+const SPIN_MARGIN: Duration = Duration::from_millis(1);
+
+// keeps the raised timer resolution until dropped
+pub(crate) struct TimerResolution;
+
+impl TimerResolution {
+    pub(crate) fn raise() -> Self {
+        unsafe {
+            timeBeginPeriod(TIMER_PERIOD);
+        }
+        TimerResolution
+    }
+}
+
+impl Drop for TimerResolution {
+    fn drop(&mut self) {
+        unsafe {
+            timeEndPeriod(TIMER_PERIOD);
+        }
+    }
+}
+
+// sleeps for most of the wait and yields to other tasks until the deadline for the rest
+pub(crate) async fn wait_until_precise(deadline: Instant) {
+    if let Some(sleep_deadline) = deadline.checked_sub(SPIN_MARGIN)
+        && sleep_deadline > Instant::now()
+    {
+        sleep_until(sleep_deadline).await;
+    }
+    while Instant::now() < deadline {
+        tokio::task::yield_now().await;
+    }
+}
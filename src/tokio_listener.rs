@@ -0,0 +1,780 @@
+/*
+
+Tokio backend, enabled by default with the "tokio" feature
+
+The key listening task is spawned with tokio::spawn and user callbacks are called from a second task.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+use crate::builder::KeyListenerBuilder;
+use crate::error::{KeyListenerError, validate_polling_interval};
+use crate::idle::IdleWatcher;
+use crate::elevation::ElevationWatcher;
+use crate::callbacks::{Callbacks, AsyncCallbacks, PanicReporter};
+use crate::callback_handle::{CallbackHandle, CallbackRegistry};
+use crate::channel::{EventSender, EventReceiver};
+use crate::pipeline::Pipeline;
+use crate::poll::{KeyStates, PriorityScan, ToggleStates};
+use crate::power::PowerWatcher;
+use crate::priority::{ThreadPriority, set_current_thread_priority};
+use crate::session::SessionWatcher;
+use crate::stats::{ListenerStats, StatsCounters};
+use crate::history::History;
+use crate::pressed_set::{PressedSet, StateChanged};
+use crate::sequence::{SequencedKeyEvent, Sequencer, with_sequence_number};
+use crate::modifier_gestures::ModifierGestures;
+use crate::privacy::PrivacyGuard;
+use crate::text::{CharTranslator, LayoutWatcher};
+use crate::timer::{TimerResolution, wait_until_precise};
+use crate::window::{ContextWatcher, FocusWatcher, ForegroundFilter, WindowContext, WindowFilter, WindowKeyEvent};
+use crate::typed_text::TypedTextStream;
+use crate::chord::ChordStream;
+use crate::warnings::{ListenerWarning, WarningSender};
+use crate::listener_handle::{ListenerHandle, ListenerState, RunState, StopOnExit};
+#[cfg(feature = "mock")]
+use crate::mock::MockBackend;
+
+use tokio::time::{sleep, Duration, Instant};
+use tokio::sync::broadcast;
+use std::future::Future;
+use std::ops::Range;
+#[cfg(feature = "tokio-util")]
+use tokio_util::sync::CancellationToken;
+use std::sync::{Arc, PoisonError};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+
+
+// events a subscriber can fall behind by before it starts missing them (RecvError::Lagged)
+const SUBSCRIBER_CAPACITY: usize = 256;
+
+// how futures returned by async callbacks are run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AsyncCallbackMode {
+    // each future is awaited before the next event is handled, keeping callbacks in order
+    Sequential,
+    // each future is spawned as its own task, so slow callbacks never hold up the next event
+    #[default]
+    Concurrent
+}
+
+
+
+// plain settings from the builder, read by the polling task when it is spawned
+#[derive(Clone)]
+pub(crate) struct ListenerOptions {
+    pub(crate) polling_interval: Duration,
+    pub(crate) high_resolution: bool,
+    pub(crate) dedicated_thread: Option<ThreadPriority>,
+    pub(crate) adaptive_polling: Option<AdaptivePolling>,
+    pub(crate) track_toggles: bool,
+    pub(crate) track_layout: bool,
+    pub(crate) track_focus: bool,
+    pub(crate) release_on_focus_loss: bool,
+    pub(crate) release_on_quit: bool,
+    pub(crate) track_session: bool,
+    pub(crate) track_power: bool,
+    pub(crate) idle_threshold: Option<Duration>,
+    pub(crate) pause_on_secure_desktop: bool,
+    pub(crate) text_mode: bool,
+    pub(crate) privacy_mode: bool,
+    pub(crate) window_filter: Option<WindowFilter>,
+    pub(crate) window_context: bool,
+    pub(crate) state_changes: bool,
+    pub(crate) modifier_gestures: Option<Duration>,
+    pub(crate) history_capacity: Option<usize>,
+    pub(crate) priority_scan: Option<PriorityScan>,
+    #[cfg(feature = "tokio-util")]
+    pub(crate) cancellation_token: Option<CancellationToken>,
+    #[cfg(feature = "mock")]
+    pub(crate) simulated: bool
+}
+
+// slower polling once no key has changed for idle_after, back to the normal interval on the first change
+#[derive(Clone, Copy)]
+pub(crate) struct AdaptivePolling {
+    pub(crate) idle_after: Duration,
+    pub(crate) idle_polling_wait: u64
+}
+
+pub struct KeyListener {
+    event_sender: EventSender,
+    broadcast_sender: broadcast::Sender<KeyEvent>,
+    context_sender: broadcast::Sender<WindowKeyEvent>,
+    state_sender: broadcast::Sender<StateChanged>,
+    sequencer: Sequencer,
+    key_states: Arc<KeyStates>,
+    pipeline: Arc<std::sync::Mutex<Pipeline>>,
+    options: ListenerOptions,
+    // in microseconds
+    polling_interval: Arc<AtomicU64>,
+    run_state: Arc<RunState>,
+    dropped_events: Arc<AtomicU64>,
+    stats: Arc<StatsCounters>,
+    history: Option<Arc<History>>,
+    warning_sender: WarningSender,
+    callbacks: CallbackRegistry,
+    output: Arc<tokio::sync::Mutex<EventOutput>>
+}
+
+impl KeyListener {
+    pub fn builder() -> KeyListenerBuilder {
+        KeyListenerBuilder::new()
+    }
+
+    pub(crate) fn new(
+        event_sender: EventSender,
+        dropped_events: Arc<AtomicU64>,
+        warning_sender: WarningSender,
+        panic_reporter: PanicReporter,
+        pipeline: Pipeline,
+        vk_codes: Vec<i32>,
+        options: ListenerOptions
+    ) -> Self {
+        let history = options.history_capacity.map(|capacity| Arc::new(History::new(capacity)));
+        let broadcast_sender = broadcast::channel(SUBSCRIBER_CAPACITY).0;
+        let context_sender = broadcast::channel(SUBSCRIBER_CAPACITY).0;
+        let state_sender = broadcast::channel(SUBSCRIBER_CAPACITY).0;
+        let sequencer = Sequencer::new(SUBSCRIBER_CAPACITY);
+        let stats = Arc::new(StatsCounters::new());
+        let callbacks = CallbackRegistry::new(panic_reporter);
+        let output = EventOutput {
+            foreground_filter: options.window_filter.clone().map(ForegroundFilter::new),
+            privacy_guard: options.privacy_mode.then(PrivacyGuard::default),
+            char_translator: options.text_mode.then(CharTranslator::default),
+            modifier_gestures: options.modifier_gestures.map(ModifierGestures::new),
+            context_watcher: options.window_context.then(ContextWatcher::default),
+            pressed_set: options.state_changes.then(PressedSet::default),
+            sender: event_sender.clone(),
+            broadcast_sender: broadcast_sender.clone(),
+            context_sender: context_sender.clone(),
+            state_sender: state_sender.clone(),
+            sequencer: sequencer.clone(),
+            stats: stats.clone(),
+            history: history.clone(),
+            callbacks: callbacks.clone()
+        };
+        KeyListener {
+            event_sender,
+            broadcast_sender,
+            context_sender,
+            state_sender,
+            sequencer,
+            key_states: Arc::new(KeyStates::new(vk_codes).with_priority_scan(options.priority_scan.clone())),
+            pipeline: Arc::new(std::sync::Mutex::new(pipeline)),
+            polling_interval: Arc::new(AtomicU64::new(options.polling_interval.as_micros() as u64)),
+            options,
+            run_state: Arc::new(RunState::default()),
+            dropped_events,
+            stats,
+            history,
+            warning_sender,
+            callbacks,
+            output: Arc::new(tokio::sync::Mutex::new(output))
+        }
+    }
+
+    pub fn quit(&mut self) {
+        self.run_state.quit();
+    }
+
+    // stop / resume polling keys without ending the listener, see ListenerHandle::pause
+    pub fn pause(&self) {
+        self.run_state.set_paused(true);
+    }
+
+    pub fn resume(&self) {
+        self.run_state.set_paused(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.run_state.is_paused()
+    }
+
+    pub fn state(&self) -> ListenerState {
+        self.run_state.listener_state()
+    }
+
+    // the current state and every transition after it. A change to Stopped that did not come from quit means
+    // the polling task ended on its own, start brings it back
+    pub fn watch_state(&self) -> tokio::sync::watch::Receiver<ListenerState> {
+        self.run_state.watch()
+    }
+
+    // quits, pauses and reads stats without locking the listener, and stays valid across quit / start
+    pub fn handle(&self) -> ListenerHandle {
+        ListenerHandle::new(self.run_state.clone(), self.stats.clone(), self.dropped_events.clone())
+    }
+
+    // polls again after quit, with the same options, callbacks and subscribers
+    pub fn start(&mut self) -> Result<(), KeyListenerError> {
+        if self.is_running() {
+            return Err(KeyListenerError::AlreadyRunning);
+        }
+        self.spawn_poll_task();
+        Ok(())
+    }
+
+    // false after quit, or once nothing is left to receive events
+    pub fn is_running(&self) -> bool {
+        self.run_state.is_running()
+    }
+
+    // feeds synthetic key events to this listener's filters, callbacks and subscribers
+    #[cfg(feature = "mock")]
+    pub fn mock_backend(&self) -> MockBackend {
+        MockBackend::new(self.injector(), self.key_states.clone())
+    }
+
+    #[cfg(any(feature = "named-pipe", feature = "websocket"))]
+    pub(crate) fn broadcast_sender(&self) -> broadcast::Sender<KeyEvent> {
+        self.broadcast_sender.clone()
+    }
+
+    pub(crate) fn injector(&self) -> EventInjector {
+        EventInjector { pipeline: self.pipeline.clone(), output: self.output.clone() }
+    }
+
+    pub(crate) fn spawn_poll_task(&mut self) {
+        // a new generation, so a task from before quit that is still finishing its last round stays stopped
+        let generation = self.run_state.start();
+        #[cfg(feature = "mock")]
+        if self.options.simulated {
+            self.run_state.started(generation);
+            return;
+        }
+
+        let task = PollTask {
+            options: self.options.clone(),
+            key_states: self.key_states.clone(),
+            pipeline: self.pipeline.clone(),
+            toggle_states: self.options.track_toggles.then(ToggleStates::new),
+            layout_watcher: self.options.track_layout.then(LayoutWatcher::new),
+            focus_watcher: (self.options.track_focus || self.options.release_on_focus_loss).then(FocusWatcher::default),
+            session_watcher: (self.options.track_session || self.options.pause_on_secure_desktop)
+                .then(|| SessionWatcher::new(self.options.track_session)),
+            power_watcher: self.options.track_power.then(PowerWatcher::new).flatten(),
+            idle_watcher: self.options.idle_threshold.map(IdleWatcher::new),
+            elevation_watcher: ElevationWatcher::new(),
+            output: self.output.clone(),
+            sender: self.event_sender.clone(),
+            polling_interval: self.polling_interval.clone(),
+            last_key_change: Instant::now(),
+            stats: self.stats.clone(),
+            dropped_events: self.dropped_events.clone(),
+            reported_dropped_events: self.dropped_events.load(Ordering::Relaxed),
+            warning_sender: self.warning_sender.clone(),
+            run_state: self.run_state.clone(),
+            generation
+        };
+
+        match self.options.dedicated_thread {
+            Some(priority) => spawn_poll_thread(task, priority),
+            None => {
+                tokio::spawn(listen(task));
+            }
+        }
+    }
+
+    // takes effect from the next round of polling, e.g. to poll faster while a game is focused. Zero is rejected
+    // like it is when spawning
+    pub fn set_polling_wait(&self, polling_wait: u64) -> Result<(), KeyListenerError> {
+        self.set_polling_interval(Duration::from_millis(polling_wait))
+    }
+
+    pub fn polling_wait(&self) -> u64 {
+        self.polling_interval().as_millis() as u64
+    }
+
+    // intervals below a millisecond need a listener built with high_resolution(true), and below a microsecond are zero
+    pub fn set_polling_interval(&self, polling_interval: Duration) -> Result<(), KeyListenerError> {
+        validate_polling_interval(polling_interval)?;
+        self.polling_interval.store(polling_interval.as_micros() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn polling_interval(&self) -> Duration {
+        Duration::from_micros(self.polling_interval.load(Ordering::Relaxed))
+    }
+
+    // another callback for presses until the handle is dropped, called from a task of its own: in the order added
+    // among added callbacks, but in no particular order relative to the callbacks given when spawning
+    pub fn on_key_down<F>(&self, callback: F) -> CallbackHandle
+    where
+        F: FnMut(KeyEvent) + Send + 'static
+    {
+        self.callbacks.add(false, Box::new(callback))
+    }
+
+    pub fn on_key_up<F>(&self, callback: F) -> CallbackHandle
+    where
+        F: FnMut(KeyEvent) + Send + 'static
+    {
+        self.callbacks.add(true, Box::new(callback))
+    }
+
+    // every subscriber receives its own copy of each event, alongside the callbacks
+    pub fn subscribe(&self) -> broadcast::Receiver<KeyEvent> {
+        self.broadcast_sender.subscribe()
+    }
+
+    // like subscribe, with the foreground window of each event. The listener has to be built with
+    // window_context(true), otherwise nothing is received
+    pub fn subscribe_with_context(&self) -> broadcast::Receiver<WindowKeyEvent> {
+        self.context_sender.subscribe()
+    }
+
+    // the pressed keys after every press or release that changed them. The listener has to be built with
+    // state_changes(true), otherwise nothing is received
+    pub fn subscribe_state_changes(&self) -> broadcast::Receiver<StateChanged> {
+        self.state_sender.subscribe()
+    }
+
+    // like subscribe, with each event's sequence number. A skipped number is an event this subscriber missed
+    pub fn subscribe_sequenced(&self) -> broadcast::Receiver<SequencedKeyEvent> {
+        self.sequencer.subscribe()
+    }
+
+    // the sequence number the next event gets, which is how many events have been sent so far
+    pub fn next_sequence_number(&self) -> u64 {
+        self.sequencer.next_seq()
+    }
+
+    // lines of text typed from now on, the listener has to be built with text_mode(true)
+    pub fn typed_lines(&self) -> TypedTextStream {
+        TypedTextStream::new(self.subscribe())
+    }
+
+    // keys pressed from now on, grouped into chords of the presses within window of each chord's first one
+    pub fn chords(&self, window: Duration) -> ChordStream {
+        ChordStream::new(self.subscribe_sequenced(), window)
+    }
+
+    // events from now on as crossterm key events, with the modifiers this listener sees held
+    #[cfg(feature = "crossterm")]
+    pub fn crossterm_events(&self) -> crate::CrosstermEvents {
+        crate::CrosstermEvents::new(self.subscribe())
+    }
+
+    pub fn is_pressed(&self, vk: impl Into<VirtualKey>) -> bool {
+        self.key_states.is_pressed(vk.into().code())
+    }
+
+    pub fn pressed_keys(&self) -> Vec<VirtualKey> {
+        self.key_states.pressed_keys()
+    }
+
+    // events lost to a bounded channel's overflow policy, always 0 for the default unbounded channel
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped_events.load(Ordering::Relaxed)
+    }
+
+    pub fn stats(&self) -> ListenerStats {
+        self.stats.snapshot(self.dropped_events())
+    }
+
+    // the last events sent, oldest first, when the listener was built with history(capacity), otherwise empty
+    pub fn recent_events(&self) -> Vec<(std::time::Instant, KeyEvent)> {
+        self.history.as_ref().map(|history| history.snapshot()).unwrap_or_default()
+    }
+
+    pub fn clear_history(&self) {
+        if let Some(history) = &self.history {
+            history.clear();
+        }
+    }
+}
+
+
+
+// everything the polling task needs, cloned out of the listener when it is spawned
+struct PollTask {
+    options: ListenerOptions,
+    key_states: Arc<KeyStates>,
+    pipeline: Arc<std::sync::Mutex<Pipeline>>,
+    toggle_states: Option<ToggleStates>,
+    layout_watcher: Option<LayoutWatcher>,
+    focus_watcher: Option<FocusWatcher>,
+    session_watcher: Option<SessionWatcher>,
+    power_watcher: Option<PowerWatcher>,
+    idle_watcher: Option<IdleWatcher>,
+    elevation_watcher: Option<ElevationWatcher>,
+    output: Arc<tokio::sync::Mutex<EventOutput>>,
+    // the output's sender, to tell whether anything still receives without locking the output
+    sender: EventSender,
+    polling_interval: Arc<AtomicU64>,
+    last_key_change: Instant,
+    stats: Arc<StatsCounters>,
+    dropped_events: Arc<AtomicU64>,
+    // dropped_events when EventsDropped was last reported
+    reported_dropped_events: u64,
+    warning_sender: WarningSender,
+    run_state: Arc<RunState>,
+    generation: u64
+}
+
+async fn listen(mut task: PollTask) {
+    let mut key_events = Vec::new();
+    let _timer_resolution = task.options.high_resolution.then(TimerResolution::raise);
+    let _stop_on_exit = StopOnExit { run_state: task.run_state.clone(), generation: task.generation };
+    task.run_state.started(task.generation);
+
+    // also stops once nothing is left to receive events (e.g. a dropped wait_for_key future)
+    while task.run_state.is_current(task.generation) && !task.sender.is_closed() && !task.is_cancelled() {
+        let round_start = Instant::now();
+        let (suppressed_events, focus_releases) = task.poll(&mut key_events);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            poll_duration = ?round_start.elapsed(), events = key_events.len(), queue_depth = ?task.sender.queue_depth(),
+            "polled"
+        );
+        let filtered = task.output.lock().await.emit(&mut key_events, focus_releases, round_start.into_std()).await;
+        task.stats.polled(suppressed_events + filtered as u64);
+        task.sender.flush();
+        task.check_dropped_events();
+
+        let mut polling_interval = Duration::from_micros(task.polling_interval.load(Ordering::Relaxed));
+        if let Some(adaptive_polling) = task.options.adaptive_polling
+            && task.last_key_change.elapsed() >= adaptive_polling.idle_after
+        {
+            polling_interval = polling_interval.max(Duration::from_millis(adaptive_polling.idle_polling_wait));
+        }
+        let round_duration = round_start.elapsed();
+        if round_duration > polling_interval {
+            task.warning_sender.warn(ListenerWarning::PollOverrun { took: round_duration, interval: polling_interval });
+        }
+        let wait_start = Instant::now();
+        let expected_wait = if task.options.high_resolution {
+            // measured from the start of the round, so the time spent polling does not add up
+            let deadline = round_start + polling_interval;
+            wait_until_precise(deadline).await;
+            deadline.saturating_duration_since(wait_start)
+        } else {
+            sleep(polling_interval).await;
+            polling_interval
+        };
+        task.stats.waited(wait_start.elapsed().saturating_sub(expected_wait));
+    }
+
+    if task.run_state.stop(task.generation) && task.sender.is_closed() {
+        task.warning_sender.warn(ListenerWarning::ReceiverClosed);
+    }
+
+    // only after quit, when the loop ended because the receiver is gone there is nobody to release keys for
+    if task.options.release_on_quit && !task.sender.is_closed() {
+        {
+            let mut pipeline = task.pipeline.lock().unwrap_or_else(PoisonError::into_inner);
+            task.key_states.release_all(|key_event| key_events.extend(pipeline.process(key_event)));
+        }
+        let releases = 0..key_events.len();
+        task.output.lock().await.emit(&mut key_events, releases, std::time::Instant::now()).await;
+    }
+}
+
+impl PollTask {
+    fn is_cancelled(&self) -> bool {
+        #[cfg(feature = "tokio-util")]
+        if let Some(cancellation_token) = &self.options.cancellation_token {
+            return cancellation_token.is_cancelled();
+        }
+        false
+    }
+
+    // one round of polling every source, returns how many events the pipeline suppressed, and where in key_events
+    // the releases for a focus change are
+    fn poll(&mut self, key_events: &mut Vec<KeyEvent>) -> (u64, Range<usize>) {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("poll").entered();
+
+        // a task that panicked while holding the pipeline poisoned it, the restarted one keeps using it
+        let mut pipeline = self.pipeline.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut emit = |key_event| key_events.extend(pipeline.process(key_event));
+
+        let secure_desktop = match self.session_watcher.as_mut() {
+            Some(session_watcher) => session_watcher.poll(&mut emit),
+            None => false
+        };
+        let suspended = match self.power_watcher.as_mut() {
+            Some(power_watcher) => power_watcher.poll(&self.key_states, &mut emit),
+            None => false
+        };
+        // while paused keys keep their last state, changes made meanwhile are picked up on resuming
+        let paused = suspended || (secure_desktop && self.options.pause_on_secure_desktop) || self.run_state.is_paused();
+        if !paused {
+            let last_key_change = &mut self.last_key_change;
+            self.key_states.poll(|key_event| {
+                *last_key_change = Instant::now();
+                emit(key_event);
+            });
+        }
+        if let Some(toggle_states) = self.toggle_states.as_mut() {
+            toggle_states.poll(&mut emit);
+        }
+        if let Some(layout_watcher) = self.layout_watcher.as_mut() {
+            layout_watcher.poll(&mut emit);
+        }
+        if let Some(key_event) = self.idle_watcher.as_mut().and_then(IdleWatcher::poll) {
+            emit(key_event);
+        }
+        let mut focus_releases = key_events.len()..key_events.len();
+        if let Some(focus_changed) = self.focus_watcher.as_mut().and_then(FocusWatcher::poll) {
+            if self.options.release_on_focus_loss {
+                self.key_states.release_all(|key_event| key_events.extend(pipeline.process(key_event)));
+                focus_releases.end = key_events.len();
+            }
+            if self.options.track_focus {
+                key_events.extend(pipeline.process(focus_changed));
+            }
+        }
+        // presses the rate limiter held back in earlier rounds
+        pipeline.flush(|key_event| key_events.push(key_event));
+        pipeline.tick();
+        let suppressed_events = pipeline.take_suppressed();
+
+        // warned about once, the user only needs to know why keys went missing
+        if self.elevation_watcher.as_mut().is_some_and(ElevationWatcher::poll) {
+            self.warning_sender.warn(ListenerWarning::ElevatedWindowFocused);
+            self.elevation_watcher = None;
+        }
+
+        (suppressed_events, focus_releases)
+    }
+
+    fn check_dropped_events(&mut self) {
+        let dropped_events = self.dropped_events.load(Ordering::Relaxed);
+        if dropped_events > self.reported_dropped_events {
+            self.warning_sender.warn(ListenerWarning::EventsDropped(dropped_events - self.reported_dropped_events));
+            self.reported_dropped_events = dropped_events;
+        }
+    }
+}
+
+
+
+// everything that happens to events once polled, shared by the polling task and event injectors so injected events
+// are handled like polled ones
+pub(crate) struct EventOutput {
+    foreground_filter: Option<ForegroundFilter>,
+    privacy_guard: Option<PrivacyGuard>,
+    char_translator: Option<CharTranslator>,
+    modifier_gestures: Option<ModifierGestures>,
+    context_watcher: Option<ContextWatcher>,
+    pressed_set: Option<PressedSet>,
+    sender: EventSender,
+    broadcast_sender: broadcast::Sender<KeyEvent>,
+    context_sender: broadcast::Sender<WindowKeyEvent>,
+    state_sender: broadcast::Sender<StateChanged>,
+    sequencer: Sequencer,
+    stats: Arc<StatsCounters>,
+    history: Option<Arc<History>>,
+    callbacks: CallbackRegistry
+}
+
+impl EventOutput {
+    // sends key_events on after the foreground filter, privacy mode, text mode and modifier gestures, leaving it
+    // empty. Returns how many passed the foreground filter, the releases in focus_releases always do
+    // returns how many events the foreground filter removed
+    async fn emit(&mut self, key_events: &mut Vec<KeyEvent>, focus_releases: Range<usize>, polled_at: std::time::Instant) -> usize {
+        // keys are still polled outside of the window so their state stays current, only the events are dropped.
+        // The releases for keys held when the window lost the focus still go out, or they would stay pressed downstream
+        let polled = key_events.len();
+        if self.foreground_filter.as_mut().is_some_and(|filter| !filter.is_foreground()) {
+            key_events.truncate(focus_releases.end);
+            key_events.drain(..focus_releases.start);
+        }
+        let filtered = polled - key_events.len();
+        let mut processed = std::mem::take(key_events);
+        if let Some(privacy_guard) = self.privacy_guard.as_mut() {
+            processed = privacy_guard.process(processed).await;
+        }
+        if let Some(char_translator) = self.char_translator.as_mut() {
+            processed = char_translator.process(processed).await;
+        }
+        if let Some(modifier_gestures) = self.modifier_gestures.as_mut() {
+            processed = modifier_gestures.process(processed);
+        }
+        // captured once per round, every event of a round went to the same window
+        let window = match self.context_watcher.as_mut() {
+            Some(context_watcher) if !processed.is_empty() => context_watcher.current(),
+            _ => None
+        };
+        for key_event in processed.drain(..) {
+            self.send(key_event, &window, polled_at).await;
+        }
+        // the allocation goes back to the polling task
+        *key_events = processed;
+        filtered
+    }
+
+    async fn send(&mut self, key_event: KeyEvent, window: &Option<WindowContext>, polled_at: std::time::Instant) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(?key_event, "emitting");
+
+        let sequenced = self.sequencer.send(key_event, polled_at);
+        self.sender.send(sequenced).await;
+        self.stats.emitted();
+        if let Some(history) = &self.history {
+            history.push(key_event, polled_at);
+        }
+        self.callbacks.forward(sequenced);
+        let _ = self.broadcast_sender.send(key_event); // only fails when there are no subscribers
+        if self.context_watcher.is_some() {
+            let _ = self.context_sender.send(WindowKeyEvent { key_event, window: window.clone() });
+        }
+        if let Some(state_changed) = self.pressed_set.as_mut().and_then(|pressed_set| pressed_set.update(key_event)) {
+            let _ = self.state_sender.send(state_changed);
+        }
+    }
+}
+
+// sends events that were not polled (e.g. replayed or mocked ones) the same way as polled events
+pub(crate) struct EventInjector {
+    pipeline: Arc<std::sync::Mutex<Pipeline>>,
+    output: Arc<tokio::sync::Mutex<EventOutput>>
+}
+
+impl EventInjector {
+    pub(crate) async fn inject(&self, key_event: KeyEvent) {
+        let processed = self.pipeline.lock().unwrap_or_else(PoisonError::into_inner).process(key_event);
+        if let Some(key_event) = processed {
+            self.output.lock().await.emit(&mut vec![key_event], 0..0, std::time::Instant::now()).await;
+        }
+    }
+}
+
+
+
+pub(crate) fn spawn_receiver<D, U>(
+    mut receiver: EventReceiver,
+    mut callbacks: Callbacks<D, U>
+)
+where
+    D: FnMut(KeyEvent) + Send + 'static,
+    U: FnMut(KeyEvent) + Send + 'static
+{
+    tokio::spawn(async move {
+        while let Some(SequencedKeyEvent { seq, key_event, .. }) = receiver.recv().await {
+            with_sequence_number(seq, || callbacks.call(key_event));
+        }
+    });
+}
+
+// the same on a thread of tokio's blocking pool, where callbacks may block without holding up the runtime
+pub(crate) fn spawn_blocking_receiver<D, U>(
+    mut receiver: EventReceiver,
+    mut callbacks: Callbacks<D, U>
+)
+where
+    D: FnMut(KeyEvent) + Send + 'static,
+    U: FnMut(KeyEvent) + Send + 'static
+{
+    tokio::task::spawn_blocking(move || {
+        while let Some(SequencedKeyEvent { seq, key_event, .. }) = receiver.blocking_recv() {
+            with_sequence_number(seq, || callbacks.call(key_event));
+        }
+    });
+}
+
+pub(crate) fn spawn_async_receiver<D, DF, U, UF>(
+    mut receiver: EventReceiver,
+    mut callbacks: AsyncCallbacks<D, U>,
+    mode: AsyncCallbackMode
+)
+where
+    D: FnMut(KeyEvent) -> DF + Send + 'static,
+    DF: Future<Output = ()> + Send + 'static,
+    U: FnMut(KeyEvent) -> UF + Send + 'static,
+    UF: Future<Output = ()> + Send + 'static
+{
+    tokio::spawn(async move {
+        while let Some(SequencedKeyEvent { seq, key_event, .. }) = receiver.recv().await {
+            let callback_future = with_sequence_number(seq, || callbacks.call(key_event));
+            match mode {
+                AsyncCallbackMode::Sequential => callback_future.await,
+                AsyncCallbackMode::Concurrent => {
+                    tokio::spawn(callback_future);
+                }
+            }
+        }
+    });
+}
+
+// runs the polling loop on a runtime of its own, so a busy application runtime cannot delay polling
+fn spawn_poll_thread(task: PollTask, priority: ThreadPriority) {
+    std::thread::spawn(move || {
+        // polling still works at the default priority
+        let _ = set_current_thread_priority(priority);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("a current thread runtime can be built");
+        runtime.block_on(listen(task));
+    });
+}
+
+
+
+
+pub fn init_default_key_listener<D, U>(
+    key_down_callback: D, key_up_callback: U
+) -> Result<Arc<tokio::sync::Mutex<KeyListener>>, KeyListenerError>
+where
+    D: FnMut(KeyEvent) + Send + 'static,
+    U: FnMut(KeyEvent) + Send + 'static
+{
+    KeyListenerBuilder::new().spawn(key_down_callback, key_up_callback)
+}
+
+pub fn init_custom_key_listener<D, U>(
+    key_down_callback: D, key_up_callback: U,
+    vk_codes: Vec<i32>,
+    polling_wait: u64
+) -> Result<Arc<tokio::sync::Mutex<KeyListener>>, KeyListenerError>
+where
+    D: FnMut(KeyEvent) + Send + 'static,
+    U: FnMut(KeyEvent) + Send + 'static
+{
+    KeyListenerBuilder::new()
+        .vk_codes(vk_codes)
+        .polling_wait(polling_wait)
+        .spawn(key_down_callback, key_up_callback)
+}
+
+pub fn init_any_event_key_listener<F>(callback: F) -> Result<Arc<tokio::sync::Mutex<KeyListener>>, KeyListenerError>
+where
+    F: FnMut(KeyEvent) + Send + 'static
+{
+    KeyListenerBuilder::new().spawn_any(callback)
+}
+
+pub fn init_default_async_callback_listener<D, DF, U, UF>(
+    key_down_callback: D, key_up_callback: U
+) -> Result<Arc<tokio::sync::Mutex<KeyListener>>, KeyListenerError>
+where
+    D: FnMut(KeyEvent) -> DF + Send + 'static,
+    DF: Future<Output = ()> + Send + 'static,
+    U: FnMut(KeyEvent) -> UF + Send + 'static,
+    UF: Future<Output = ()> + Send + 'static
+{
+    KeyListenerBuilder::new().spawn_async(key_down_callback, key_up_callback, AsyncCallbackMode::default())
+}
+
+pub fn init_custom_async_callback_listener<D, DF, U, UF>(
+    key_down_callback: D, key_up_callback: U,
+    vk_codes: Vec<i32>,
+    polling_wait: u64,
+    mode: AsyncCallbackMode
+) -> Result<Arc<tokio::sync::Mutex<KeyListener>>, KeyListenerError>
+where
+    D: FnMut(KeyEvent) -> DF + Send + 'static,
+    DF: Future<Output = ()> + Send + 'static,
+    U: FnMut(KeyEvent) -> UF + Send + 'static,
+    UF: Future<Output = ()> + Send + 'static
+{
+    KeyListenerBuilder::new()
+        .vk_codes(vk_codes)
+        .polling_wait(polling_wait)
+        .spawn_async(key_down_callback, key_up_callback, mode)
+}
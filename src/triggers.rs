@@ -0,0 +1,466 @@
+/*
+
+Hotkey triggers
+
+Named hotkeys bound to closures or shell commands, for global launcher style tools. Each trigger can be enabled
+and disabled while running, and fires at most once per debounce window however fast its hotkey is repeated.
+Commands are started with `cmd /C` without waiting for them to finish. Actions run one after the other on a thread
+of their own, never under the lock of the trigger state: an action can call back into ActiveTriggers, and a slow
+one holds up neither polling nor a runtime worker.
+
+capture_binding rebinds a trigger to the next chord pressed (any modifiers and one other key), for "press the new
+shortcut" settings. Triggers do not fire while a capture runs, and not before the captured key's release reached
+them either: they see events after capture_binding does, by which time the trigger is bound to the chord already.
+Chords Windows keeps for itself and chords bound to another trigger are refused. The bindings are a map from trigger
+names to hotkeys. save_bindings writes it to a file as "name = hotkey" lines and load_bindings reads it back for
+Triggers::bindings on the next start, with the serde feature the map can be saved in any other format too.
+
+*/
+
+
+use crate::{Hotkey, KeyEvent, Modifiers, VirtualKey, MOUSE_BUTTONS};
+use crate::builder::KeyListenerBuilder;
+use crate::error::KeyListenerError;
+use crate::hotkey_router::{KeyRouter, Routed, SharedRouter, lock, spawn_router};
+use crate::tokio_listener::KeyListener;
+
+use tokio::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::{Arc, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Sender, channel};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+
+// chords the system handles before any application sees them, or that users rely on too much to lose
+const RESERVED_HOTKEYS: [&str; 19] = [
+    "Ctrl+Alt+Delete", "Ctrl+Shift+Esc", "Ctrl+Esc", "Alt+Tab", "Alt+Esc", "Alt+F4",
+    "Win+L", "Win+D", "Win+E", "Win+R", "Win+I", "Win+S", "Win+X", "Win+A", "Win+V", "Win+M", "Win+Tab", "Win+Space",
+    "Win+Shift+S"
+];
+
+type RebindCallback = Box<dyn FnMut(&BTreeMap<String, Hotkey>) + Send + 'static>;
+
+enum Action {
+    Callback(Box<dyn FnMut() + Send + 'static>),
+    Command(String)
+}
+
+impl Action {
+    fn run(&mut self) {
+        match self {
+            Action::Callback(callback) => callback(),
+            Action::Command(command) => {
+                // nobody to report a failed start to, the command is simply not run
+                let _ = Command::new("cmd").arg("/C").arg(&*command).spawn();
+            }
+        }
+    }
+}
+
+struct Trigger {
+    name: String,
+    hotkey: Hotkey,
+    enabled: Arc<AtomicBool>,
+    last_fired: Option<Instant>
+}
+
+// runs the actions of fired triggers, sent by index, until the trigger state is gone
+fn spawn_action_thread(mut actions: Vec<Action>) -> Sender<usize> {
+    let (sender, receiver) = channel::<usize>();
+    thread::spawn(move || {
+        for index in receiver {
+            // a panicking action must not take the others with it
+            let _ = catch_unwind(AssertUnwindSafe(|| actions[index].run()));
+        }
+    });
+    sender
+}
+
+
+
+#[derive(Default)]
+pub struct Triggers {
+    triggers: Vec<(Trigger, Action)>,
+    names: HashMap<String, Arc<AtomicBool>>,
+    debounce: Duration,
+    rebindable: bool,
+    reserved: Vec<Hotkey>,
+    rebind_callback: Option<RebindCallback>
+}
+
+impl Triggers {
+    pub fn new() -> Self {
+        Triggers::default()
+    }
+
+    // a trigger replaces an earlier one of the same name
+    pub fn on<F>(self, name: &str, hotkey: Hotkey, callback: F) -> Self
+    where
+        F: FnMut() + Send + 'static
+    {
+        self.add(name, hotkey, Action::Callback(Box::new(callback)))
+    }
+
+    // runs command line with cmd /C, e.g. "start notepad.exe"
+    pub fn command(self, name: &str, hotkey: Hotkey, command_line: &str) -> Self {
+        self.add(name, hotkey, Action::Command(command_line.to_string()))
+    }
+
+    // minimum time between two firings of the same trigger
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    // watch every key rather than only the triggers' ones, so capture_binding can bind any of them
+    pub fn rebindable(mut self, rebindable: bool) -> Self {
+        self.rebindable = rebindable;
+        self
+    }
+
+    // refused by capture_binding, in addition to the chords Windows keeps for itself (Ctrl+Alt+Delete, Alt+Tab, ...)
+    pub fn reserve(mut self, hotkey: Hotkey) -> Self {
+        self.reserved.push(hotkey);
+        self
+    }
+
+    // replaces the hotkeys of triggers added so far with saved ones, names without a trigger are ignored
+    pub fn bindings(mut self, bindings: &BTreeMap<String, Hotkey>) -> Self {
+        for (trigger, _) in self.triggers.iter_mut() {
+            if let Some(hotkey) = bindings.get(&trigger.name) {
+                trigger.hotkey = *hotkey;
+            }
+        }
+        self
+    }
+
+    // called with every trigger's binding after capture_binding changed one, e.g. to save them
+    pub fn on_rebind<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(&BTreeMap<String, Hotkey>) + Send + 'static
+    {
+        self.rebind_callback = Some(Box::new(callback));
+        self
+    }
+
+    fn add(mut self, name: &str, hotkey: Hotkey, action: Action) -> Self {
+        let enabled = Arc::new(AtomicBool::new(true));
+        if let Some(replaced) = self.names.insert(name.to_string(), enabled.clone()) {
+            self.triggers.retain(|(trigger, _)| !Arc::ptr_eq(&trigger.enabled, &replaced));
+        }
+        self.triggers.push((Trigger { name: name.to_string(), hotkey, enabled, last_fired: None }, action));
+        self
+    }
+
+    // spawns a listener with the builder's options, the builder's key set is replaced with the modifiers and
+    // every trigger's keys (every key but the mouse buttons when rebindable)
+    pub fn spawn(self, builder: KeyListenerBuilder) -> Result<ActiveTriggers, KeyListenerError> {
+        let every_key = (0x01..=0xFE).filter(|vk_code| self.rebindable && !MOUSE_BUTTONS.contains(&VirtualKey(*vk_code)));
+        let vk_codes: Vec<i32> = self.triggers.iter().flat_map(|(trigger, _)| trigger.hotkey.vk_codes()).chain(every_key).collect();
+
+        let names = self.names;
+        let mut reserved: Vec<Hotkey> = RESERVED_HOTKEYS.iter().map(|hotkey| hotkey.parse().expect("reserved hotkeys parse")).collect();
+        reserved.extend(self.reserved);
+        let (triggers, actions) = self.triggers.into_iter().unzip();
+        let (key_listener, state) = spawn_router(builder, vk_codes, TriggerState {
+            triggers,
+            fired: spawn_action_thread(actions),
+            debounce: self.debounce,
+            capturing: false,
+            capture_presses: HashSet::new(),
+            capture_key: None
+        })?;
+        let rebind_callback = std::sync::Mutex::new(self.rebind_callback);
+        Ok(ActiveTriggers { key_listener, names, state, reserved, rebind_callback })
+    }
+}
+
+
+
+struct TriggerState {
+    triggers: Vec<Trigger>,
+    // the action thread, each trigger's action is at its index
+    fired: Sender<usize>,
+    debounce: Duration,
+    // a capture_binding is waiting for a chord, which must not fire the trigger bound to it
+    capturing: bool,
+    // keys pressed while capturing, to tell whether the captured key's release already went by
+    capture_presses: HashSet<VirtualKey>,
+    // the captured chord's key, the capture ends once its release comes through here
+    capture_key: Option<VirtualKey>
+}
+
+impl KeyRouter for TriggerState {
+    fn press(&mut self, key: VirtualKey, held: Modifiers) {
+        if self.capturing {
+            self.capture_presses.insert(key);
+            return;
+        }
+        for (index, trigger) in self.triggers.iter_mut().enumerate() {
+            if !trigger.enabled.load(Ordering::Relaxed) || !trigger.hotkey.matches(key, held) {
+                continue;
+            }
+            if let Some(last_fired) = trigger.last_fired && last_fired.elapsed() < self.debounce {
+                continue;
+            }
+            trigger.last_fired = Some(Instant::now());
+            let _ = self.fired.send(index);
+        }
+    }
+
+    fn release(&mut self, key: VirtualKey) {
+        if self.capture_key == Some(key) {
+            self.end_capture();
+        }
+    }
+}
+
+impl TriggerState {
+    // the captured key's press and release may both have come through already, or neither
+    fn finish_capture(&mut self, key: VirtualKey, pressed: &HashSet<VirtualKey>) {
+        if self.capture_presses.contains(&key) && !pressed.contains(&key) {
+            self.end_capture();
+        } else {
+            self.capture_key = Some(key);
+        }
+    }
+
+    fn end_capture(&mut self) {
+        self.capturing = false;
+        self.capture_presses.clear();
+        self.capture_key = None;
+    }
+
+    fn bindings(&self) -> BTreeMap<String, Hotkey> {
+        self.triggers.iter().map(|trigger| (trigger.name.clone(), trigger.hotkey)).collect()
+    }
+}
+
+// ends the capture however capture_binding returns, its future may be dropped while waiting. Once a chord was
+// pressed, refused or not, triggers stay quiet until its key is released
+struct Capturing<'a> {
+    state: &'a std::sync::Mutex<Routed<TriggerState>>,
+    chord_key: Option<VirtualKey>
+}
+
+impl Drop for Capturing<'_> {
+    fn drop(&mut self) {
+        let mut state = lock(self.state);
+        let Routed { router, pressed } = &mut *state;
+        match self.chord_key {
+            Some(key) => router.finish_capture(key, pressed),
+            None => router.end_capture()
+        }
+    }
+}
+
+fn is_modifier(vk: VirtualKey) -> bool {
+    Modifiers::from_any_side(&HashSet::from([vk])) != Modifiers::default()
+}
+
+
+
+pub struct ActiveTriggers {
+    key_listener: Arc<Mutex<KeyListener>>,
+    names: HashMap<String, Arc<AtomicBool>>,
+    state: SharedRouter<TriggerState>,
+    reserved: Vec<Hotkey>,
+    rebind_callback: std::sync::Mutex<Option<RebindCallback>>
+}
+
+impl ActiveTriggers {
+    // false if there is no trigger of that name
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> bool {
+        match self.names.get(name) {
+            Some(flag) => {
+                flag.store(enabled, Ordering::Relaxed);
+                true
+            }
+            None => false
+        }
+    }
+
+    pub fn enable(&self, name: &str) -> bool {
+        self.set_enabled(name, true)
+    }
+
+    pub fn disable(&self, name: &str) -> bool {
+        self.set_enabled(name, false)
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.names.get(name).is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+
+    // every trigger's current hotkey, by name
+    pub fn bindings(&self) -> BTreeMap<String, Hotkey> {
+        lock(&self.state).router.bindings()
+    }
+
+    // writes bindings() to path, see save_bindings
+    pub fn save_bindings(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        save_bindings(path, &self.bindings())
+    }
+
+    // waits for the next chord and binds the trigger to it. Only watched keys count, see Triggers::rebindable, and
+    // keys held when the capture starts only once pressed again. The trigger keeps its hotkey when the chord is refused
+    pub async fn capture_binding(&self, name: &str) -> Result<Hotkey, BindingError> {
+        if !self.names.contains_key(name) {
+            return Err(BindingError::UnknownTrigger(name.to_string()));
+        }
+        let (mut receiver, mut held) = {
+            let key_listener = self.key_listener.lock().await;
+            (key_listener.subscribe(), key_listener.pressed_keys().into_iter().collect::<HashSet<_>>())
+        };
+        lock(&self.state).router.capturing = true;
+        let mut capturing = Capturing { state: &self.state, chord_key: None };
+
+        // modifiers held from the start still count towards the chord
+        let mut modifiers: HashSet<VirtualKey> = held.iter().copied().filter(|vk| is_modifier(*vk)).collect();
+        let hotkey = loop {
+            match receiver.recv().await {
+                Ok(KeyEvent::Press(vk)) if is_modifier(vk) => {
+                    modifiers.insert(vk);
+                }
+                Ok(KeyEvent::Press(vk)) if !held.contains(&vk) => {
+                    capturing.chord_key = Some(vk);
+                    break Hotkey::new(Modifiers::from_any_side(&modifiers), vk);
+                }
+                Ok(KeyEvent::Release(vk)) => {
+                    modifiers.remove(&vk);
+                    held.remove(&vk);
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Err(BindingError::Stopped)
+            }
+        };
+
+        if self.reserved.contains(&hotkey) {
+            return Err(BindingError::Reserved(hotkey));
+        }
+        let bindings = {
+            let mut state = lock(&self.state);
+            let state = &mut state.router;
+            if let Some(other) = state.triggers.iter().find(|trigger| trigger.hotkey == hotkey && trigger.name != name) {
+                return Err(BindingError::Conflict { hotkey, trigger: other.name.clone() });
+            }
+            for trigger in state.triggers.iter_mut().filter(|trigger| trigger.name == name) {
+                trigger.hotkey = hotkey;
+            }
+            state.bindings()
+        };
+        if let Some(callback) = self.rebind_callback.lock().unwrap_or_else(PoisonError::into_inner).as_mut() {
+            callback(&bindings);
+        }
+        Ok(hotkey)
+    }
+
+    pub fn key_listener(&self) -> Arc<Mutex<KeyListener>> {
+        self.key_listener.clone()
+    }
+
+    pub async fn quit(&self) {
+        self.key_listener.lock().await.quit();
+    }
+}
+
+
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BindingError {
+    UnknownTrigger(String),
+    // one of the system chords, or one passed to Triggers::reserve
+    Reserved(Hotkey),
+    // already bound to the named trigger
+    Conflict { hotkey: Hotkey, trigger: String },
+    // the listener quit before a chord was pressed
+    Stopped
+}
+
+impl fmt::Display for BindingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingError::UnknownTrigger(name) => write!(f, "there is no trigger named '{}'", name),
+            BindingError::Reserved(hotkey) => write!(f, "{} is reserved", hotkey),
+            BindingError::Conflict { hotkey, trigger } => write!(f, "{} is already bound to '{}'", hotkey, trigger),
+            BindingError::Stopped => write!(f, "the listener quit before a chord was pressed")
+        }
+    }
+}
+
+impl std::error::Error for BindingError {}
+
+
+
+// writes one "name = hotkey" line per binding, replacing the file. Names must not span lines
+pub fn save_bindings(path: impl AsRef<Path>, bindings: &BTreeMap<String, Hotkey>) -> io::Result<()> {
+    fs::write(path, format_bindings(bindings))
+}
+
+// reads a file written by save_bindings, a missing file holds no bindings
+pub fn load_bindings(path: impl AsRef<Path>) -> io::Result<BTreeMap<String, Hotkey>> {
+    match fs::read_to_string(path) {
+        Ok(text) => parse_bindings(&text),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(error) => Err(error)
+    }
+}
+
+fn format_bindings(bindings: &BTreeMap<String, Hotkey>) -> String {
+    bindings.iter().map(|(name, hotkey)| format!("{} = {}\n", name, hotkey)).collect()
+}
+
+// hotkeys are written without spaces, so the last " = " ends the name. Blank lines are skipped
+fn parse_bindings(text: &str) -> io::Result<BTreeMap<String, Hotkey>> {
+    let invalid = |line: &str, reason: String| io::Error::new(io::ErrorKind::InvalidData, format!("'{}': {}", line, reason));
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (name, hotkey) = line.rsplit_once(" = ").ok_or_else(|| invalid(line, "expected name = hotkey".to_string()))?;
+            let hotkey = hotkey.trim().parse().map_err(|error| invalid(line, format!("{}", error)))?;
+            Ok((name.to_string(), hotkey))
+        })
+        .collect()
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_round_trip() {
+        let bindings = BTreeMap::from([
+            ("screenshot".to_string(), "Ctrl+Shift+S".parse().unwrap()),
+            ("a = b".to_string(), "Win+0xBA".parse().unwrap())
+        ]);
+        let text = format_bindings(&bindings);
+        assert_eq!(text, "a = b = Win+0xBA\nscreenshot = Ctrl+Shift+S\n");
+        assert_eq!(parse_bindings(&text).unwrap(), bindings);
+    }
+
+    #[test]
+    fn invalid_bindings_are_refused() {
+        assert_eq!(parse_bindings("screenshot Ctrl+S").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(parse_bindings("screenshot = Ctrl+Nope").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(parse_bindings("\n  \n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_file_holds_no_bindings() {
+        let path = std::env::temp_dir().join("win-key-event-no-such-bindings");
+        assert!(load_bindings(path).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+/*
+
+Typed text reconstruction
+
+Assembles the Char events of a text mode listener into lines, honoring backspace and finishing a line on enter.
+Only appending at the end of the line is tracked, so cursor movement (arrows, home, mouse clicks) is not reflected.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+
+#[cfg(feature = "tokio")]
+use tokio::sync::broadcast;
+
+
+
+#[derive(Clone, Debug, Default)]
+pub struct TextBuffer {
+    line: String
+}
+
+impl TextBuffer {
+    pub fn new() -> Self {
+        TextBuffer::default()
+    }
+
+    // feeds one event in, returning the finished line when it was an enter press
+    pub fn push(&mut self, key_event: KeyEvent) -> Option<String> {
+        match key_event {
+            KeyEvent::Char { ch, .. } => self.line.push(ch),
+            KeyEvent::Press(VirtualKey::BACK) => {
+                self.line.pop();
+            }
+            KeyEvent::Press(VirtualKey::RETURN) => return Some(std::mem::take(&mut self.line)),
+            _ => {}
+        }
+        None
+    }
+
+    // the unfinished line typed so far
+    pub fn current(&self) -> &str {
+        &self.line
+    }
+
+    pub fn clear(&mut self) {
+        self.line.clear();
+    }
+}
+
+
+
+// lines typed while a text mode listener is running, see KeyListener::typed_lines
+#[cfg(feature = "tokio")]
+pub struct TypedTextStream {
+    receiver: broadcast::Receiver<KeyEvent>,
+    buffer: TextBuffer
+}
+
+#[cfg(feature = "tokio")]
+impl TypedTextStream {
+    pub fn new(receiver: broadcast::Receiver<KeyEvent>) -> Self {
+        TypedTextStream { receiver, buffer: TextBuffer::new() }
+    }
+
+    // None once the listener is gone
+    pub async fn next_line(&mut self) -> Option<String> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(key_event) => {
+                    if let Some(line) = self.buffer.push(key_event) {
+                        return Some(line);
+                    }
+                }
+                // missed events would corrupt the line, so start over
+                Err(broadcast::error::RecvError::Lagged(_)) => self.buffer.clear(),
+                Err(broadcast::error::RecvError::Closed) => return None
+            }
+        }
+    }
+
+    pub fn current(&self) -> &str {
+        self.buffer.current()
+    }
+}
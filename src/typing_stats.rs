@@ -0,0 +1,150 @@
+/*
+
+Typing statistics
+
+Fed with key events (e.g. from KeyListener::subscribe), for typing trainers and productivity dashboards:
+live words per minute, press counts per key and a histogram of the time between presses.
+A word is counted as five characters, the usual convention for WPM.
+
+*/
+
+
+use crate::{KeyEvent, VirtualKey};
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "tokio")]
+use tokio::sync::broadcast;
+#[cfg(feature = "tokio")]
+use std::sync::{Arc, Mutex};
+
+
+
+// live WPM is measured over the last minute
+const WPM_WINDOW: Duration = Duration::from_secs(60);
+pub const INTERVAL_BUCKET_WIDTH: Duration = Duration::from_millis(50);
+// the last bucket holds every interval of a second or more
+pub const INTERVAL_BUCKETS: usize = 21;
+
+
+
+#[derive(Clone, Debug)]
+pub struct TypingStats {
+    key_counts: HashMap<VirtualKey, u64>,
+    presses: u64,
+    characters: u64,
+    // character presses within WPM_WINDOW
+    recent_characters: VecDeque<Instant>,
+    interval_histogram: [u64; INTERVAL_BUCKETS],
+    last_press: Option<Instant>,
+    started: Instant
+}
+
+impl Default for TypingStats {
+    fn default() -> Self {
+        TypingStats {
+            key_counts: HashMap::new(),
+            presses: 0,
+            characters: 0,
+            recent_characters: VecDeque::new(),
+            interval_histogram: [0; INTERVAL_BUCKETS],
+            last_press: None,
+            started: Instant::now()
+        }
+    }
+}
+
+impl TypingStats {
+    pub fn new() -> Self {
+        TypingStats::default()
+    }
+
+    // only presses are counted, other events are ignored
+    pub fn record(&mut self, key_event: &KeyEvent) {
+        let KeyEvent::Press(vk) = *key_event else {
+            return;
+        };
+        let now = Instant::now();
+
+        *self.key_counts.entry(vk).or_default() += 1;
+        self.presses += 1;
+        if types_character(vk) {
+            self.characters += 1;
+            self.recent_characters.push_back(now);
+        }
+        if let Some(last_press) = self.last_press {
+            let bucket = (now.duration_since(last_press).as_millis() / INTERVAL_BUCKET_WIDTH.as_millis()) as usize;
+            self.interval_histogram[bucket.min(INTERVAL_BUCKETS - 1)] += 1;
+        }
+        self.last_press = Some(now);
+    }
+
+    // words per minute over the last minute, or since the stats were started / reset if that is shorter
+    pub fn wpm(&mut self) -> f64 {
+        let now = Instant::now();
+        while self.recent_characters.front().is_some_and(|&typed| now.duration_since(typed) >= WPM_WINDOW) {
+            self.recent_characters.pop_front();
+        }
+        let minutes = now.duration_since(self.started).min(WPM_WINDOW).as_secs_f64() / 60.0;
+        if minutes == 0.0 {
+            return 0.0;
+        }
+        self.recent_characters.len() as f64 / 5.0 / minutes
+    }
+
+    // words per minute since the stats were started / reset
+    pub fn average_wpm(&self) -> f64 {
+        let minutes = self.started.elapsed().as_secs_f64() / 60.0;
+        if minutes == 0.0 {
+            return 0.0;
+        }
+        self.characters as f64 / 5.0 / minutes
+    }
+
+    pub fn presses(&self) -> u64 {
+        self.presses
+    }
+
+    pub fn press_count(&self, vk: impl Into<VirtualKey>) -> u64 {
+        self.key_counts.get(&vk.into()).copied().unwrap_or(0)
+    }
+
+    // press counts of every key pressed so far, most pressed first, e.g. for a heatmap
+    pub fn key_counts(&self) -> Vec<(VirtualKey, u64)> {
+        let mut key_counts: Vec<_> = self.key_counts.iter().map(|(&vk, &count)| (vk, count)).collect();
+        key_counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.code().cmp(&b.0.code())));
+        key_counts
+    }
+
+    // presses by the time since the previous press, bucket i covers i * INTERVAL_BUCKET_WIDTH up to the next
+    pub fn interval_histogram(&self) -> [u64; INTERVAL_BUCKETS] {
+        self.interval_histogram
+    }
+
+    pub fn reset(&mut self) {
+        *self = TypingStats::default();
+    }
+
+    // stats kept up to date from a subscription until the listener is gone, readable at any time
+    #[cfg(feature = "tokio")]
+    pub fn spawn(mut receiver: broadcast::Receiver<KeyEvent>) -> Arc<Mutex<TypingStats>> {
+        let typing_stats = Arc::new(Mutex::new(TypingStats::new()));
+        let updated = typing_stats.clone();
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(key_event) => updated.lock().unwrap().record(&key_event),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break
+                }
+            }
+        });
+        typing_stats
+    }
+}
+
+// keys that type a character on common layouts: digits, letters, space, the numpad digits and the OEM keys
+fn types_character(vk: VirtualKey) -> bool {
+    matches!(vk.code(), 0x20 | 0x30..=0x39 | 0x41..=0x5A | 0x60..=0x69 | 0xBA..=0xC0 | 0xDB..=0xDF | 0xE2)
+}
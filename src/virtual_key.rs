@@ -0,0 +1,212 @@
+/*
+
+Virtual key code wrapper
+
+https://learn.microsoft.com/en-us/windows/win32/inputdev/virtual-key-codes
+
+*/
+
+
+use windows::Win32::UI::Input::KeyboardAndMouse::{MapVirtualKeyW, MAPVK_VK_TO_VSC_EX, VIRTUAL_KEY};
+
+use std::fmt;
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VirtualKey(pub i32);
+
+impl VirtualKey {
+    // mouse buttons
+    pub const LBUTTON: VirtualKey = VirtualKey(0x01);
+    pub const RBUTTON: VirtualKey = VirtualKey(0x02);
+    pub const MBUTTON: VirtualKey = VirtualKey(0x04);
+    pub const XBUTTON1: VirtualKey = VirtualKey(0x05);
+    pub const XBUTTON2: VirtualKey = VirtualKey(0x06);
+
+    // modifiers, the generic codes are down while either the left or right key is down
+    pub const SHIFT: VirtualKey = VirtualKey(0x10);
+    pub const CONTROL: VirtualKey = VirtualKey(0x11);
+    pub const MENU: VirtualKey = VirtualKey(0x12); // alt
+    pub const LSHIFT: VirtualKey = VirtualKey(0xA0);
+    pub const RSHIFT: VirtualKey = VirtualKey(0xA1);
+    pub const LCONTROL: VirtualKey = VirtualKey(0xA2);
+    pub const RCONTROL: VirtualKey = VirtualKey(0xA3);
+    pub const LMENU: VirtualKey = VirtualKey(0xA4);
+    pub const RMENU: VirtualKey = VirtualKey(0xA5);
+    pub const LWIN: VirtualKey = VirtualKey(0x5B);
+    pub const RWIN: VirtualKey = VirtualKey(0x5C);
+    pub const APPS: VirtualKey = VirtualKey(0x5D); // context menu key
+
+    pub const BACK: VirtualKey = VirtualKey(0x08); // backspace
+    pub const TAB: VirtualKey = VirtualKey(0x09);
+    pub const RETURN: VirtualKey = VirtualKey(0x0D); // enter
+    pub const ESCAPE: VirtualKey = VirtualKey(0x1B);
+    pub const SPACE: VirtualKey = VirtualKey(0x20);
+    pub const SNAPSHOT: VirtualKey = VirtualKey(0x2C); // print screen
+    pub const PAUSE: VirtualKey = VirtualKey(0x13);
+    pub const CANCEL: VirtualKey = VirtualKey(0x03); // break, what ctrl + pause reports
+
+    // lock keys
+    pub const CAPITAL: VirtualKey = VirtualKey(0x14); // caps lock
+    pub const NUMLOCK: VirtualKey = VirtualKey(0x90);
+    pub const SCROLL: VirtualKey = VirtualKey(0x91);
+
+    // volume and media playback keys
+    pub const VOLUME_MUTE: VirtualKey = VirtualKey(0xAD);
+    pub const VOLUME_DOWN: VirtualKey = VirtualKey(0xAE);
+    pub const VOLUME_UP: VirtualKey = VirtualKey(0xAF);
+    pub const MEDIA_NEXT_TRACK: VirtualKey = VirtualKey(0xB0);
+    pub const MEDIA_PREV_TRACK: VirtualKey = VirtualKey(0xB1);
+    pub const MEDIA_STOP: VirtualKey = VirtualKey(0xB2);
+    pub const MEDIA_PLAY_PAUSE: VirtualKey = VirtualKey(0xB3);
+
+    // browser keys
+    pub const BROWSER_BACK: VirtualKey = VirtualKey(0xA6);
+    pub const BROWSER_FORWARD: VirtualKey = VirtualKey(0xA7);
+    pub const BROWSER_REFRESH: VirtualKey = VirtualKey(0xA8);
+    pub const BROWSER_STOP: VirtualKey = VirtualKey(0xA9);
+    pub const BROWSER_SEARCH: VirtualKey = VirtualKey(0xAA);
+    pub const BROWSER_FAVORITES: VirtualKey = VirtualKey(0xAB);
+    pub const BROWSER_HOME: VirtualKey = VirtualKey(0xAC);
+
+    // function keys, F1 - F24 are consecutive codes
+    pub const F1: VirtualKey = VirtualKey(0x70);
+    pub const F2: VirtualKey = VirtualKey(0x71);
+    pub const F3: VirtualKey = VirtualKey(0x72);
+    pub const F4: VirtualKey = VirtualKey(0x73);
+    pub const F5: VirtualKey = VirtualKey(0x74);
+    pub const F6: VirtualKey = VirtualKey(0x75);
+    pub const F7: VirtualKey = VirtualKey(0x76);
+    pub const F8: VirtualKey = VirtualKey(0x77);
+    pub const F9: VirtualKey = VirtualKey(0x78);
+    pub const F10: VirtualKey = VirtualKey(0x79);
+    pub const F11: VirtualKey = VirtualKey(0x7A);
+    pub const F12: VirtualKey = VirtualKey(0x7B);
+    pub const F13: VirtualKey = VirtualKey(0x7C);
+    pub const F14: VirtualKey = VirtualKey(0x7D);
+    pub const F15: VirtualKey = VirtualKey(0x7E);
+    pub const F16: VirtualKey = VirtualKey(0x7F);
+    pub const F17: VirtualKey = VirtualKey(0x80);
+    pub const F18: VirtualKey = VirtualKey(0x81);
+    pub const F19: VirtualKey = VirtualKey(0x82);
+    pub const F20: VirtualKey = VirtualKey(0x83);
+    pub const F21: VirtualKey = VirtualKey(0x84);
+    pub const F22: VirtualKey = VirtualKey(0x85);
+    pub const F23: VirtualKey = VirtualKey(0x86);
+    pub const F24: VirtualKey = VirtualKey(0x87);
+
+    pub fn code(&self) -> i32 {
+        self.0
+    }
+
+    pub fn is_mouse_button(&self) -> bool {
+        MOUSE_BUTTONS.contains(self)
+    }
+
+    // the hardware scan code the key maps to, None for keys without one (e.g. mouse buttons)
+    pub fn scan_code(&self) -> Option<ScanCode> {
+        let scan_code = unsafe {
+            MapVirtualKeyW(self.0 as u32, MAPVK_VK_TO_VSC_EX)
+        };
+        if scan_code == 0 {
+            return None;
+        }
+        // extended keys come back with an 0xE0 (or 0xE1 for pause) prefix byte
+        Some(ScanCode {
+            code: (scan_code & 0xFF) as u16,
+            extended: matches!(scan_code >> 8, 0xE0 | 0xE1)
+        })
+    }
+}
+
+
+
+// GetAsyncKeyState reports logical buttons, so left / right follow the "swap mouse buttons" setting
+pub const MOUSE_BUTTONS: [VirtualKey; 5] = [
+    VirtualKey::LBUTTON, VirtualKey::RBUTTON, VirtualKey::MBUTTON, VirtualKey::XBUTTON1, VirtualKey::XBUTTON2
+];
+
+
+
+// not in the default key list, pressing Win alone opens the start menu and Apps the context menu
+pub const WINDOWS_KEYS: [VirtualKey; 3] = [VirtualKey::LWIN, VirtualKey::RWIN, VirtualKey::APPS];
+
+
+
+// not in the default key list. Windows acts on them (changing the volume, ...) whether or not they are watched
+pub const MEDIA_KEYS: [VirtualKey; 7] = [
+    VirtualKey::VOLUME_MUTE, VirtualKey::VOLUME_DOWN, VirtualKey::VOLUME_UP, VirtualKey::MEDIA_NEXT_TRACK,
+    VirtualKey::MEDIA_PREV_TRACK, VirtualKey::MEDIA_STOP, VirtualKey::MEDIA_PLAY_PAUSE
+];
+
+pub const BROWSER_KEYS: [VirtualKey; 7] = [
+    VirtualKey::BROWSER_BACK, VirtualKey::BROWSER_FORWARD, VirtualKey::BROWSER_REFRESH, VirtualKey::BROWSER_STOP,
+    VirtualKey::BROWSER_SEARCH, VirtualKey::BROWSER_FAVORITES, VirtualKey::BROWSER_HOME
+];
+
+
+
+// F13 - F24 are missing from most keyboards, but programmable keyboards and macro pads often send them
+pub const FUNCTION_KEYS: [VirtualKey; 24] = [
+    VirtualKey::F1, VirtualKey::F2, VirtualKey::F3, VirtualKey::F4, VirtualKey::F5, VirtualKey::F6, VirtualKey::F7,
+    VirtualKey::F8, VirtualKey::F9, VirtualKey::F10, VirtualKey::F11, VirtualKey::F12, VirtualKey::F13,
+    VirtualKey::F14, VirtualKey::F15, VirtualKey::F16, VirtualKey::F17, VirtualKey::F18, VirtualKey::F19,
+    VirtualKey::F20, VirtualKey::F21, VirtualKey::F22, VirtualKey::F23, VirtualKey::F24
+];
+
+
+
+// a key or event has no counterpart in another crate's key model, e.g. a mouse button has no keyboard key code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnmappedKey;
+
+impl fmt::Display for UnmappedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the key has no counterpart in the other key model")
+    }
+}
+
+impl std::error::Error for UnmappedKey {}
+
+
+
+// a scan code, extended keys (right ctrl / alt, arrows, insert / delete / home / end / page up / down,
+// numpad enter and divide) share their low byte with a non extended key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ScanCode {
+    pub code: u16,
+    pub extended: bool
+}
+
+// formats as the hex key code, e.g. 0x20 for space
+impl fmt::Display for VirtualKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:02X}", self.0)
+    }
+}
+
+impl From<i32> for VirtualKey {
+    fn from(vk_code: i32) -> Self {
+        VirtualKey(vk_code)
+    }
+}
+
+impl From<VirtualKey> for i32 {
+    fn from(vk: VirtualKey) -> Self {
+        vk.0
+    }
+}
+
+// windows-rs key codes, e.g. VK_SPACE, can be passed wherever the crate takes a key
+impl From<VIRTUAL_KEY> for VirtualKey {
+    fn from(vk: VIRTUAL_KEY) -> Self {
+        VirtualKey(vk.0 as i32)
+    }
+}
+
+impl From<VirtualKey> for VIRTUAL_KEY {
+    fn from(vk: VirtualKey) -> Self {
+        VIRTUAL_KEY(vk.0 as u16)
+    }
+}
@@ -0,0 +1,59 @@
+/*
+
+W3C key codes
+
+Both keyboard-types' Code and winit's KeyCode name physical key positions after the W3C UI Events KeyboardEvent code
+values, i.e. after the key at that place on a US keyboard. This is the one table of virtual keys and their code,
+expanded for either enum. Sided modifiers come before the generic codes so each code maps back to a sided key.
+
+https://w3c.github.io/uievents-code/
+
+*/
+
+
+
+// an array of (virtual key code, $code variant) pairs. The crates disagree on the Win keys' names, which are
+// passed in as $meta_left / $meta_right
+macro_rules! w3c_codes {
+    ($code:ident, $meta_left:ident, $meta_right:ident) => {
+        [
+            (0x30, $code::Digit0), (0x31, $code::Digit1), (0x32, $code::Digit2), (0x33, $code::Digit3),
+            (0x34, $code::Digit4), (0x35, $code::Digit5), (0x36, $code::Digit6), (0x37, $code::Digit7),
+            (0x38, $code::Digit8), (0x39, $code::Digit9), (0x41, $code::KeyA), (0x42, $code::KeyB),
+            (0x43, $code::KeyC), (0x44, $code::KeyD), (0x45, $code::KeyE), (0x46, $code::KeyF),
+            (0x47, $code::KeyG), (0x48, $code::KeyH), (0x49, $code::KeyI), (0x4A, $code::KeyJ),
+            (0x4B, $code::KeyK), (0x4C, $code::KeyL), (0x4D, $code::KeyM), (0x4E, $code::KeyN),
+            (0x4F, $code::KeyO), (0x50, $code::KeyP), (0x51, $code::KeyQ), (0x52, $code::KeyR),
+            (0x53, $code::KeyS), (0x54, $code::KeyT), (0x55, $code::KeyU), (0x56, $code::KeyV),
+            (0x57, $code::KeyW), (0x58, $code::KeyX), (0x59, $code::KeyY), (0x5A, $code::KeyZ),
+            (0x60, $code::Numpad0), (0x61, $code::Numpad1), (0x62, $code::Numpad2), (0x63, $code::Numpad3),
+            (0x64, $code::Numpad4), (0x65, $code::Numpad5), (0x66, $code::Numpad6), (0x67, $code::Numpad7),
+            (0x68, $code::Numpad8), (0x69, $code::Numpad9), (0x70, $code::F1), (0x71, $code::F2),
+            (0x72, $code::F3), (0x73, $code::F4), (0x74, $code::F5), (0x75, $code::F6),
+            (0x76, $code::F7), (0x77, $code::F8), (0x78, $code::F9), (0x79, $code::F10),
+            (0x7A, $code::F11), (0x7B, $code::F12), (0x7C, $code::F13), (0x7D, $code::F14),
+            (0x7E, $code::F15), (0x7F, $code::F16), (0x80, $code::F17), (0x81, $code::F18),
+            (0x82, $code::F19), (0x83, $code::F20), (0x84, $code::F21), (0x85, $code::F22),
+            (0x86, $code::F23), (0x87, $code::F24), (0x08, $code::Backspace), (0x09, $code::Tab),
+            (0x0D, $code::Enter), (0x0D, $code::NumpadEnter), (0xA0, $code::ShiftLeft), (0xA1, $code::ShiftRight),
+            (0xA2, $code::ControlLeft), (0xA3, $code::ControlRight), (0xA4, $code::AltLeft), (0xA5, $code::AltRight),
+            (0x10, $code::ShiftLeft), (0x11, $code::ControlLeft), (0x12, $code::AltLeft), (0x13, $code::Pause),
+            (0x14, $code::CapsLock), (0x1B, $code::Escape), (0x20, $code::Space), (0x21, $code::PageUp),
+            (0x22, $code::PageDown), (0x23, $code::End), (0x24, $code::Home), (0x25, $code::ArrowLeft),
+            (0x26, $code::ArrowUp), (0x27, $code::ArrowRight), (0x28, $code::ArrowDown), (0x2C, $code::PrintScreen),
+            (0x2D, $code::Insert), (0x2E, $code::Delete), (0x5B, $code::$meta_left), (0x5C, $code::$meta_right),
+            (0x5D, $code::ContextMenu), (0x6A, $code::NumpadMultiply), (0x6B, $code::NumpadAdd), (0x6D, $code::NumpadSubtract),
+            (0x6E, $code::NumpadDecimal), (0x6F, $code::NumpadDivide), (0x90, $code::NumLock), (0x91, $code::ScrollLock),
+            (0xA6, $code::BrowserBack), (0xA7, $code::BrowserForward), (0xA8, $code::BrowserRefresh), (0xA9, $code::BrowserStop),
+            (0xAA, $code::BrowserSearch), (0xAB, $code::BrowserFavorites), (0xAC, $code::BrowserHome), (0xAD, $code::AudioVolumeMute),
+            (0xAE, $code::AudioVolumeDown), (0xAF, $code::AudioVolumeUp), (0xB0, $code::MediaTrackNext), (0xB1, $code::MediaTrackPrevious),
+            (0xB2, $code::MediaStop), (0xB3, $code::MediaPlayPause), (0xB4, $code::LaunchMail), (0xB5, $code::MediaSelect),
+            (0xB6, $code::LaunchApp1), (0xB7, $code::LaunchApp2), (0xBA, $code::Semicolon), (0xBB, $code::Equal),
+            (0xBC, $code::Comma), (0xBD, $code::Minus), (0xBE, $code::Period), (0xBF, $code::Slash),
+            (0xC0, $code::Backquote), (0xDB, $code::BracketLeft), (0xDC, $code::Backslash), (0xDD, $code::BracketRight),
+            (0xDE, $code::Quote), (0xE2, $code::IntlBackslash)
+        ]
+    };
+}
+
+pub(crate) use w3c_codes;
@@ -0,0 +1,202 @@
+/*
+
+One-shot helpers that wait for a single key press or hotkey
+
+These spin up a temporary listener and quit it as soon as a matching press arrives.
+If the returned future is dropped early the listener stops on its next poll, since its receiver is gone.
+discover_key watches every key code, for "press the key you want to bind" prompts. Keys already held when it starts
+(e.g. the enter that confirmed the prompt) only count once they were released and pressed again. Reserved and
+unassigned key codes are not watched, nor VK_PROCESSKEY and VK_PACKET, which stand for IME and injected unicode
+input rather than a key. The helpers watch the listener's state next to its events: the listener keeps its sender
+for its whole lifetime, so a polling task that stops or crashes early fails them with ListenerStopped instead.
+
+*/
+
+
+use crate::{KeyEvent, ScanCode, VirtualKey, MOUSE_BUTTONS};
+use crate::builder::KeyListenerBuilder;
+use crate::channel::EventReceiver;
+use crate::error::KeyListenerError;
+use crate::tokio_listener::KeyListener;
+use crate::hotkey::{Hotkey, Modifiers, ParseHotkeyError};
+use crate::listener_handle::ListenerState;
+use crate::modifiers::ModifierMode;
+use crate::poll::is_down;
+
+use tokio::sync::{watch, Mutex};
+use tokio::time::{timeout, Duration};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+
+
+const WAIT_POLLING_WAIT: u64 = 10;
+
+// the key found by discover_key
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredKey {
+    // the left / right code for modifiers
+    pub vk: VirtualKey,
+    pub scan_code: Option<ScanCode>,
+    // see VirtualKey::name
+    pub name: Option<String>
+}
+
+// why wait_for_hotkey did not see its hotkey
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum HotkeyWaitError {
+    Parse(ParseHotkeyError),
+    Listener(KeyListenerError)
+}
+
+impl fmt::Display for HotkeyWaitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HotkeyWaitError::Parse(error) => write!(f, "{}", error),
+            HotkeyWaitError::Listener(error) => write!(f, "{}", error)
+        }
+    }
+}
+
+impl std::error::Error for HotkeyWaitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HotkeyWaitError::Parse(error) => Some(error),
+            HotkeyWaitError::Listener(error) => Some(error)
+        }
+    }
+}
+
+impl From<ParseHotkeyError> for HotkeyWaitError {
+    fn from(error: ParseHotkeyError) -> Self {
+        HotkeyWaitError::Parse(error)
+    }
+}
+
+impl From<KeyListenerError> for HotkeyWaitError {
+    fn from(error: KeyListenerError) -> Self {
+        HotkeyWaitError::Listener(error)
+    }
+}
+
+// a listener spawned for one wait, with its events and its state
+struct Temporary {
+    key_listener: Arc<Mutex<KeyListener>>,
+    receiver: EventReceiver,
+    state: watch::Receiver<ListenerState>
+}
+
+impl Temporary {
+    async fn spawn(builder: KeyListenerBuilder) -> Result<Self, KeyListenerError> {
+        let (key_listener, receiver) = builder.polling_wait(WAIT_POLLING_WAIT).spawn_with_receiver()?;
+        let state = key_listener.lock().await.watch_state();
+        Ok(Temporary { key_listener, receiver, state })
+    }
+
+    // events that were already polled go first, then a stopped or crashed polling task ends the wait
+    async fn next_event(&mut self) -> Result<KeyEvent, KeyListenerError> {
+        tokio::select! {
+            biased;
+            received = self.receiver.recv() => {
+                received.map(|sequenced| sequenced.key_event).ok_or(KeyListenerError::ListenerStopped)
+            }
+            _ = self.state.wait_for(|state| matches!(state, ListenerState::Stopped | ListenerState::Crashed)) => {
+                Err(KeyListenerError::ListenerStopped)
+            }
+        }
+    }
+
+    async fn quit(self) {
+        self.key_listener.lock().await.quit();
+    }
+}
+
+
+
+// fails right away for an invalid key code
+pub async fn wait_for_key(vk: impl Into<VirtualKey>) -> Result<(), KeyListenerError> {
+    wait_for_any(&[vk.into()]).await.map(|_| ())
+}
+
+pub async fn wait_for_any(vks: &[VirtualKey]) -> Result<VirtualKey, KeyListenerError> {
+    let vk_codes: Vec<i32> = vks.iter().map(|vk| vk.code()).collect();
+    let mut listener = Temporary::spawn(KeyListenerBuilder::new().vk_codes(vk_codes)).await?;
+
+    let pressed = loop {
+        if let KeyEvent::Press(vk) = listener.next_event().await? {
+            break vk;
+        }
+    };
+
+    listener.quit().await;
+    Ok(pressed)
+}
+
+pub async fn wait_for_hotkey(hotkey: &str) -> Result<(), HotkeyWaitError> {
+    let hotkey: Hotkey = hotkey.parse()?;
+    let mut listener = Temporary::spawn(KeyListenerBuilder::new().vk_codes(hotkey.vk_codes())).await?;
+
+    let mut pressed = HashSet::new();
+    loop {
+        match listener.next_event().await? {
+            KeyEvent::Press(vk) => {
+                pressed.insert(vk);
+                if hotkey.matches(vk, Modifiers::from_pressed(&pressed)) {
+                    break;
+                }
+            }
+            KeyEvent::Release(vk) => {
+                pressed.remove(&vk);
+            }
+            _ => {}
+        }
+    }
+
+    listener.quit().await;
+    Ok(())
+}
+
+// the next key pressed, whichever it is. Mouse buttons are left out, a click on the prompt would count otherwise
+pub async fn discover_key() -> Result<DiscoveredKey, KeyListenerError> {
+    let vk_codes: Vec<i32> = (0x01..=0xFE).filter(|vk_code| is_discoverable(VirtualKey(*vk_code))).collect();
+    let mut held: HashSet<VirtualKey> = vk_codes.iter().filter(|vk_code| is_down(**vk_code)).map(|vk_code| VirtualKey(*vk_code)).collect();
+    // a shift press is reported once, as left or right shift, instead of once more as shift
+    let builder = KeyListenerBuilder::new().vk_codes(vk_codes).modifier_mode(ModifierMode::Deduplicated);
+    let mut listener = Temporary::spawn(builder).await?;
+
+    let pressed = loop {
+        match listener.next_event().await? {
+            KeyEvent::Press(vk) if !held.contains(&vk) => break vk,
+            KeyEvent::Release(vk) => {
+                held.remove(&vk);
+            }
+            _ => {}
+        }
+    };
+
+    listener.quit().await;
+    Ok(DiscoveredKey { vk: pressed, scan_code: pressed.scan_code(), name: pressed.name() })
+}
+
+// resolves to Ok(false) if the hotkey was not pressed before the timeout
+pub async fn wait_for_hotkey_timeout(hotkey: &str, duration: Duration) -> Result<bool, HotkeyWaitError> {
+    // parse up front so an invalid hotkey is reported instead of timing out
+    hotkey.parse::<Hotkey>()?;
+    match timeout(duration, wait_for_hotkey(hotkey)).await {
+        Ok(result) => result.map(|_| true),
+        Err(_) => Ok(false)
+    }
+}
+
+
+
+fn is_discoverable(vk: VirtualKey) -> bool {
+    let reserved = matches!(
+        vk.code(),
+        0x07 | 0x0A..=0x0B | 0x0E..=0x0F | 0x3A..=0x40 | 0x5E | 0x88..=0x8F | 0x97..=0x9F | 0xB8..=0xB9 | 0xC1..=0xDA
+            | 0xE0 | 0xE8
+    );
+    !reserved && !MOUSE_BUTTONS.contains(&vk) && vk != VirtualKey(0xE5) && vk != VirtualKey(0xE7)
+}
@@ -0,0 +1,62 @@
+/*
+
+Listener warnings
+
+Conditions that do not stop the listener but mean events are late or lost. They are sent to a task of their own
+so a slow warning handler never holds up polling. A panicking handler only loses the warning it panicked on.
+
+*/
+
+
+use tokio::sync::mpsc;
+use tokio::time::Duration;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ListenerWarning {
+    // nothing receives events anymore, e.g. the callback task ended, so the listener stops
+    ReceiverClosed,
+    // events lost to a bounded channel's overflow policy since the previous warning
+    EventsDropped(u64),
+    // a round of polling (and sending its events) took longer than the polling interval
+    PollOverrun { took: Duration, interval: Duration },
+    // an elevated window has the focus, and this process is not elevated: UIPI hides its keys, which read as up.
+    // Sent once per listener, see capture_limitations
+    ElevatedWindowFocused,
+    // the polling task panicked and a supervisor started it again, restarts counts every restart so far.
+    // Events of the rounds in between are lost
+    Restarted { restarts: u32 }
+}
+
+
+
+pub(crate) type WarningHandler = Box<dyn FnMut(ListenerWarning) + Send>;
+
+#[derive(Clone, Default)]
+pub(crate) struct WarningSender(Option<mpsc::UnboundedSender<ListenerWarning>>);
+
+impl WarningSender {
+    // calls handler for each warning from a task of its own, ends once every sender is dropped
+    pub(crate) fn spawn(mut handler: WarningHandler) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(warning) = receiver.recv().await {
+                // keeps the task alive, later warnings would otherwise be dropped unheard
+                let _ = catch_unwind(AssertUnwindSafe(|| handler(warning)));
+            }
+        });
+        WarningSender(Some(sender))
+    }
+
+    pub(crate) fn warn(&self, warning: ListenerWarning) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(?warning, "listener warning");
+
+        if let Some(sender) = &self.0 {
+            let _ = sender.send(warning);
+        }
+    }
+}
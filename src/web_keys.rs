@@ -0,0 +1,142 @@
+/*
+
+Conversions to and from the keyboard-types crate
+
+keyboard-types models keys the way web browsers do: a Code is a physical key position named after its place on a
+US keyboard, and a Key is the logical value it produces, either a named key or typed text. A virtual key sits in
+between, so only a Code, or a named Key, converts back to one exactly. Character keys convert to the text they type
+with the foreground window's current keyboard layout, see translate_key.
+
+https://w3c.github.io/uievents-code/
+
+*/
+
+
+use crate::{KeyEvent, UnmappedKey, VirtualKey};
+use crate::text::translate_key;
+use crate::w3c_codes::w3c_codes;
+
+use keyboard_types::{Code, Key, KeyState, KeyboardEvent, Location, NamedKey};
+
+
+
+const CODES: [(i32, Code); 138] = w3c_codes!(Code, MetaLeft, MetaRight);
+
+const NAMED_KEYS: [(i32, NamedKey); 75] = [
+    (0x08, NamedKey::Backspace), (0x09, NamedKey::Tab), (0x0C, NamedKey::Clear), (0x0D, NamedKey::Enter),
+    (0x10, NamedKey::Shift), (0x11, NamedKey::Control), (0x12, NamedKey::Alt), (0x13, NamedKey::Pause),
+    (0x14, NamedKey::CapsLock), (0x1B, NamedKey::Escape), (0x21, NamedKey::PageUp), (0x22, NamedKey::PageDown),
+    (0x23, NamedKey::End), (0x24, NamedKey::Home), (0x25, NamedKey::ArrowLeft), (0x26, NamedKey::ArrowUp),
+    (0x27, NamedKey::ArrowRight), (0x28, NamedKey::ArrowDown), (0x2C, NamedKey::PrintScreen), (0x2D, NamedKey::Insert),
+    (0x2E, NamedKey::Delete), (0x2F, NamedKey::Help), (0x5B, NamedKey::Meta), (0x5C, NamedKey::Meta),
+    (0x5D, NamedKey::ContextMenu), (0x70, NamedKey::F1), (0x71, NamedKey::F2), (0x72, NamedKey::F3),
+    (0x73, NamedKey::F4), (0x74, NamedKey::F5), (0x75, NamedKey::F6), (0x76, NamedKey::F7),
+    (0x77, NamedKey::F8), (0x78, NamedKey::F9), (0x79, NamedKey::F10), (0x7A, NamedKey::F11),
+    (0x7B, NamedKey::F12), (0x7C, NamedKey::F13), (0x7D, NamedKey::F14), (0x7E, NamedKey::F15),
+    (0x7F, NamedKey::F16), (0x80, NamedKey::F17), (0x81, NamedKey::F18), (0x82, NamedKey::F19),
+    (0x83, NamedKey::F20), (0x84, NamedKey::F21), (0x85, NamedKey::F22), (0x86, NamedKey::F23),
+    (0x87, NamedKey::F24), (0x90, NamedKey::NumLock), (0x91, NamedKey::ScrollLock), (0xA0, NamedKey::Shift),
+    (0xA1, NamedKey::Shift), (0xA2, NamedKey::Control), (0xA3, NamedKey::Control), (0xA4, NamedKey::Alt),
+    (0xA5, NamedKey::Alt), (0xA6, NamedKey::BrowserBack), (0xA7, NamedKey::BrowserForward), (0xA8, NamedKey::BrowserRefresh),
+    (0xA9, NamedKey::BrowserStop), (0xAA, NamedKey::BrowserSearch), (0xAB, NamedKey::BrowserFavorites), (0xAC, NamedKey::BrowserHome),
+    (0xAD, NamedKey::AudioVolumeMute), (0xAE, NamedKey::AudioVolumeDown), (0xAF, NamedKey::AudioVolumeUp), (0xB0, NamedKey::MediaTrackNext),
+    (0xB1, NamedKey::MediaTrackPrevious), (0xB2, NamedKey::MediaStop), (0xB3, NamedKey::MediaPlayPause), (0xB4, NamedKey::LaunchMail),
+    (0xB5, NamedKey::LaunchMediaPlayer), (0xB6, NamedKey::LaunchApplication1), (0xB7, NamedKey::LaunchApplication2)
+];
+
+
+
+impl TryFrom<VirtualKey> for Code {
+    type Error = UnmappedKey;
+
+    fn try_from(vk: VirtualKey) -> Result<Self, Self::Error> {
+        CODES.iter().find(|(vk_code, _)| *vk_code == vk.0).map(|(_, code)| *code).ok_or(UnmappedKey)
+    }
+}
+
+impl TryFrom<Code> for VirtualKey {
+    type Error = UnmappedKey;
+
+    fn try_from(code: Code) -> Result<Self, Self::Error> {
+        CODES.iter().find(|(_, other)| *other == code).map(|(vk_code, _)| VirtualKey(*vk_code)).ok_or(UnmappedKey)
+    }
+}
+
+// named keys map directly, other keys to the text they type right now, Unidentified if they type nothing
+impl From<VirtualKey> for Key {
+    fn from(vk: VirtualKey) -> Self {
+        if let Some((_, named_key)) = NAMED_KEYS.iter().find(|(vk_code, _)| *vk_code == vk.0) {
+            return Key::Named(*named_key);
+        }
+        match translate_key(vk) {
+            Some(text) if !text.is_empty() => Key::Character(text),
+            _ => Key::Named(NamedKey::Unidentified)
+        }
+    }
+}
+
+// named keys, letters, digits and space, other characters depend on the layout and are not mapped
+impl TryFrom<&Key> for VirtualKey {
+    type Error = UnmappedKey;
+
+    fn try_from(key: &Key) -> Result<Self, Self::Error> {
+        match key {
+            Key::Named(named_key) => NAMED_KEYS.iter()
+                .find(|(_, other)| other == named_key)
+                .map(|(vk_code, _)| VirtualKey(*vk_code))
+                .ok_or(UnmappedKey),
+            Key::Character(text) => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii_alphanumeric() => Ok(VirtualKey(c.to_ascii_uppercase() as i32)),
+                    (Some(' '), None) => Ok(VirtualKey::SPACE),
+                    _ => Err(UnmappedKey)
+                }
+            }
+        }
+    }
+}
+
+
+
+fn location(vk: VirtualKey) -> Location {
+    match vk.0 {
+        0xA0 | 0xA2 | 0xA4 | 0x5B => Location::Left,
+        0xA1 | 0xA3 | 0xA5 | 0x5C => Location::Right,
+        0x60..=0x6F => Location::Numpad,
+        _ => Location::Standard
+    }
+}
+
+// presses and releases of keys with a Code, the modifiers are left empty since a KeyEvent does not carry them
+impl TryFrom<KeyEvent> for KeyboardEvent {
+    type Error = UnmappedKey;
+
+    fn try_from(key_event: KeyEvent) -> Result<Self, Self::Error> {
+        let (vk, state) = match key_event {
+            KeyEvent::Press(vk) => (vk, KeyState::Down),
+            KeyEvent::Release(vk) => (vk, KeyState::Up),
+            _ => return Err(UnmappedKey)
+        };
+        Ok(KeyboardEvent {
+            state,
+            key: Key::from(vk),
+            code: Code::try_from(vk)?,
+            location: location(vk),
+            ..Default::default()
+        })
+    }
+}
+
+// the virtual key is taken from the Code, or from the Key for events without a known Code
+impl TryFrom<&KeyboardEvent> for KeyEvent {
+    type Error = UnmappedKey;
+
+    fn try_from(keyboard_event: &KeyboardEvent) -> Result<Self, Self::Error> {
+        let vk = VirtualKey::try_from(keyboard_event.code).or_else(|_| VirtualKey::try_from(&keyboard_event.key))?;
+        Ok(match keyboard_event.state {
+            KeyState::Down => KeyEvent::Press(vk),
+            KeyState::Up => KeyEvent::Release(vk)
+        })
+    }
+}
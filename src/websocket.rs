@@ -0,0 +1,127 @@
+/*
+
+WebSocket server, enabled with the "websocket" feature
+
+Broadcasts a listener's events to every connected WebSocket client, e.g. a browser based key overlay for
+streams. Each event is a text message holding the JSON object the session recorder writes, or a binary message
+holding a record of the binary session format.
+Browsers let any page open a WebSocket to 127.0.0.1, so handshakes carrying an Origin header (which browsers always
+send) are refused unless the origin was allowed. Clients outside a browser send none and are accepted. Messages from
+clients are read, and dropped, so pings are answered and a close handshake ends the connection.
+
+*/
+
+
+use crate::KeyEvent;
+use crate::recorder::{RedactedField, Redaction, SessionFormat, encode_event};
+use crate::tokio_listener::KeyListener;
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use std::collections::HashSet;
+use std::io;
+use std::sync::Arc;
+
+
+
+pub struct WebSocketServerBuilder {
+    format: SessionFormat,
+    redaction: Redaction,
+    allowed_origins: HashSet<String>
+}
+
+impl Default for WebSocketServerBuilder {
+    fn default() -> Self {
+        WebSocketServerBuilder { format: SessionFormat::JsonLines, redaction: Redaction::default(), allowed_origins: HashSet::new() }
+    }
+}
+
+impl WebSocketServerBuilder {
+    pub fn new() -> Self {
+        WebSocketServerBuilder::default()
+    }
+
+    pub fn format(mut self, format: SessionFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn redact(mut self, field: RedactedField) -> Self {
+        self.redaction.fields.insert(field);
+        self
+    }
+
+    // lets pages from origin connect, e.g. "http://localhost:8080", or "null" for pages opened from a file
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        self.allowed_origins.insert(origin.to_string());
+        self
+    }
+
+    // binds to address, e.g. "127.0.0.1:9001". The returned task accepts clients until it is aborted
+    pub async fn serve(self, address: impl ToSocketAddrs, key_listener: &KeyListener) -> io::Result<JoinHandle<io::Result<()>>> {
+        let tcp_listener = TcpListener::bind(address).await?;
+        let events = key_listener.broadcast_sender();
+        let settings = Arc::new(self);
+
+        Ok(tokio::spawn(async move {
+            loop {
+                let (stream, _) = tcp_listener.accept().await?;
+                tokio::spawn(serve_client(stream, events.subscribe(), settings.clone()));
+            }
+        }))
+    }
+}
+
+// refuses handshakes from browser pages of origins not allowed
+struct OriginCheck<'a>(&'a HashSet<String>);
+
+impl Callback for OriginCheck<'_> {
+    fn on_request(self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        match request.headers().get("Origin") {
+            Some(origin) if !origin.to_str().is_ok_and(|origin| self.0.contains(origin)) => {
+                let mut refusal = ErrorResponse::new(Some("origin not allowed".to_string()));
+                *refusal.status_mut() = StatusCode::FORBIDDEN;
+                Err(refusal)
+            }
+            _ => Ok(response)
+        }
+    }
+}
+
+// until the client disconnects or the listener is gone
+async fn serve_client(stream: TcpStream, mut receiver: broadcast::Receiver<KeyEvent>, settings: Arc<WebSocketServerBuilder>) {
+    let Ok(mut websocket) = tokio_tungstenite::accept_hdr_async(stream, OriginCheck(&settings.allowed_origins)).await else {
+        return;
+    };
+    let mut frame = Vec::new();
+    loop {
+        let key_event = tokio::select! {
+            received = receiver.recv() => match received {
+                Ok(key_event) => key_event,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break
+            },
+            // reading answers pings and close frames, the messages themselves mean nothing here
+            message = websocket.next() => match message {
+                Some(Ok(_)) => continue,
+                Some(Err(_)) | None => return
+            }
+        };
+        frame.clear();
+        encode_event(settings.format, &key_event, &settings.redaction, &mut frame);
+        let message = match settings.format {
+            // without the line break that separates JSON lines, a message already holds one event
+            SessionFormat::JsonLines => Message::text(String::from_utf8_lossy(frame.trim_ascii_end()).into_owned()),
+            SessionFormat::Binary => Message::binary(frame.clone())
+        };
+        if websocket.send(message).await.is_err() {
+            return;
+        }
+    }
+    let _ = websocket.close(None).await;
+}
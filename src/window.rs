@@ -0,0 +1,214 @@
+/*
+
+Foreground window information
+
+Window titles are read on every call since they change freely, process names are looked up from the owning
+process id. Reading another process' name can fail (e.g. for elevated processes when not elevated ourselves).
+
+https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getforegroundwindow
+
+*/
+
+
+#[cfg(feature = "tokio")]
+use crate::KeyEvent;
+
+use windows::Win32::Foundation::{CloseHandle, HWND};
+use windows::Win32::System::Threading::{
+    OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetForegroundWindow, GetWindowTextLengthW, GetWindowTextW, GetWindowThreadProcessId, IsWindow
+};
+use windows::core::PWSTR;
+
+
+
+// a window handle (HWND) stored as an integer so it can be sent between threads
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WindowHandle(pub isize);
+
+impl WindowHandle {
+    // None while no window has focus, e.g. during a window switch
+    pub fn foreground() -> Option<Self> {
+        let hwnd = unsafe { GetForegroundWindow() };
+        (!hwnd.is_invalid()).then(|| WindowHandle::from(hwnd))
+    }
+
+    pub fn hwnd(&self) -> HWND {
+        HWND(self.0 as *mut _)
+    }
+
+    // false once the window has been destroyed
+    pub fn is_window(&self) -> bool {
+        unsafe { IsWindow(Some(self.hwnd())).as_bool() }
+    }
+
+    pub fn title(&self) -> String {
+        unsafe {
+            let length = GetWindowTextLengthW(self.hwnd());
+            let mut buffer = vec![0u16; length as usize + 1];
+            let copied = GetWindowTextW(self.hwnd(), &mut buffer);
+            String::from_utf16_lossy(&buffer[..copied.max(0) as usize])
+        }
+    }
+
+    pub fn process_id(&self) -> u32 {
+        let mut process_id = 0;
+        unsafe {
+            GetWindowThreadProcessId(self.hwnd(), Some(&mut process_id));
+        }
+        process_id
+    }
+
+    // executable file name of the owning process, e.g. "notepad.exe"
+    pub fn process_name(&self) -> Option<String> {
+        process_name(self.process_id())
+    }
+}
+
+impl From<HWND> for WindowHandle {
+    fn from(hwnd: HWND) -> Self {
+        WindowHandle(hwnd.0 as isize)
+    }
+}
+
+pub(crate) fn process_name(process_id: u32) -> Option<String> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, process_id).ok()?;
+        let mut buffer = [0u16; 1024];
+        let mut length = buffer.len() as u32;
+        let result = QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(buffer.as_mut_ptr()), &mut length);
+        let _ = CloseHandle(process);
+        result.ok()?;
+
+        let path = String::from_utf16_lossy(&buffer[..length as usize]);
+        path.rsplit('\\').next().map(str::to_owned)
+    }
+}
+
+
+
+// the foreground window at the time of an event
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowContext {
+    pub window: WindowHandle,
+    pub title: String,
+    pub process_id: u32,
+    pub process_name: Option<String>
+}
+
+impl WindowContext {
+    pub fn foreground() -> Option<Self> {
+        WindowHandle::foreground().map(WindowContext::of)
+    }
+
+    pub fn of(window: WindowHandle) -> Self {
+        let process_id = window.process_id();
+        WindowContext { window, title: window.title(), process_id, process_name: process_name(process_id) }
+    }
+}
+
+// a key event along with the window that received it
+#[cfg(feature = "tokio")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowKeyEvent {
+    pub key_event: KeyEvent,
+    // None when no window had focus
+    pub window: Option<WindowContext>
+}
+
+
+
+// which foreground window events are emitted for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WindowFilter {
+    Handle(WindowHandle),
+    // the title contains this text, ignoring case
+    Title(String),
+    // the executable file name, ignoring case, e.g. "notepad.exe"
+    Process(String)
+}
+
+impl WindowFilter {
+    pub fn matches(&self, window: WindowHandle) -> bool {
+        match self {
+            WindowFilter::Handle(handle) => *handle == window,
+            WindowFilter::Title(text) => window.title().to_lowercase().contains(&text.to_lowercase()),
+            WindowFilter::Process(name) => window.process_name().is_some_and(|process| process.eq_ignore_ascii_case(name))
+        }
+    }
+}
+
+
+
+// reads the foreground window context, the process is only looked up again when the window changes
+#[cfg(feature = "tokio")]
+#[derive(Default)]
+pub(crate) struct ContextWatcher {
+    last: Option<WindowContext>
+}
+
+#[cfg(feature = "tokio")]
+impl ContextWatcher {
+    pub(crate) fn current(&mut self) -> Option<WindowContext> {
+        let window = WindowHandle::foreground()?;
+        let context = match self.last.take() {
+            Some(last) if last.window == window => WindowContext { title: window.title(), ..last },
+            _ => WindowContext::of(window)
+        };
+        self.last = Some(context.clone());
+        Some(context)
+    }
+}
+
+
+
+// last seen foreground window, owned by the polling loop
+#[cfg(feature = "tokio")]
+#[derive(Default)]
+pub(crate) struct FocusWatcher {
+    window: Option<WindowHandle>
+}
+
+#[cfg(feature = "tokio")]
+impl FocusWatcher {
+    // the first poll only records the current window, later polls return a FocusChanged when another window has
+    // focus. Moments without a foreground window (while switching) are skipped
+    pub(crate) fn poll(&mut self) -> Option<KeyEvent> {
+        let current = WindowHandle::foreground()?;
+        let changed = self.window.is_some_and(|window| window != current);
+        self.window = Some(current);
+        changed.then(|| KeyEvent::FocusChanged { window: current, process_id: current.process_id() })
+    }
+}
+
+
+
+// checks the filter against the foreground window, process names are only looked up when the window changes
+#[cfg(feature = "tokio")]
+pub(crate) struct ForegroundFilter {
+    filter: WindowFilter,
+    last: Option<(WindowHandle, bool)>
+}
+
+#[cfg(feature = "tokio")]
+impl ForegroundFilter {
+    pub(crate) fn new(filter: WindowFilter) -> Self {
+        ForegroundFilter { filter, last: None }
+    }
+
+    pub(crate) fn is_foreground(&mut self) -> bool {
+        let Some(window) = WindowHandle::foreground() else {
+            return false;
+        };
+        match (&self.filter, self.last) {
+            (WindowFilter::Process(_), Some((last, matches))) if last == window => matches,
+            _ => {
+                let matches = self.filter.matches(window);
+                self.last = Some((window, matches));
+                matches
+            }
+        }
+    }
+}
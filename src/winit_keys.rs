@@ -0,0 +1,76 @@
+/*
+
+Conversions to and from winit's physical keys
+
+winit names physical key positions after the key at that place on a US keyboard, like keyboard-types does, so both
+share one table. Keys without a KeyCode become PhysicalKey::Unidentified with their Windows scan code, the way winit
+itself reports them (extended keys with an 0xE0 prefix), and convert back from it.
+
+*/
+
+
+use crate::{UnmappedKey, VirtualKey};
+use crate::w3c_codes::w3c_codes;
+
+use windows::Win32::UI::Input::KeyboardAndMouse::{MapVirtualKeyW, MAPVK_VSC_TO_VK_EX};
+use winit::keyboard::{KeyCode, NativeKeyCode, PhysicalKey};
+
+
+
+const KEY_CODES: [(i32, KeyCode); 138] = w3c_codes!(KeyCode, SuperLeft, SuperRight);
+
+
+
+impl TryFrom<VirtualKey> for KeyCode {
+    type Error = UnmappedKey;
+
+    fn try_from(vk: VirtualKey) -> Result<Self, Self::Error> {
+        KEY_CODES.iter().find(|(vk_code, _)| *vk_code == vk.0).map(|(_, key_code)| *key_code).ok_or(UnmappedKey)
+    }
+}
+
+impl TryFrom<KeyCode> for VirtualKey {
+    type Error = UnmappedKey;
+
+    fn try_from(key_code: KeyCode) -> Result<Self, Self::Error> {
+        KEY_CODES.iter()
+            .find(|(_, other)| *other == key_code)
+            .map(|(vk_code, _)| VirtualKey(*vk_code))
+            .ok_or(UnmappedKey)
+    }
+}
+
+impl From<VirtualKey> for PhysicalKey {
+    fn from(vk: VirtualKey) -> Self {
+        if let Ok(key_code) = KeyCode::try_from(vk) {
+            return PhysicalKey::Code(key_code);
+        }
+        match vk.scan_code() {
+            Some(scan_code) => {
+                let prefix = if scan_code.extended { 0xE000 } else { 0 };
+                PhysicalKey::Unidentified(NativeKeyCode::Windows(prefix | scan_code.code))
+            }
+            None => PhysicalKey::Unidentified(NativeKeyCode::Unidentified)
+        }
+    }
+}
+
+impl TryFrom<PhysicalKey> for VirtualKey {
+    type Error = UnmappedKey;
+
+    fn try_from(physical_key: PhysicalKey) -> Result<Self, Self::Error> {
+        match physical_key {
+            PhysicalKey::Code(key_code) => VirtualKey::try_from(key_code),
+            PhysicalKey::Unidentified(NativeKeyCode::Windows(scan_code)) => {
+                let vk_code = unsafe {
+                    MapVirtualKeyW(scan_code as u32, MAPVK_VSC_TO_VK_EX)
+                };
+                if vk_code == 0 {
+                    return Err(UnmappedKey);
+                }
+                Ok(VirtualKey(vk_code as i32))
+            }
+            PhysicalKey::Unidentified(_) => Err(UnmappedKey)
+        }
+    }
+}
@@ -0,0 +1,92 @@
+/*
+
+XInput gamepad listener for the tokio backend
+
+Runs alongside (or instead of) a key listener, sending gamepad events through the same channel and callbacks.
+Button presses and releases go to the callbacks, subscribers receive every event including axis changes and
+controllers connecting or disconnecting.
+
+*/
+
+
+use crate::KeyEvent;
+use crate::callbacks::Callbacks;
+use crate::error::{KeyListenerError, validate_polling_interval};
+use crate::channel::{ChannelMode, EventSender, event_channel};
+use crate::gamepad::GamepadStates;
+use crate::sequence::SequencedKeyEvent;
+use crate::tokio_listener::spawn_receiver;
+
+use tokio::time::{sleep, Duration};
+use tokio::sync::{broadcast, Mutex};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+
+
+// events a subscriber can fall behind by before it starts missing them (RecvError::Lagged)
+const SUBSCRIBER_CAPACITY: usize = 256;
+
+pub struct XInputListener {
+    broadcast_sender: broadcast::Sender<KeyEvent>,
+    is_watching: Arc<AtomicBool>
+}
+
+impl XInputListener {
+    pub fn quit(&mut self) {
+        self.is_watching.store(false, Ordering::Relaxed);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<KeyEvent> {
+        self.broadcast_sender.subscribe()
+    }
+}
+
+
+
+async fn listen(
+    sender: EventSender,
+    broadcast_sender: broadcast::Sender<KeyEvent>,
+    is_watching: Arc<AtomicBool>,
+    polling_wait: u64
+) {
+    let sleep_time = Duration::from_millis(polling_wait);
+    let mut gamepad_states = GamepadStates::default();
+    let mut key_events = Vec::new();
+    // numbered on their own, gamepad listeners have no sequenced subscription
+    let mut next_seq = 0;
+
+    while is_watching.load(Ordering::Relaxed) && !sender.is_closed() {
+        let polled_at = std::time::Instant::now();
+        gamepad_states.poll(|key_event| key_events.push(key_event));
+        for key_event in key_events.drain(..) {
+            sender.send(SequencedKeyEvent { seq: next_seq, key_event, polled_at }).await;
+            next_seq += 1;
+            let _ = broadcast_sender.send(key_event); // only fails when there are no subscribers
+        }
+        sleep(sleep_time).await;
+    }
+}
+
+
+
+// callbacks receive KeyEvent::GamepadPress and KeyEvent::GamepadRelease, polling_wait is in milliseconds
+pub fn init_xinput_listener<D, U>(
+    button_down_callback: D, button_up_callback: U,
+    polling_wait: u64
+) -> Result<Arc<Mutex<XInputListener>>, KeyListenerError>
+where
+    D: FnMut(KeyEvent) + Send + 'static,
+    U: FnMut(KeyEvent) + Send + 'static
+{
+    validate_polling_interval(Duration::from_millis(polling_wait))?;
+
+    let (sender, receiver) = event_channel(ChannelMode::Unbounded, Arc::new(AtomicU64::new(0)));
+    let broadcast_sender = broadcast::channel(SUBSCRIBER_CAPACITY).0;
+    let is_watching = Arc::new(AtomicBool::new(true));
+
+    tokio::spawn(listen(sender, broadcast_sender.clone(), is_watching.clone(), polling_wait));
+    spawn_receiver(receiver, Callbacks::new(button_down_callback, button_up_callback));
+
+    Ok(Arc::new(Mutex::new(XInputListener { broadcast_sender, is_watching })))
+}